@@ -20,13 +20,7 @@ pub extern "C" fn datom_db(conn: &'_ Connection) -> Option<Box<Database<'_>>> {
         let db = conn.c.db()?;
         Ok(Box::new(db.into()))
     })();
-    match res {
-        Ok(d) => Some(d),
-        Err(_) => {
-            // update_last_connection_error(e.into())
-            None
-        }
-    }
+    res.ok()
 }
 
 #[no_mangle]
@@ -35,41 +29,23 @@ pub extern "C" fn datom_as_of(conn: &'_ Connection, t: u64) -> Option<Box<Databa
         let db = conn.c.as_of(t)?;
         Ok(Box::new(db.into()))
     })();
-    match res {
-        Ok(d) => Some(d),
-        Err(_) => {
-            // update_last_connection_error(e.into())
-            None
-        }
-    }
+    res.ok()
 }
 
 #[no_mangle]
 pub extern "C" fn datom_latest_t(conn: &Connection) -> u64 {
     let res: Result<u64, ConnectionError> = conn.c.latest_t();
-    match res {
-        Ok(t) => t,
-        Err(_) => {
-            // update_last_connection_error(e.into())
-            u64::MAX
-        }
-    }
+    res.unwrap_or(u64::MAX)
 }
 
 #[no_mangle]
 pub extern "C" fn datom_transact(
     conn: &Connection,
     tx: Box<Transaction>,
-) -> Option<Box<TransactionResult>> {
+) -> Option<Box<TransactionResult<'_>>> {
     let res: Result<Box<TransactionResult>, TransactionError> = (|| {
         let r = conn.c.transact_tx(tx.t)?;
         Ok(Box::new(r.into()))
     })();
-    match res {
-        Ok(r) => Some(r),
-        Err(_) => {
-            // update_last_transaction_error(e.into())
-            None
-        }
-    }
+    res.ok()
 }
@@ -15,13 +15,7 @@ pub extern "C" fn datom_datoms<'s>(
         let iter = database.d.datoms(index.into())?;
         Ok(Box::new(iter.into()))
     })();
-    match res {
-        Ok(d) => Some(d),
-        Err(_) => {
-            // update_last_query_error(e.into())
-            None
-        }
-    }
+    res.ok()
 }
 
 #[no_mangle]
@@ -33,11 +27,5 @@ pub extern "C" fn datom_entity<'s>(
         let e = database.d.entity(entity.i.into())?;
         Ok(Box::new(e.into()))
     })();
-    match res {
-        Ok(d) => Some(d),
-        Err(_) => {
-            // update_last_query_error(e.into())
-            None
-        }
-    }
+    res.ok()
 }
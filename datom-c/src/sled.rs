@@ -12,11 +12,5 @@ pub extern "C" fn datom_sled_connect(path: Box<Str>) -> Option<Box<Storage>> {
         let storage = SledStorage::connect(&path.s)?;
         Ok(Box::new(storage.into()))
     })();
-    match res {
-        Ok(s) => Some(s),
-        Err(_) => {
-            // update_last_storage_error(e.into())
-            None
-        }
-    }
+    res.ok()
 }
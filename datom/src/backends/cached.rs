@@ -0,0 +1,274 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Range,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+};
+
+use crate::{
+    storage::{DurableStorage, Item, ItemIterator, Storage},
+    StorageError, ID,
+};
+
+/// The bounds of a cached range
+type RangeKey = (Item, Item);
+
+/// The items within a cached range
+struct Entry {
+    items: Arc<Vec<Item>>,
+    bytes: usize,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Cache {
+    entries: HashMap<RangeKey, Entry>,
+    /// Cached ranges by when they were last used, oldest first
+    recency: BTreeMap<u64, RangeKey>,
+    bytes: usize,
+    tick: u64,
+    /// Incremented on every insert, so a read which overlapped one
+    /// isn't cached
+    generation: u64,
+}
+
+impl Cache {
+    fn get(&mut self, key: &RangeKey) -> Option<Arc<Vec<Item>>> {
+        self.tick += 1;
+        let entry = self.entries.get_mut(key)?;
+        self.recency.remove(&entry.last_used);
+        entry.last_used = self.tick;
+        self.recency.insert(self.tick, key.to_owned());
+        Some(entry.items.clone())
+    }
+
+    fn remove(&mut self, key: &RangeKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.recency.remove(&entry.last_used);
+            self.bytes -= entry.bytes;
+        }
+    }
+
+    /// Cache a range's items, evicting the least recently used ranges
+    /// until they fit in the budget
+    fn store(&mut self, key: RangeKey, items: Vec<Item>, bytes: usize, budget: usize) {
+        if bytes > budget {
+            return;
+        }
+        self.remove(&key);
+        while self.bytes + bytes > budget {
+            let Some(oldest) = self.recency.values().next().cloned() else {
+                break;
+            };
+            self.remove(&oldest);
+        }
+        self.tick += 1;
+        self.recency.insert(self.tick, key.clone());
+        self.entries.insert(
+            key,
+            Entry {
+                items: Arc::new(items),
+                bytes,
+                last_used: self.tick,
+            },
+        );
+        self.bytes += bytes;
+    }
+
+    /// Drop every cached range which would include any of `items`
+    fn invalidate(&mut self, items: &[Item]) {
+        self.generation += 1;
+        let stale: Vec<RangeKey> = self
+            .entries
+            .keys()
+            .filter(|(start, end)| items.iter().any(|item| start <= item && item < end))
+            .cloned()
+            .collect();
+        for key in stale {
+            self.remove(&key);
+        }
+    }
+}
+
+/**
+A storage backend which caches the results of recent range reads
+from another storage backend in memory
+
+A read is cached once its iterator has been run to the end, and a
+later read of exactly the same range is served from the cache. The
+cache holds at most a given number of bytes of keys and items, and
+evicts the least recently used ranges to stay within it. Inserts
+through the [CachedStorage] drop every cached range they fall within,
+but writes made to the underlying storage by other means aren't
+noticed.
+
+```
+use datom::{backends::{CachedStorage, SledStorage}, Connection, Transaction, ID};
+let storage = CachedStorage::new(SledStorage::connect_temp()?, 1 << 20);
+let conn = Connection::new(storage);
+let user = ID::new();
+let mut tx = Transaction::new();
+tx.add(user.into(), "db/doc".into(), "cached".into());
+conn.transact(tx)?;
+let db = conn.db()?;
+for _ in 0..2 {
+    db.entity(user.into())?.get("db/doc".into())?;
+}
+# Ok::<(), Box<dyn std::error::Error>>(())
+```
+*/
+pub struct CachedStorage<S: Storage> {
+    storage: S,
+    id: ID,
+    budget: usize,
+    cache: Mutex<Cache>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<S: Storage> CachedStorage<S> {
+    /// Cache reads from a storage backend, using at most `budget`
+    /// bytes
+    pub fn new(storage: S, budget: usize) -> Self {
+        Self {
+            storage,
+            id: ID::new(),
+            budget,
+            cache: Mutex::new(Cache::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    fn cache(&self) -> MutexGuard<'_, Cache> {
+        // The cache is consistent between method calls, so it's safe
+        // to reuse after a panic
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The number of reads which were served from the cache
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// The number of reads which went to the underlying storage
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// The number of bytes currently cached
+    pub fn cached_bytes(&self) -> usize {
+        self.cache().bytes
+    }
+}
+
+/// An iterator over an uncached read, which caches the items it
+/// yielded once it reaches the end
+struct CachingIter<'s, S: Storage> {
+    iter: ItemIterator<'s>,
+    storage: &'s CachedStorage<S>,
+    key: RangeKey,
+    generation: u64,
+    /// The items yielded from each end, or [None] if the read won't
+    /// be cached
+    yielded: Option<(Vec<Item>, Vec<Item>)>,
+    bytes: usize,
+}
+
+impl<'s, S: Storage> CachingIter<'s, S> {
+    fn record(
+        &mut self,
+        next: Option<Result<Item, StorageError>>,
+        back: bool,
+    ) -> Option<Result<Item, StorageError>> {
+        match &next {
+            Some(Ok(item)) => {
+                self.bytes += item.len();
+                if self.bytes > self.storage.budget {
+                    self.yielded = None;
+                }
+                if let Some((front, rear)) = &mut self.yielded {
+                    if back { rear } else { front }.push(item.to_owned());
+                }
+            }
+            Some(Err(_)) => self.yielded = None,
+            None => {
+                if let Some((mut items, rear)) = self.yielded.take() {
+                    items.extend(rear.into_iter().rev());
+                    let mut cache = self.storage.cache();
+                    if cache.generation == self.generation {
+                        let key = self.key.clone();
+                        cache.store(key, items, self.bytes, self.storage.budget);
+                    }
+                }
+            }
+        }
+        next
+    }
+}
+
+impl<'s, S: Storage> Iterator for CachingIter<'s, S> {
+    type Item = Result<Item, StorageError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.iter.next();
+        self.record(next, false)
+    }
+}
+
+impl<'s, S: Storage> DoubleEndedIterator for CachingIter<'s, S> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let next = self.iter.next_back();
+        self.record(next, true)
+    }
+}
+
+impl<S: Storage> Storage for CachedStorage<S> {
+    fn range(&self, r: Range<&[u8]>) -> Result<ItemIterator<'_>, StorageError> {
+        let key = (r.start.to_vec(), r.end.to_vec());
+        let (cached, generation) = {
+            let mut cache = self.cache();
+            (cache.get(&key), cache.generation)
+        };
+        if let Some(items) = cached {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(Box::new(
+                (0..items.len()).map(move |i| Ok(items[i].to_owned())),
+            ));
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let bytes = key.0.len() + key.1.len();
+        Ok(Box::new(CachingIter {
+            iter: self.storage.range(r)?,
+            storage: self,
+            key,
+            generation,
+            yielded: Some((vec![], vec![])),
+            bytes,
+        }))
+    }
+
+    fn insert(&self, is: &[Item]) -> Result<(), StorageError> {
+        let res = self.storage.insert(is);
+        self.cache().invalidate(is);
+        res
+    }
+
+    fn insert_if_empty(&self, guard: Range<&[u8]>, is: &[Item]) -> Result<(), StorageError> {
+        self.storage.insert_if_empty(guard, is)?;
+        self.cache().invalidate(is);
+        Ok(())
+    }
+
+    fn id(&self) -> ID {
+        self.id
+    }
+}
+
+impl<S: DurableStorage> DurableStorage for CachedStorage<S> {}
@@ -12,5 +12,39 @@ mod redblacktreeset;
 #[cfg(feature = "redblacktreeset")]
 pub use self::redblacktreeset::RedBlackTreeSetStorage;
 
+#[cfg(feature = "segment")]
+mod segment;
+#[cfg(feature = "segment")]
+pub use self::segment::SegmentStorage;
+
+#[cfg(feature = "sqlite")]
+mod sqlite;
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::SqliteStorage;
+
+#[cfg(feature = "objectstore")]
+mod object;
+#[cfg(feature = "objectstore")]
+pub use self::object::ObjectStorage;
+
+mod cached;
+pub use self::cached::CachedStorage;
+
 mod tiered;
-pub use self::tiered::TieredStorage;
+pub use self::tiered::{Promotion, TieredStorage};
+
+/// Append a length-prefixed field to `out`
+#[cfg(any(feature = "segment", feature = "objectstore"))]
+fn write_field(out: &mut Vec<u8>, field: &[u8]) {
+    out.extend_from_slice(&(field.len() as u32).to_be_bytes());
+    out.extend_from_slice(field);
+}
+
+/// Read a length-prefixed field from the front of `bytes`
+#[cfg(any(feature = "segment", feature = "objectstore"))]
+fn read_field<'b>(bytes: &mut &'b [u8]) -> Option<&'b [u8]> {
+    let len = u32::from_be_bytes(bytes.get(..4)?.try_into().ok()?) as usize;
+    let field = bytes.get(4..4 + len)?;
+    *bytes = &bytes[4 + len..];
+    Some(field)
+}
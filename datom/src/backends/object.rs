@@ -0,0 +1,606 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    io,
+    ops::Range,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use sha2::{Digest, Sha256};
+
+use super::{read_field, write_field};
+use crate::{
+    object_store::ObjectStore,
+    serial::{self, range_slice},
+    storage::{DurableStorage, Item, ItemIterator, Storage},
+    StorageError, ID,
+};
+
+/// The key of the object holding the key of the root segment
+const ROOT_KEY: &str = "root";
+
+/// The default for [ObjectStorage::segment_size]
+const DEFAULT_SEGMENT_SIZE: usize = 64 << 10;
+
+/// The default for [ObjectStorage::branch_cache_size]
+const DEFAULT_BRANCH_CACHE_SIZE: usize = 16 << 20;
+
+/// The first item in each of a branch's children, and the child's key
+type Children = Vec<(Item, String)>;
+
+struct CachedBranch {
+    level: u8,
+    children: Arc<Children>,
+    bytes: usize,
+    last_used: u64,
+}
+
+/// Branches which have been read or written, which never go stale
+/// because segments are immutable
+#[derive(Default)]
+struct BranchCache {
+    entries: HashMap<String, CachedBranch>,
+    /// Cached branches by when they were last used, oldest first
+    recency: BTreeMap<u64, String>,
+    bytes: usize,
+    tick: u64,
+}
+
+impl BranchCache {
+    fn get(&mut self, key: &str) -> Option<(u8, Arc<Children>)> {
+        self.tick += 1;
+        let entry = self.entries.get_mut(key)?;
+        self.recency.remove(&entry.last_used);
+        entry.last_used = self.tick;
+        self.recency.insert(self.tick, key.to_owned());
+        Some((entry.level, entry.children.clone()))
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.recency.remove(&entry.last_used);
+            self.bytes -= entry.bytes;
+        }
+    }
+
+    /// Cache a branch, evicting the least recently used branches until
+    /// it fits in the budget
+    fn store(&mut self, key: String, level: u8, children: Arc<Children>, budget: usize) {
+        let bytes = key.len()
+            + children
+                .iter()
+                .map(|(first, key)| first.len() + key.len())
+                .sum::<usize>();
+        if bytes > budget {
+            return;
+        }
+        self.remove(&key);
+        while self.bytes + bytes > budget {
+            let Some(oldest) = self.recency.values().next().cloned() else {
+                break;
+            };
+            self.remove(&oldest);
+        }
+        self.tick += 1;
+        self.recency.insert(self.tick, key.clone());
+        self.entries.insert(
+            key,
+            CachedBranch {
+                level,
+                children,
+                bytes,
+                last_used: self.tick,
+            },
+        );
+        self.bytes += bytes;
+    }
+}
+
+/// A segment of the tree
+enum Node {
+    /// Sorted items
+    Leaf(Vec<Item>),
+    /// Children which are leaves if the level is 1, or branches of
+    /// the level below
+    Branch(u8, Arc<Children>),
+}
+
+impl Node {
+    fn encode(&self) -> Vec<u8> {
+        match self {
+            Self::Leaf(items) => {
+                let mut bytes = vec![0];
+                for item in items {
+                    write_field(&mut bytes, item);
+                }
+                bytes
+            }
+            Self::Branch(level, children) => {
+                let mut bytes = vec![*level];
+                for (first, key) in children.iter() {
+                    write_field(&mut bytes, first);
+                    write_field(&mut bytes, key.as_bytes());
+                }
+                bytes
+            }
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        let (&level, mut rest) = bytes.split_first()?;
+        if level == 0 {
+            let mut items = vec![];
+            while !rest.is_empty() {
+                items.push(read_field(&mut rest)?.to_vec());
+            }
+            return Some(Self::Leaf(items));
+        }
+        let mut children = vec![];
+        while !rest.is_empty() {
+            let first = read_field(&mut rest)?.to_vec();
+            let key = String::from_utf8(read_field(&mut rest)?.to_vec()).ok()?;
+            children.push((first, key));
+        }
+        Some(Self::Branch(level, Arc::new(children)))
+    }
+
+    const fn level(&self) -> u8 {
+        match self {
+            Self::Leaf(_) => 0,
+            Self::Branch(level, _) => *level,
+        }
+    }
+}
+
+/// Split values into runs of at least `min` values, starting a new run
+/// once one reaches `limit` bytes. A last run which is too short is
+/// merged into the one before it, so only a single run can be short.
+fn split<T>(values: Vec<T>, size: impl Fn(&T) -> usize, limit: usize, min: usize) -> Vec<Vec<T>> {
+    let mut runs = vec![];
+    let mut run = vec![];
+    let mut bytes = 0;
+    for value in values {
+        if run.len() >= min && bytes >= limit {
+            runs.push(std::mem::take(&mut run));
+            bytes = 0;
+        }
+        bytes += size(&value);
+        run.push(value);
+    }
+    match runs.last_mut() {
+        Some(last) if run.len() < min => last.append(&mut run),
+        _ if run.is_empty() => {}
+        _ => runs.push(run),
+    }
+    runs
+}
+
+/**
+A storage backend which keeps its items in an [ObjectStore], as a
+tree of immutable segments
+
+Each segment is written once, under a key derived from its contents,
+and never changed. Leaf segments hold sorted runs of items, and branch
+segments point to the segments below them, in the style of Datomic's
+index trees. The only mutable object is a small root pointer to the
+top segment, which is replaced with
+[ObjectStore::compare_and_swap] after an insert writes new segments
+for the paths it changed, so any number of readers can share the
+store, and a database can be read purely from its segments.
+
+Segments which an insert replaced stay in the store, since a reader
+may still be working from an older root. Removing the segments which
+are no longer reachable from the root is left to the store, for
+instance with an offline sweep or the store's own lifecycle rules.
+
+```
+use datom::{backends::ObjectStorage, object_store::LocalObjectStore, Connection, Transaction, ID};
+let conn = Connection::new(ObjectStorage::new(LocalObjectStore::temp()?)?);
+let user = ID::new();
+let mut tx = Transaction::new();
+tx.add(user.into(), "db/doc".into(), "in segments".into());
+conn.transact(tx)?;
+conn.db()?.entity(user.into())?.get("db/doc".into())?;
+# Ok::<(), Box<dyn std::error::Error>>(())
+```
+*/
+pub struct ObjectStorage<O: ObjectStore> {
+    store: O,
+    id: ID,
+    segment_size: usize,
+    branch_cache_size: usize,
+    branches: Mutex<BranchCache>,
+    write_lock: Mutex<()>,
+}
+
+impl<O: ObjectStore> ObjectStorage<O> {
+    /// Use an [ObjectStore], which may already hold a database
+    pub fn new(store: O) -> Result<Self, StorageError> {
+        let storage = Self {
+            store,
+            id: ID::new(),
+            segment_size: DEFAULT_SEGMENT_SIZE,
+            branch_cache_size: DEFAULT_BRANCH_CACHE_SIZE,
+            branches: Mutex::new(BranchCache::default()),
+            write_lock: Mutex::new(()),
+        };
+        storage.check_version()?;
+        Ok(storage)
+    }
+
+    /// Set roughly how many bytes each new segment holds before it's
+    /// split in two
+    #[allow(clippy::missing_const_for_fn)]
+    pub fn segment_size(mut self, segment_size: usize) -> Self {
+        self.segment_size = segment_size;
+        self
+    }
+
+    /// Set roughly how many bytes of branch segments are kept in
+    /// memory, evicting the least recently used ones beyond that
+    #[allow(clippy::missing_const_for_fn)]
+    pub fn branch_cache_size(mut self, branch_cache_size: usize) -> Self {
+        self.branch_cache_size = branch_cache_size;
+        self
+    }
+
+    /// The number of bytes of branch segments currently cached
+    pub fn cached_branch_bytes(&self) -> usize {
+        self.branches().bytes
+    }
+
+    /// The underlying object store
+    pub const fn store(&self) -> &O {
+        &self.store
+    }
+
+    /// Mark a new database with the current
+    /// [format version](serial::FORMAT_VERSION), and refuse to open
+    /// a database with any other
+    fn check_version(&self) -> Result<(), StorageError> {
+        let empty = self.root()?.is_none();
+        let version = match self.range(range_slice(&serial::format_range()))?.next() {
+            Some(item) => item?.get(1).copied().unwrap_or(0),
+            None if empty => return self.insert(&[serial::format_marker().to_vec()]),
+            None => 0,
+        };
+        if version != serial::FORMAT_VERSION {
+            return Err(malformed(format!(
+                "database format version {} isn't {}",
+                version,
+                serial::FORMAT_VERSION
+            )));
+        }
+        Ok(())
+    }
+
+    fn branches(&self) -> MutexGuard<'_, BranchCache> {
+        self.branches.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The key of the root segment, or [None] if the tree is empty
+    fn root(&self) -> Result<Option<String>, StorageError> {
+        self.store
+            .get(ROOT_KEY)?
+            .map(|key| String::from_utf8(key).map_err(|_| malformed("root pointer".to_owned())))
+            .transpose()
+    }
+
+    fn node(&self, key: &str) -> Result<Node, StorageError> {
+        let cached = self.branches().get(key);
+        if let Some((level, children)) = cached {
+            return Ok(Node::Branch(level, children));
+        }
+        let bytes = self
+            .store
+            .get(key)?
+            .ok_or_else(|| malformed(format!("missing segment {}", key)))?;
+        let node =
+            Node::decode(&bytes).ok_or_else(|| malformed(format!("malformed segment {}", key)))?;
+        if let Node::Branch(level, children) = &node {
+            self.branches().store(
+                key.to_owned(),
+                *level,
+                children.clone(),
+                self.branch_cache_size,
+            );
+        }
+        Ok(node)
+    }
+
+    /// Write a segment, returning its key
+    fn write(&self, node: &Node) -> Result<String, StorageError> {
+        let bytes = node.encode();
+        let hash: String = Sha256::digest(&bytes)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        let key = format!("segments/{}", hash);
+        self.store.put(&key, &bytes)?;
+        if let Node::Branch(level, children) = node {
+            self.branches().store(
+                key.clone(),
+                *level,
+                children.clone(),
+                self.branch_cache_size,
+            );
+        }
+        Ok(key)
+    }
+
+    /// Write sorted items to as many leaves as they need
+    fn write_leaves(&self, items: Vec<Item>) -> Result<Children, StorageError> {
+        split(items, Vec::len, self.segment_size, 1)
+            .into_iter()
+            .map(|run| Ok((run[0].clone(), self.write(&Node::Leaf(run))?)))
+            .collect()
+    }
+
+    /// Write children to as many branches of a level as they need.
+    /// Given at least two children, each branch has at least two, so
+    /// the tree always gets narrower towards the root.
+    fn write_branches(&self, level: u8, children: Children) -> Result<Children, StorageError> {
+        let size = |(first, key): &(Item, String)| first.len() + key.len();
+        split(children, size, self.segment_size, 2)
+            .into_iter()
+            .map(|run| {
+                let first = run[0].0.clone();
+                Ok((first, self.write(&Node::Branch(level, Arc::new(run)))?))
+            })
+            .collect()
+    }
+
+    /// Insert sorted items into the subtree under a segment, returning
+    /// the segments which replace it. The segment itself is returned if
+    /// it already had every item.
+    fn insert_node(&self, key: &str, items: &[Item]) -> Result<Children, StorageError> {
+        match self.node(key)? {
+            Node::Leaf(existing) => {
+                let first = existing.first().cloned().unwrap_or_default();
+                let count = existing.len();
+                let merged: BTreeSet<Item> =
+                    existing.into_iter().chain(items.iter().cloned()).collect();
+                if merged.len() == count {
+                    return Ok(vec![(first, key.to_owned())]);
+                }
+                self.write_leaves(merged.into_iter().collect())
+            }
+            Node::Branch(level, children) => {
+                let mut replaced = vec![];
+                let mut changed = false;
+                let mut rest = items;
+                for (i, (first, child)) in children.iter().enumerate() {
+                    // Items before the first child's first item belong
+                    // to the first child
+                    let n = match children.get(i + 1) {
+                        Some((next, _)) => rest.partition_point(|item| item < next),
+                        None => rest.len(),
+                    };
+                    let (mine, others) = rest.split_at(n);
+                    rest = others;
+                    if mine.is_empty() {
+                        replaced.push((first.clone(), child.clone()));
+                    } else {
+                        let new = self.insert_node(child, mine)?;
+                        changed |= new.len() != 1 || new[0].1 != *child;
+                        replaced.extend(new);
+                    }
+                }
+                if !changed {
+                    return Ok(vec![(children[0].0.clone(), key.to_owned())]);
+                }
+                self.write_branches(level, replaced)
+            }
+        }
+    }
+
+    /// Insert sorted items into the tree under a root, returning the
+    /// new root
+    fn insert_tree(
+        &self,
+        root: Option<&str>,
+        items: &[Item],
+    ) -> Result<Option<String>, StorageError> {
+        let (mut level, mut children) = match root {
+            Some(root) => (self.node(root)?.level(), self.insert_node(root, items)?),
+            None => (0, self.write_leaves(items.to_vec())?),
+        };
+        while children.len() > 1 {
+            level += 1;
+            children = self.write_branches(level, children)?;
+        }
+        Ok(children.pop().map(|(_, key)| key))
+    }
+
+    /// Write new segments holding the items, and point the root at
+    /// them, if there are no items within `guard` yet
+    fn insert_guarded(&self, guard: Option<Range<&[u8]>>, is: &[Item]) -> Result<(), StorageError> {
+        let _lock = self
+            .write_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let items: Vec<Item> = is
+            .iter()
+            .cloned()
+            .collect::<BTreeSet<Item>>()
+            .into_iter()
+            .collect();
+        // Another process can move the root between reading and
+        // replacing it, in which case the insert starts again from its
+        // new root
+        loop {
+            let root = self.root()?;
+            if let Some(guard) = &guard {
+                if self
+                    .range_under(root.as_deref(), guard.clone())?
+                    .next()
+                    .is_some()
+                {
+                    return Err(StorageError::ConcurrencyError);
+                }
+            }
+            let new_root = self.insert_tree(root.as_deref(), &items)?;
+            let Some(new_root) = new_root.filter(|new| Some(new) != root.as_ref()) else {
+                return Ok(());
+            };
+            let expected = root.as_ref().map(String::as_bytes);
+            if self
+                .store
+                .compare_and_swap(ROOT_KEY, expected, new_root.as_bytes())?
+            {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Find the leaves under a segment which could hold items in a
+    /// range
+    fn leaves(
+        &self,
+        key: &str,
+        r: Range<&[u8]>,
+        out: &mut Vec<String>,
+    ) -> Result<(), StorageError> {
+        let Node::Branch(level, children) = self.node(key)? else {
+            out.push(key.to_owned());
+            return Ok(());
+        };
+        for (i, (first, child)) in children.iter().enumerate() {
+            let after_start = children
+                .get(i + 1)
+                .map_or(true, |(next, _)| next.as_slice() > r.start);
+            let before_end = i == 0 || first.as_slice() < r.end;
+            if !(after_start && before_end) {
+                continue;
+            }
+            if level == 1 {
+                out.push(child.clone());
+            } else {
+                self.leaves(child, r.clone(), out)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn range_under(
+        &self,
+        root: Option<&str>,
+        r: Range<&[u8]>,
+    ) -> Result<ObjectIter<'_, O>, StorageError> {
+        let mut leaves = vec![];
+        if let Some(root) = root {
+            if r.start < r.end {
+                self.leaves(root, r.clone(), &mut leaves)?;
+            }
+        }
+        Ok(ObjectIter {
+            storage: self,
+            leaves: leaves.into(),
+            front: VecDeque::new(),
+            back: VecDeque::new(),
+            bounds: r.start.to_vec()..r.end.to_vec(),
+        })
+    }
+
+    fn leaf(&self, key: &str) -> Result<VecDeque<Item>, StorageError> {
+        match self.node(key)? {
+            Node::Leaf(items) => Ok(items.into()),
+            Node::Branch(..) => Err(malformed(format!("segment {} isn't a leaf", key))),
+        }
+    }
+}
+
+fn malformed(message: String) -> StorageError {
+    io::Error::new(io::ErrorKind::InvalidData, message).into()
+}
+
+/// An iterator over a range of an [ObjectStorage], which reads one
+/// leaf at a time from either end
+struct ObjectIter<'s, O: ObjectStore> {
+    storage: &'s ObjectStorage<O>,
+    leaves: VecDeque<String>,
+    front: VecDeque<Item>,
+    back: VecDeque<Item>,
+    bounds: Range<Item>,
+}
+
+impl<'s, O: ObjectStore> ObjectIter<'s, O> {
+    fn load(&mut self, key: &str) -> Result<VecDeque<Item>, StorageError> {
+        self.storage.leaf(key).map_err(|e| {
+            // Stop at the first error
+            self.leaves.clear();
+            self.front.clear();
+            self.back.clear();
+            e
+        })
+    }
+}
+
+impl<'s, O: ObjectStore> Iterator for ObjectIter<'s, O> {
+    type Item = Result<Item, StorageError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.front.pop_front() {
+                if self.bounds.contains(&item) {
+                    return Some(Ok(item));
+                }
+            } else if let Some(leaf) = self.leaves.pop_front() {
+                match self.load(&leaf) {
+                    Ok(items) => self.front = items,
+                    Err(e) => return Some(Err(e)),
+                }
+            } else {
+                let item = self.back.pop_front()?;
+                if self.bounds.contains(&item) {
+                    return Some(Ok(item));
+                }
+            }
+        }
+    }
+}
+
+impl<'s, O: ObjectStore> DoubleEndedIterator for ObjectIter<'s, O> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.back.pop_back() {
+                if self.bounds.contains(&item) {
+                    return Some(Ok(item));
+                }
+            } else if let Some(leaf) = self.leaves.pop_back() {
+                match self.load(&leaf) {
+                    Ok(items) => self.back = items,
+                    Err(e) => return Some(Err(e)),
+                }
+            } else {
+                let item = self.front.pop_back()?;
+                if self.bounds.contains(&item) {
+                    return Some(Ok(item));
+                }
+            }
+        }
+    }
+}
+
+impl<O: ObjectStore> Storage for ObjectStorage<O> {
+    fn range(&self, r: Range<&[u8]>) -> Result<ItemIterator<'_>, StorageError> {
+        Ok(Box::new(self.range_under(self.root()?.as_deref(), r)?))
+    }
+
+    fn insert(&self, is: &[Item]) -> Result<(), StorageError> {
+        self.insert_guarded(None, is)
+    }
+
+    fn insert_if_empty(&self, guard: Range<&[u8]>, is: &[Item]) -> Result<(), StorageError> {
+        self.insert_guarded(Some(guard), is)
+    }
+
+    fn id(&self) -> ID {
+        self.id
+    }
+}
+
+impl<O: ObjectStore> DurableStorage for ObjectStorage<O> {}
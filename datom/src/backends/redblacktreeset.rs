@@ -86,12 +86,33 @@ impl Storage for RedBlackTreeSetStorage {
     }
 
     fn insert(&self, is: &[Item]) -> Result<(), StorageError> {
-        let set = (*self.set.load_full()).clone();
-        let set = is.iter().fold(set, |s, i| s.insert(i.to_owned()));
-        self.set.swap(Arc::new(set));
+        self.set.rcu(|set| {
+            is.iter()
+                .fold((**set).clone(), |s, i| s.insert(i.to_owned()))
+        });
         Ok(())
     }
 
+    fn insert_if_empty(&self, guard: Range<&[u8]>, is: &[Item]) -> Result<(), StorageError> {
+        loop {
+            let current = self.set.load_full();
+            if current
+                .range(guard.start.to_vec()..guard.end.to_vec())
+                .next()
+                .is_some()
+            {
+                return Err(StorageError::ConcurrencyError);
+            }
+            let set = is
+                .iter()
+                .fold((*current).clone(), |s, i| s.insert(i.to_owned()));
+            let previous = self.set.compare_and_swap(&current, Arc::new(set));
+            if Arc::ptr_eq(&previous, &current) {
+                return Ok(());
+            }
+        }
+    }
+
     fn id(&self) -> ID {
         self.id
     }
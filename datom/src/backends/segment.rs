@@ -0,0 +1,784 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+use std::{
+    collections::{BTreeSet, VecDeque},
+    env::temp_dir,
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
+    thread::{self, JoinHandle},
+};
+
+use fs2::FileExt;
+use uuid::Uuid;
+
+use super::{read_field, write_field};
+use crate::{
+    merge_iters::MergeIters,
+    serial::{self, range_slice},
+    storage::{DurableStorage, Item, ItemIterator, Storage},
+    StorageError, ID,
+};
+
+/// How many bytes of items the memtable holds before it's flushed to
+/// a segment
+const MEMTABLE_LIMIT: usize = 4 << 20;
+
+/// Roughly how many bytes of items are between each entry in a
+/// segment's sparse index
+const BLOCK_SIZE: usize = 4096;
+
+/// How many segments there can be before they're compacted in the
+/// background
+const COMPACTION_THRESHOLD: usize = 4;
+
+const LOG_NAME: &str = "wal.log";
+const LOCK_NAME: &str = "LOCK";
+const SEGMENT_EXTENSION: &str = "seg";
+const TEMPORARY_EXTENSION: &str = "tmp";
+const SEGMENT_MAGIC: &[u8; 8] = b"DATOMSEG";
+
+/// The length of a segment's footer: the offset of its index, the
+/// index's checksum, and [SEGMENT_MAGIC]
+const FOOTER_LEN: usize = 8 + 4 + 8;
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 == 1 {
+                0xEDB8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+};
+
+/// The CRC-32 of some bytes
+fn checksum(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |c, &b| {
+        CRC_TABLE[((c ^ u32::from(b)) & 0xff) as usize] ^ (c >> 8)
+    })
+}
+
+fn corrupt(message: String) -> StorageError {
+    io::Error::new(io::ErrorKind::InvalidData, message).into()
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    u64::from_be_bytes(bytes[..8].try_into().expect("8 bytes"))
+}
+
+/// Make a new or renamed file in a directory durable
+fn sync_dir(dir: &Path) {
+    // Directories can't be opened on every platform, and those which
+    // can't don't need syncing
+    if let Ok(dir) = File::open(dir) {
+        dir.sync_all().ok();
+    }
+}
+
+/// An entry in a segment's sparse index
+struct Block {
+    /// The first item in the block
+    first: Item,
+    offset: u64,
+    /// The checksum of the block's bytes
+    checksum: u32,
+}
+
+/// An immutable, sorted file of items
+struct Segment {
+    path: PathBuf,
+    file: Mutex<File>,
+    index: Vec<Block>,
+    /// The offset of the end of the last block
+    data_end: u64,
+    /// Set once the segment has been compacted into another, so its
+    /// file is removed when the last read from it finishes
+    obsolete: AtomicBool,
+}
+
+impl Segment {
+    fn path(dir: &Path, number: u64, extension: &str) -> PathBuf {
+        let mut path = dir.join(format!("{:020}", number));
+        path.set_extension(extension);
+        path
+    }
+
+    /// Write sorted items to a new segment. The segment is written to a
+    /// temporary file and renamed into place, so a crash never leaves
+    /// part of a segment behind.
+    fn write(
+        dir: &Path,
+        number: u64,
+        items: impl Iterator<Item = Result<Item, StorageError>>,
+    ) -> Result<Self, StorageError> {
+        let temporary = Self::path(dir, number, TEMPORARY_EXTENSION);
+        let mut out = BufWriter::new(File::create(&temporary)?);
+        let mut index: Vec<Block> = vec![];
+        let mut offset = 0;
+        let mut block = vec![];
+        for item in items {
+            let item = item?;
+            if block.len() >= BLOCK_SIZE {
+                offset += Self::write_block(&mut out, &mut index, &mut block)?;
+            }
+            if block.is_empty() {
+                index.push(Block {
+                    first: item.clone(),
+                    offset,
+                    checksum: 0,
+                });
+            }
+            write_field(&mut block, &item);
+        }
+        offset += Self::write_block(&mut out, &mut index, &mut block)?;
+        let mut index_bytes = (index.len() as u64).to_be_bytes().to_vec();
+        for block in index.iter() {
+            index_bytes.extend_from_slice(&block.offset.to_be_bytes());
+            index_bytes.extend_from_slice(&block.checksum.to_be_bytes());
+            write_field(&mut index_bytes, &block.first);
+        }
+        out.write_all(&index_bytes)?;
+        out.write_all(&offset.to_be_bytes())?;
+        out.write_all(&checksum(&index_bytes).to_be_bytes())?;
+        out.write_all(SEGMENT_MAGIC)?;
+        out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        let path = Self::path(dir, number, SEGMENT_EXTENSION);
+        fs::rename(&temporary, &path)?;
+        sync_dir(dir);
+        Self::open(path)
+    }
+
+    /// Write the bytes of the last block in the index, and record their
+    /// checksum. Returns how many bytes were written.
+    fn write_block(
+        out: &mut impl Write,
+        index: &mut [Block],
+        block: &mut Vec<u8>,
+    ) -> Result<u64, StorageError> {
+        if let Some(last) = index.last_mut() {
+            last.checksum = checksum(block);
+        }
+        out.write_all(block)?;
+        let len = block.len() as u64;
+        block.clear();
+        Ok(len)
+    }
+
+    /// Open a segment, reading its sparse index into memory
+    fn open(path: PathBuf) -> Result<Self, StorageError> {
+        let mut file = File::open(&path)?;
+        let len = file.metadata()?.len();
+        let malformed = || corrupt(format!("malformed segment {}", path.display()));
+        if len < FOOTER_LEN as u64 {
+            return Err(malformed());
+        }
+        let mut footer = [0; FOOTER_LEN];
+        file.seek(SeekFrom::Start(len - FOOTER_LEN as u64))?;
+        file.read_exact(&mut footer)?;
+        let data_end = read_u64(&footer);
+        let index_checksum = u32::from_be_bytes(footer[8..12].try_into().expect("4 bytes"));
+        if &footer[12..] != SEGMENT_MAGIC || data_end > len - FOOTER_LEN as u64 {
+            return Err(malformed());
+        }
+        let mut index_bytes = vec![0; (len - FOOTER_LEN as u64 - data_end) as usize];
+        file.seek(SeekFrom::Start(data_end))?;
+        file.read_exact(&mut index_bytes)?;
+        if index_bytes.len() < 8 || checksum(&index_bytes) != index_checksum {
+            return Err(malformed());
+        }
+        let count = read_u64(&index_bytes);
+        let mut rest = &index_bytes[8..];
+        let mut index = vec![];
+        for _ in 0..count {
+            let header = rest.get(..12).ok_or_else(malformed)?;
+            let offset = read_u64(header);
+            let checksum = u32::from_be_bytes(header[8..].try_into().expect("4 bytes"));
+            rest = &rest[12..];
+            let first = read_field(&mut rest).ok_or_else(malformed)?;
+            index.push(Block {
+                first: first.to_vec(),
+                offset,
+                checksum,
+            });
+        }
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+            index,
+            data_end,
+            obsolete: AtomicBool::new(false),
+        })
+    }
+
+    /// Read every item in a block, checking it against its checksum
+    fn block(&self, i: usize) -> Result<VecDeque<Item>, StorageError> {
+        let block = &self.index[i];
+        let end = self.index.get(i + 1).map_or(self.data_end, |b| b.offset);
+        let mut bytes = vec![0; (end - block.offset) as usize];
+        {
+            let mut file = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+            file.seek(SeekFrom::Start(block.offset))?;
+            file.read_exact(&mut bytes)?;
+        }
+        let malformed = || corrupt(format!("malformed segment {}", self.path.display()));
+        if checksum(&bytes) != block.checksum {
+            return Err(malformed());
+        }
+        let mut rest = &bytes[..];
+        let mut items = VecDeque::new();
+        while !rest.is_empty() {
+            let item = read_field(&mut rest).ok_or_else(malformed)?;
+            items.push_back(item.to_vec());
+        }
+        Ok(items)
+    }
+
+    /// The blocks which could hold items in a range
+    fn blocks(&self, r: Range<&[u8]>) -> Range<usize> {
+        let start = self
+            .index
+            .partition_point(|block| block.first.as_slice() <= r.start)
+            .saturating_sub(1);
+        let end = self
+            .index
+            .partition_point(|block| block.first.as_slice() < r.end);
+        start..end.max(start)
+    }
+}
+
+impl Drop for Segment {
+    fn drop(&mut self) {
+        if *self.obsolete.get_mut() {
+            fs::remove_file(&self.path).ok();
+        }
+    }
+}
+
+/// An iterator over the items of a [Segment], which reads one block
+/// at a time from either end
+struct SegmentIter {
+    segment: Arc<Segment>,
+    blocks: Range<usize>,
+    front: VecDeque<Item>,
+    back: VecDeque<Item>,
+    /// The range to yield items from, or [None] for the whole segment
+    bounds: Option<Range<Item>>,
+}
+
+impl SegmentIter {
+    fn new(segment: Arc<Segment>, bounds: Option<Range<Item>>) -> Self {
+        let blocks = bounds.as_ref().map_or_else(
+            || 0..segment.index.len(),
+            |r| segment.blocks(r.start.as_slice()..r.end.as_slice()),
+        );
+        Self {
+            segment,
+            blocks,
+            front: VecDeque::new(),
+            back: VecDeque::new(),
+            bounds,
+        }
+    }
+
+    fn includes(&self, item: &Item) -> bool {
+        self.bounds.as_ref().map_or(true, |r| r.contains(item))
+    }
+
+    fn load(&mut self, block: usize) -> Result<VecDeque<Item>, StorageError> {
+        self.segment.block(block).map_err(|e| {
+            // Stop at the first error
+            self.blocks = 0..0;
+            self.front.clear();
+            self.back.clear();
+            e
+        })
+    }
+}
+
+impl Iterator for SegmentIter {
+    type Item = Result<Item, StorageError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.front.pop_front() {
+                if self.includes(&item) {
+                    return Some(Ok(item));
+                }
+            } else if let Some(block) = self.blocks.next() {
+                match self.load(block) {
+                    Ok(items) => self.front = items,
+                    Err(e) => return Some(Err(e)),
+                }
+            } else {
+                let item = self.back.pop_front()?;
+                if self.includes(&item) {
+                    return Some(Ok(item));
+                }
+            }
+        }
+    }
+}
+
+impl DoubleEndedIterator for SegmentIter {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.back.pop_back() {
+                if self.includes(&item) {
+                    return Some(Ok(item));
+                }
+            } else if let Some(block) = self.blocks.next_back() {
+                match self.load(block) {
+                    Ok(items) => self.back = items,
+                    Err(e) => return Some(Err(e)),
+                }
+            } else {
+                let item = self.front.pop_back()?;
+                if self.includes(&item) {
+                    return Some(Ok(item));
+                }
+            }
+        }
+    }
+}
+
+/// Merge sorted iterators, yielding items which are in several once
+fn merge<'s>(iters: impl Iterator<Item = ItemIterator<'s>>) -> ItemIterator<'s> {
+    iters.fold(Box::new(std::iter::empty()), |merged, iter| {
+        Box::new(MergeIters::new(merged, iter).map(|x| x.0))
+    })
+}
+
+/// The write-ahead log, holding every insert since the memtable was
+/// last flushed
+struct Log {
+    file: File,
+    len: u64,
+}
+
+impl Log {
+    /// Open the log, returning it and the items it holds. A record
+    /// which was only partly written when the process stopped is
+    /// truncated away.
+    fn open(path: &Path) -> Result<(Self, Vec<Item>), StorageError> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        let mut bytes = vec![];
+        file.read_to_end(&mut bytes)?;
+        let mut items = vec![];
+        let mut rest = &bytes[..];
+        while rest.len() >= 8 {
+            let len = u32::from_be_bytes(rest[..4].try_into().expect("4 bytes")) as usize;
+            let record_checksum = u32::from_be_bytes(rest[4..8].try_into().expect("4 bytes"));
+            let Some(mut payload) = rest.get(8..8 + len) else {
+                break;
+            };
+            if checksum(payload) != record_checksum {
+                break;
+            }
+            let mut record = vec![];
+            while let Some(item) = read_field(&mut payload) {
+                record.push(item.to_vec());
+            }
+            if !payload.is_empty() {
+                break;
+            }
+            items.extend(record);
+            rest = &rest[8 + len..];
+        }
+        let len = (bytes.len() - rest.len()) as u64;
+        if !rest.is_empty() {
+            file.set_len(len)?;
+            file.sync_all()?;
+        }
+        Ok((Self { file, len }, items))
+    }
+
+    /// Durably append a record of items to the log
+    fn append(&mut self, items: &[Item]) -> Result<(), StorageError> {
+        let mut payload = vec![];
+        for item in items {
+            write_field(&mut payload, item);
+        }
+        let mut record = (payload.len() as u32).to_be_bytes().to_vec();
+        record.extend_from_slice(&checksum(&payload).to_be_bytes());
+        record.extend_from_slice(&payload);
+        let res = self
+            .file
+            .write_all(&record)
+            .and_then(|_| self.file.sync_data());
+        if let Err(e) = res {
+            // Don't leave part of a record for later records to follow
+            self.file.set_len(self.len).ok();
+            return Err(e.into());
+        }
+        self.len += record.len() as u64;
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<(), StorageError> {
+        self.file.set_len(0)?;
+        self.file.sync_all()?;
+        self.len = 0;
+        Ok(())
+    }
+}
+
+struct State {
+    /// Items which are in the log but not yet in a segment
+    memtable: BTreeSet<Item>,
+    memtable_bytes: usize,
+    segments: Vec<Arc<Segment>>,
+}
+
+/// The parts of a [SegmentStorage] which background compaction uses
+struct Shared {
+    dir: PathBuf,
+    state: RwLock<State>,
+    /// Held for the whole of every write, so conditional inserts are
+    /// atomic and the log matches the memtable
+    log: Mutex<Log>,
+    /// Held for the whole of every compaction
+    compaction: Mutex<()>,
+    next_segment: AtomicU64,
+}
+
+impl Shared {
+    fn state(&self) -> RwLockReadGuard<'_, State> {
+        self.state.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn state_mut(&self) -> RwLockWriteGuard<'_, State> {
+        self.state.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn log(&self) -> MutexGuard<'_, Log> {
+        self.log.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn range(&self, r: Range<&[u8]>) -> ItemIterator<'static> {
+        if r.start >= r.end {
+            return Box::new(std::iter::empty());
+        }
+        let bounds = r.start.to_vec()..r.end.to_vec();
+        // Reads see the segments and memtable as they were when they
+        // started, so they don't hold the lock
+        let (memtable, segments) = {
+            let state = self.state();
+            let memtable: Vec<Item> = state.memtable.range(bounds.clone()).cloned().collect();
+            (memtable, state.segments.clone())
+        };
+        let segments = segments.into_iter().map(|segment| -> ItemIterator {
+            Box::new(SegmentIter::new(segment, Some(bounds.clone())))
+        });
+        merge(
+            std::iter::once::<ItemIterator>(Box::new(memtable.into_iter().map(Ok))).chain(segments),
+        )
+    }
+
+    /// Write the memtable to a new segment and clear the log. The
+    /// caller must hold the log's lock.
+    fn flush(&self, log: &mut Log) -> Result<(), StorageError> {
+        let items: Vec<Item> = self.state().memtable.iter().cloned().collect();
+        if items.is_empty() {
+            return Ok(());
+        }
+        let number = self.next_segment.fetch_add(1, Ordering::SeqCst);
+        let segment = Segment::write(&self.dir, number, items.into_iter().map(Ok))?;
+        {
+            let mut state = self.state_mut();
+            state.segments.push(Arc::new(segment));
+            state.memtable.clear();
+            state.memtable_bytes = 0;
+        }
+        // If the process stops before the log is cleared, replaying it
+        // only inserts items which are already in the segment
+        log.clear()
+    }
+
+    /// Merge every segment into one. A segment which was merged is
+    /// removed once nothing is reading from it, and if the process
+    /// stops first it's merged again next time.
+    fn compact(&self) -> Result<(), StorageError> {
+        let _lock = self
+            .compaction
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let segments = self.state().segments.clone();
+        if segments.len() < 2 {
+            return Ok(());
+        }
+        let number = self.next_segment.fetch_add(1, Ordering::SeqCst);
+        let items =
+            merge(segments.iter().map(|segment| -> ItemIterator {
+                Box::new(SegmentIter::new(segment.clone(), None))
+            }));
+        let compacted = Arc::new(Segment::write(&self.dir, number, items)?);
+        {
+            let mut state = self.state_mut();
+            state
+                .segments
+                .retain(|segment| !segments.iter().any(|s| Arc::ptr_eq(segment, s)));
+            state.segments.push(compacted);
+        }
+        for segment in segments {
+            segment.obsolete.store(true, Ordering::SeqCst);
+        }
+        Ok(())
+    }
+}
+
+/**
+A storage backend which keeps its items in a directory of sorted
+segment files, in the style of a log-structured merge tree
+
+Inserts are appended to a write-ahead log and kept in memory until
+there are enough of them to write a new segment. Segments are never
+modified, and once there are several they're merged into one in the
+background. When a [SegmentStorage] is opened, inserts which are in
+the log but not yet in a segment are replayed, so nothing which was
+inserted is lost if the process stops. Only one [SegmentStorage] may
+use a directory at a time, which a lock file in it enforces, and every
+block of a segment is checked against a checksum as it's read.
+
+```
+use datom::{backends::SegmentStorage, Connection, Transaction, ID};
+let conn = Connection::new(SegmentStorage::connect_temp()?);
+let user = ID::new();
+let mut tx = Transaction::new();
+tx.add(user.into(), "db/doc".into(), "segmented".into());
+conn.transact(tx)?;
+conn.db()?.entity(user.into())?.get("db/doc".into())?;
+# Ok::<(), Box<dyn std::error::Error>>(())
+```
+*/
+pub struct SegmentStorage {
+    shared: Arc<Shared>,
+    id: ID,
+    compactor: Mutex<Option<JoinHandle<()>>>,
+    /// Exclusively locked while the storage is open, so no other
+    /// [SegmentStorage] uses the directory
+    lock: Option<File>,
+    temporary: bool,
+}
+
+impl Storage for SegmentStorage {
+    fn range(&self, r: Range<&[u8]>) -> Result<ItemIterator<'_>, StorageError> {
+        Ok(self.shared.range(r))
+    }
+
+    fn insert(&self, is: &[Item]) -> Result<(), StorageError> {
+        let mut log = self.shared.log();
+        self.insert_locked(&mut log, is)
+    }
+
+    fn insert_if_empty(&self, guard: Range<&[u8]>, is: &[Item]) -> Result<(), StorageError> {
+        let mut log = self.shared.log();
+        if self.shared.range(guard).next().is_some() {
+            return Err(StorageError::ConcurrencyError);
+        }
+        self.insert_locked(&mut log, is)
+    }
+
+    fn id(&self) -> ID {
+        self.id
+    }
+}
+
+impl DurableStorage for SegmentStorage {}
+
+impl SegmentStorage {
+    fn open(dir: PathBuf, temporary: bool) -> Result<Self, StorageError> {
+        fs::create_dir_all(&dir)?;
+        let lock = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(dir.join(LOCK_NAME))?;
+        if lock.try_lock_exclusive().is_err() {
+            return Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                format!("segment storage {} is already open", dir.display()),
+            )
+            .into());
+        }
+        let mut numbers = vec![];
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let number = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse::<u64>().ok());
+            match (path.extension().and_then(|e| e.to_str()), number) {
+                (Some(TEMPORARY_EXTENSION), _) => fs::remove_file(&path)?,
+                (Some(SEGMENT_EXTENSION), Some(number)) => numbers.push(number),
+                _ => {}
+            }
+        }
+        numbers.sort_unstable();
+        let segments = numbers
+            .iter()
+            .map(|n| Segment::open(Segment::path(&dir, *n, SEGMENT_EXTENSION)).map(Arc::new))
+            .collect::<Result<Vec<_>, _>>()?;
+        let (log, logged) = Log::open(&dir.join(LOG_NAME))?;
+        let memtable_bytes = logged.iter().map(Vec::len).sum();
+        let storage = Self {
+            shared: Arc::new(Shared {
+                state: RwLock::new(State {
+                    memtable: logged.into_iter().collect(),
+                    memtable_bytes,
+                    segments,
+                }),
+                log: Mutex::new(log),
+                compaction: Mutex::new(()),
+                next_segment: AtomicU64::new(numbers.last().map_or(0, |n| n + 1)),
+                dir,
+            }),
+            id: ID::new(),
+            compactor: Mutex::new(None),
+            lock: Some(lock),
+            temporary,
+        };
+        storage.check_version()?;
+        Ok(storage)
+    }
+
+    /// Mark new storage with the current
+    /// [format version](serial::FORMAT_VERSION), and refuse to open
+    /// storage with any other
+    fn check_version(&self) -> Result<(), StorageError> {
+        let empty = {
+            let state = self.shared.state();
+            state.memtable.is_empty() && state.segments.is_empty()
+        };
+        let version = match self.range(range_slice(&serial::format_range()))?.next() {
+            Some(item) => item?.get(1).copied().unwrap_or(0),
+            None if empty => {
+                return self.insert(&[serial::format_marker().to_vec()]);
+            }
+            None => 0,
+        };
+        if version != serial::FORMAT_VERSION {
+            return Err(corrupt(format!(
+                "segment storage format version {} isn't {}",
+                version,
+                serial::FORMAT_VERSION
+            )));
+        }
+        Ok(())
+    }
+
+    fn insert_locked(&self, log: &mut Log, is: &[Item]) -> Result<(), StorageError> {
+        if is.is_empty() {
+            return Ok(());
+        }
+        log.append(is)?;
+        let full = {
+            let mut state = self.shared.state_mut();
+            for item in is {
+                if state.memtable.insert(item.to_owned()) {
+                    state.memtable_bytes += item.len();
+                }
+            }
+            state.memtable_bytes >= MEMTABLE_LIMIT
+        };
+        if full {
+            self.shared.flush(log)?;
+            self.compact_in_background();
+        }
+        Ok(())
+    }
+
+    /// Start compacting in the background if there are enough segments
+    /// and a compaction isn't already running
+    fn compact_in_background(&self) {
+        if self.shared.state().segments.len() < COMPACTION_THRESHOLD {
+            return;
+        }
+        let mut compactor = self
+            .compactor
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if compactor.as_ref().map_or(false, |c| !c.is_finished()) {
+            return;
+        }
+        let shared = self.shared.clone();
+        *compactor = Some(thread::spawn(move || {
+            // A failed compaction leaves the segments as they were, and
+            // is retried after the next flush
+            shared.compact().ok();
+        }));
+    }
+
+    /// Write every item which is only in the write-ahead log to a new
+    /// segment
+    pub fn flush(&self) -> Result<(), StorageError> {
+        let mut log = self.shared.log();
+        self.shared.flush(&mut log)
+    }
+
+    /// Merge every segment into one, waiting for any background
+    /// compaction to finish first
+    pub fn compact(&self) -> Result<(), StorageError> {
+        self.shared.compact()
+    }
+
+    /// The number of segments
+    pub fn segments(&self) -> usize {
+        self.shared.state().segments.len()
+    }
+
+    /// Create a connection to a temporary database. When the
+    /// [SegmentStorage] is dropped, the temporary database will be
+    /// removed from the disk. This is useful for tests.
+    pub fn connect_temp() -> Result<Self, StorageError> {
+        let mut path = temp_dir();
+        path.push(Uuid::new_v4().to_string());
+        path.set_extension("db");
+        Self::open(path, true)
+    }
+
+    /// Create a connection to a database in a directory, creating the
+    /// directory if it doesn't exist
+    pub fn connect(path: &str) -> Result<Self, StorageError> {
+        Self::open(PathBuf::from(path), false)
+    }
+}
+
+impl Drop for SegmentStorage {
+    fn drop(&mut self) {
+        let compactor = self
+            .compactor
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        if let Some(compactor) = compactor {
+            compactor.join().ok();
+        }
+        // Closing the file releases the lock
+        drop(self.lock.take());
+        if self.temporary {
+            fs::remove_dir_all(&self.shared.dir).ok();
+        }
+    }
+}
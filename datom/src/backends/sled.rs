@@ -2,16 +2,25 @@
 // SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
 // SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
 
-use std::{env::temp_dir, ops::Range};
+use std::{
+    collections::HashSet,
+    env::temp_dir,
+    ops::Range,
+    sync::{Mutex, PoisonError},
+};
 
-use sled::{transaction::ConflictableTransactionError, Config, Db};
+use sled::{transaction::ConflictableTransactionError, Batch, Config, Db};
 use uuid::Uuid;
 
 use crate::{
+    serial::{self, range_slice},
     storage::{DurableStorage, Item, ItemIterator, Storage},
-    StorageError, ID,
+    Index, StorageError, ID,
 };
 
+/// How many items an upgrade rewrites in each batch
+const UPGRADE_BATCH_SIZE: usize = 1 << 12;
+
 impl From<sled::Error> for StorageError {
     fn from(e: sled::Error) -> Self {
         Self::Miscellaneous(Box::new(e))
@@ -28,6 +37,9 @@ impl From<sled::transaction::TransactionError> for StorageError {
 pub struct SledStorage {
     db: Db,
     id: ID,
+    /// sled only lets one process open a database, so holding this
+    /// makes conditional writes atomic
+    write_lock: Mutex<()>,
 }
 
 impl Storage for SledStorage {
@@ -49,6 +61,17 @@ impl Storage for SledStorage {
         Ok(())
     }
 
+    fn insert_if_empty(&self, guard: Range<&[u8]>, is: &[Item]) -> Result<(), StorageError> {
+        let _lock = self
+            .write_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if self.db.range(guard).next().is_some() {
+            return Err(StorageError::ConcurrencyError);
+        }
+        self.insert(is)
+    }
+
     fn id(&self) -> ID {
         self.id
     }
@@ -57,6 +80,123 @@ impl Storage for SledStorage {
 impl DurableStorage for SledStorage {}
 
 impl SledStorage {
+    fn from_db(db: Db) -> Result<Self, sled::Error> {
+        Self::upgrade(&db)?;
+        Ok(Self {
+            db,
+            id: ID::new(),
+            write_lock: Mutex::new(()),
+        })
+    }
+
+    /// Bring a database up to the current
+    /// [format version](serial::FORMAT_VERSION) if it was written by
+    /// an older version
+    fn upgrade(db: &Db) -> Result<(), sled::Error> {
+        let version = match db.range(range_slice(&serial::format_range())).next() {
+            Some(item) => item?.0.get(1).copied().unwrap_or(0),
+            None if db.is_empty() => {
+                db.insert(serial::format_marker(), vec![])?;
+                return Ok(());
+            }
+            None => 0,
+        };
+        if version == serial::FORMAT_VERSION {
+            return Ok(());
+        }
+        if version > serial::FORMAT_VERSION {
+            return Err(sled::Error::Unsupported(format!(
+                "database format version {} is newer than {}",
+                version,
+                serial::FORMAT_VERSION
+            )));
+        }
+        if version < 2 {
+            Self::upgrade_to_v2(db, version)?;
+        }
+        Self::upgrade_from_v2(db)?;
+        db.flush()?;
+        Ok(())
+    }
+
+    /// Rewrite every item of a version 0 or 1 database in one batch,
+    /// since the new keys are spread across every index
+    fn upgrade_to_v2(db: &Db, version: u8) -> Result<(), sled::Error> {
+        let marker = serial::format_marker();
+        let malformed = |key: &[u8]| {
+            sled::Error::Unsupported(format!("malformed version {} item {:?}", version, key))
+        };
+        let mut removed = vec![];
+        let mut upgraded = vec![];
+        for item in db.iter() {
+            let (key, _) = item?;
+            if key.first() != Some(&marker[0]) {
+                upgraded.extend(
+                    match version {
+                        0 => serial::upgrade_v1(
+                            &serial::upgrade_v0(&key).ok_or_else(|| malformed(&key))?,
+                        ),
+                        _ => serial::upgrade_v1(&key),
+                    }
+                    .ok_or_else(|| malformed(&key))?,
+                );
+            }
+            removed.push(key);
+        }
+        let mut batch = Batch::default();
+        for key in removed {
+            batch.remove(key);
+        }
+        for key in upgraded {
+            batch.insert(key, vec![]);
+        }
+        batch.insert(&[marker[0], 2], vec![]);
+        db.apply_batch(batch)
+    }
+
+    /// Give booleans the boolean value type in two scans: one to find
+    /// the attributes with boolean values, and one rewriting the items
+    /// which give them the value type of references, a chunk at a
+    /// time. Rewritten items are left alone if they're seen again, so
+    /// an interrupted upgrade is finished the next time the database
+    /// is opened, as the marker is only updated at the end.
+    fn upgrade_from_v2(db: &Db) -> Result<(), sled::Error> {
+        let marker = serial::format_marker();
+        let malformed =
+            |key: &[u8]| sled::Error::Unsupported(format!("malformed version 2 item {:?}", key));
+        let mut boolean_attributes = HashSet::new();
+        for item in db.range(range_slice(&serial::index_range(Index::EAVT))) {
+            let (key, _) = item?;
+            boolean_attributes.extend(serial::boolean_attribute(&key));
+        }
+        let mut batch = Batch::default();
+        let mut batched = 0;
+        for item in db.iter() {
+            let (key, _) = item?;
+            if key.first() == Some(&marker[0]) {
+                continue;
+            }
+            let new_key =
+                serial::upgrade_v2(&key, &boolean_attributes).ok_or_else(|| malformed(&key))?;
+            if new_key != *key {
+                batch.remove(key);
+                batch.insert(new_key, vec![]);
+                batched += 1;
+            }
+            if batched == UPGRADE_BATCH_SIZE {
+                db.apply_batch(std::mem::take(&mut batch))?;
+                batched = 0;
+            }
+        }
+        db.apply_batch(batch)?;
+        let mut batch = Batch::default();
+        for item in db.range(range_slice(&serial::format_range())) {
+            batch.remove(item?.0);
+        }
+        batch.insert(&marker, vec![]);
+        db.apply_batch(batch)
+    }
+
     /// Create a connection to a temporary database. When the
     /// [SledStorage] is dropped, the temporary database will be
     /// removed from the disk. This is useful for tests.
@@ -66,13 +206,174 @@ impl SledStorage {
         path.set_extension("db");
         let cfg = Config::new().path(path).temporary(true);
         let db = cfg.open()?;
-        Ok(Self { db, id: ID::new() })
+        Self::from_db(db)
     }
 
-    /// Create a connection to a database.
+    /**
+    Create a connection to a database.
+
+    Databases written by older versions of this crate, whose index
+    keys didn't sort values in their natural order, which had no
+    transaction log, or which gave booleans the value type of
+    references, are upgraded to
+    the current [format version](serial::FORMAT_VERSION) when they're
+    opened. Databases written by newer versions fail to open.
+    */
     pub fn connect(uri: &str) -> Result<Self, sled::Error> {
         let cfg = Config::new().path(uri);
         let db = cfg.open()?;
-        Ok(Self { db, id: ID::new() })
+        Self::from_db(db)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+    use crate::{
+        builtin_idents, Connection, Datom, DatomType, EntityResult, LogEntry, QueryError,
+        TransactionRecord, Value,
+    };
+
+    fn serialize_v0(datom: &Datom) -> Vec<Item> {
+        let v = datom.value.bytes();
+        let mut value = (v.len() as u64).to_be_bytes().to_vec();
+        value.extend_from_slice(&v);
+        let entity = <[u8; 16]>::from(datom.entity).to_vec();
+        let attribute = <[u8; 16]>::from(datom.attribute).to_vec();
+        let mut suffix = datom.t.to_be_bytes().to_vec();
+        suffix.push(datom.datom_type.byte());
+        [
+            [&[0], &entity[..], &attribute, &value, &suffix].concat(),
+            [&[1], &attribute[..], &entity, &value, &suffix].concat(),
+            [&[3], &value[..], &attribute, &entity, &suffix].concat(),
+        ]
+        .into()
+    }
+
+    #[test]
+    fn upgrade_v0() -> Result<(), Box<dyn std::error::Error>> {
+        let mut path = temp_dir();
+        path.push(Uuid::new_v4().to_string());
+        let db = Config::new().path(path).temporary(true).open()?;
+        let entity = ID::new();
+        let datom = Datom {
+            entity,
+            attribute: builtin_idents::DOC,
+            value: "an entity from before the format was versioned".into(),
+            t: 1,
+            datom_type: DatomType::Addition,
+        };
+        let record = TransactionRecord {
+            t: 1,
+            timestamp: Utc.timestamp_millis_opt(1_650_000_000_000).unwrap(),
+        };
+        for item in serialize_v0(&datom)
+            .into_iter()
+            .chain([serial::serialize_tr(&record)])
+        {
+            db.insert(item, vec![])?;
+        }
+
+        let conn = Connection::new(SledStorage::from_db(db)?);
+        let db = conn.db()?;
+        assert_eq!(
+            db.entity(entity.into())?.get(builtin_idents::DOC.into())?,
+            EntityResult::Value(datom.value.clone())
+        );
+        assert_eq!(db.datoms_for_value(datom.value.clone())?.count(), 1);
+        let log = conn.log(..)?.collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(
+            log,
+            vec![LogEntry {
+                record,
+                data: vec![datom]
+            }]
+        );
+        let markers: Vec<Item> = conn
+            .storage
+            .range(range_slice(&serial::format_range()))?
+            .collect::<Result<_, _>>()?;
+        assert_eq!(markers, vec![serial::format_marker().to_vec()]);
+        Ok(())
+    }
+    #[test]
+    fn upgrade_v2() -> Result<(), Box<dyn std::error::Error>> {
+        let mut path = temp_dir();
+        path.push(Uuid::new_v4().to_string());
+        let db = Config::new().path(path).temporary(true).open()?;
+        let admin = ID::new();
+        let friend = ID::new();
+        let user = ID::new();
+        let datom = |entity: ID, attribute: ID, value: Value| Datom {
+            entity,
+            attribute,
+            value,
+            t: 1,
+            datom_type: DatomType::Addition,
+        };
+        let data = [
+            datom(admin, builtin_idents::IDENT, "user/admin".into()),
+            datom(
+                admin,
+                builtin_idents::VALUE_TYPE,
+                builtin_idents::TYPE_REF.into(),
+            ),
+            datom(friend, builtin_idents::IDENT, "user/friend".into()),
+            datom(
+                friend,
+                builtin_idents::VALUE_TYPE,
+                builtin_idents::TYPE_REF.into(),
+            ),
+            datom(user, admin, true.into()),
+            datom(user, friend, admin.into()),
+        ];
+        db.insert([254, 2], vec![])?;
+        for d in &data {
+            db.insert(serial::serialize_eavt(d), vec![])?;
+            db.insert(serial::serialize_aevt(d), vec![])?;
+            if let Value::ID(_) = d.value {
+                db.insert(serial::serialize_vaet(d), vec![])?;
+            }
+            db.insert(serial::serialize_log(d), vec![])?;
+        }
+        let record = TransactionRecord {
+            t: 1,
+            timestamp: Utc.timestamp_millis_opt(1_650_000_000_000).unwrap(),
+        };
+        db.insert(serial::serialize_tr(&record), vec![])?;
+
+        let conn = Connection::new(SledStorage::from_db(db)?);
+        let db = conn.db()?;
+        let typed = |value_type: ID| -> Result<Vec<ID>, QueryError> {
+            Ok(db
+                .datoms_for_value_attribute(value_type.into(), builtin_idents::VALUE_TYPE)?
+                .map(|d| d.map(|d| d.entity))
+                .collect::<Result<_, _>>()?)
+        };
+        assert_eq!(typed(builtin_idents::TYPE_BOOLEAN)?, vec![admin]);
+        assert_eq!(typed(builtin_idents::TYPE_REF)?, vec![friend]);
+        assert_eq!(
+            db.entity(user.into())?.get(admin.into())?,
+            EntityResult::Value(true.into())
+        );
+        let log = conn.log(..)?.collect::<Result<Vec<_>, _>>()?;
+        let mut upgraded = data.to_vec();
+        upgraded[1].value = builtin_idents::TYPE_BOOLEAN.into();
+        upgraded.sort_by_key(serial::serialize_log);
+        assert_eq!(
+            log,
+            vec![LogEntry {
+                record,
+                data: upgraded
+            }]
+        );
+        let markers: Vec<Item> = conn
+            .storage
+            .range(range_slice(&serial::format_range()))?
+            .collect::<Result<_, _>>()?;
+        assert_eq!(markers, vec![serial::format_marker().to_vec()]);
+        Ok(())
     }
 }
@@ -0,0 +1,292 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+use std::{
+    collections::VecDeque,
+    env::temp_dir,
+    fs, io,
+    ops::Range,
+    path::PathBuf,
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+use rusqlite::{params, Connection, TransactionBehavior};
+use uuid::Uuid;
+
+use crate::{
+    serial::{self, range_slice},
+    storage::{DurableStorage, Item, ItemIterator, Storage},
+    StorageError, ID,
+};
+
+/// How many items a range read fetches at a time
+const BATCH_SIZE: usize = 256;
+
+impl From<rusqlite::Error> for StorageError {
+    fn from(e: rusqlite::Error) -> Self {
+        Self::Miscellaneous(Box::new(e))
+    }
+}
+
+/// A temporary database file, removed when it's dropped
+struct TempFile(PathBuf);
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        fs::remove_file(&self.0).ok();
+    }
+}
+
+/**
+A storage backend backed by a [SQLite](rusqlite) database
+
+Every item is a BLOB primary key of a single `items` table, so the
+database can be backed up and inspected with the usual SQLite tools.
+
+```
+use datom::{backends::SqliteStorage, Connection, Transaction, ID};
+let conn = Connection::new(SqliteStorage::connect_temp()?);
+let user = ID::new();
+let mut tx = Transaction::new();
+tx.add(user.into(), "db/doc".into(), "in sqlite".into());
+conn.transact(tx)?;
+conn.db()?.entity(user.into())?.get("db/doc".into())?;
+# Ok::<(), Box<dyn std::error::Error>>(())
+```
+*/
+pub struct SqliteStorage {
+    conn: Mutex<Connection>,
+    id: ID,
+    /// Declared after the connection, so the connection is closed
+    /// before the file is removed
+    _temp: Option<TempFile>,
+}
+
+/// An iterator over a range of a [SqliteStorage], which fetches
+/// batches of items from either end without holding the connection
+/// between them
+struct SqliteIter<'s> {
+    storage: &'s SqliteStorage,
+    start: Item,
+    end: Item,
+    /// The greatest item fetched from the front
+    last_front: Option<Item>,
+    /// The least item fetched from the back
+    last_back: Option<Item>,
+    front: VecDeque<Item>,
+    /// Fetched from the back, greatest first
+    back: VecDeque<Item>,
+    exhausted: bool,
+}
+
+impl<'s> SqliteIter<'s> {
+    /// Fetch the next batch of items between the last items fetched
+    /// from each end
+    fn fetch(&mut self, from_back: bool) -> Result<VecDeque<Item>, StorageError> {
+        let lower = self.last_front.as_ref().unwrap_or(&self.start);
+        let upper = self.last_back.as_ref().unwrap_or(&self.end);
+        let conn = self.storage.conn();
+        let mut stmt = conn.prepare_cached(if from_back {
+            "SELECT item FROM items WHERE item BETWEEN ?1 AND ?2 ORDER BY item DESC LIMIT ?3"
+        } else {
+            "SELECT item FROM items WHERE item BETWEEN ?1 AND ?2 ORDER BY item ASC LIMIT ?3"
+        })?;
+        let fetched = stmt
+            .query_map(params![lower, upper, BATCH_SIZE as i64], |row| row.get(0))?
+            .collect::<Result<Vec<Item>, _>>()?;
+        drop(stmt);
+        drop(conn);
+        // BETWEEN includes both bounds, but the end of the range and
+        // the items already fetched are excluded
+        let items: VecDeque<Item> = fetched
+            .into_iter()
+            .filter(|item| item != upper && Some(item) != self.last_front.as_ref())
+            .collect();
+        if from_back {
+            if let Some(last) = items.back() {
+                self.last_back = Some(last.to_owned());
+            }
+        } else if let Some(last) = items.back() {
+            self.last_front = Some(last.to_owned());
+        }
+        Ok(items)
+    }
+
+    fn fetch_or_stop(&mut self, from_back: bool) -> Option<Result<(), StorageError>> {
+        if self.exhausted {
+            return None;
+        }
+        match self.fetch(from_back) {
+            Ok(items) if items.is_empty() => {
+                self.exhausted = true;
+                None
+            }
+            Ok(items) => {
+                if from_back {
+                    self.back = items;
+                } else {
+                    self.front = items;
+                }
+                Some(Ok(()))
+            }
+            Err(e) => {
+                // Stop at the first error
+                self.exhausted = true;
+                self.front.clear();
+                self.back.clear();
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl<'s> Iterator for SqliteIter<'s> {
+    type Item = Result<Item, StorageError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(item) = self.front.pop_front() {
+            return Some(Ok(item));
+        }
+        match self.fetch_or_stop(false) {
+            Some(Ok(())) => self.front.pop_front().map(Ok),
+            Some(Err(e)) => Some(Err(e)),
+            None => self.back.pop_back().map(Ok),
+        }
+    }
+}
+
+impl<'s> DoubleEndedIterator for SqliteIter<'s> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if let Some(item) = self.back.pop_front() {
+            return Some(Ok(item));
+        }
+        match self.fetch_or_stop(true) {
+            Some(Ok(())) => self.back.pop_front().map(Ok),
+            Some(Err(e)) => Some(Err(e)),
+            None => self.front.pop_back().map(Ok),
+        }
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn range(&self, r: Range<&[u8]>) -> Result<ItemIterator<'_>, StorageError> {
+        Ok(Box::new(SqliteIter {
+            storage: self,
+            start: r.start.to_vec(),
+            end: r.end.to_vec(),
+            last_front: None,
+            last_back: None,
+            front: VecDeque::new(),
+            back: VecDeque::new(),
+            exhausted: r.start >= r.end,
+        }))
+    }
+
+    fn insert(&self, is: &[Item]) -> Result<(), StorageError> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        Self::insert_into(&tx, is)?;
+        tx.commit()?;
+        drop(conn);
+        Ok(())
+    }
+
+    fn insert_if_empty(&self, guard: Range<&[u8]>, is: &[Item]) -> Result<(), StorageError> {
+        let mut conn = self.conn();
+        // Take the write lock up front, so other processes can't write
+        // between the check and the insert
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let occupied = tx
+            .prepare_cached(
+                "SELECT 1 FROM items WHERE item BETWEEN ?1 AND ?2 AND item <> ?2 LIMIT 1",
+            )?
+            .exists(params![guard.start, guard.end])?;
+        if occupied {
+            return Err(StorageError::ConcurrencyError);
+        }
+        Self::insert_into(&tx, is)?;
+        tx.commit()?;
+        drop(conn);
+        Ok(())
+    }
+
+    fn id(&self) -> ID {
+        self.id
+    }
+}
+
+impl DurableStorage for SqliteStorage {}
+
+impl SqliteStorage {
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn insert_into(conn: &Connection, is: &[Item]) -> Result<(), StorageError> {
+        let mut stmt = conn.prepare_cached("INSERT OR IGNORE INTO items (item) VALUES (?1)")?;
+        for i in is {
+            stmt.execute(params![i])?;
+        }
+        Ok(())
+    }
+
+    fn from_conn(conn: Connection, temp: Option<TempFile>) -> Result<Self, StorageError> {
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS items (item BLOB PRIMARY KEY NOT NULL) WITHOUT ROWID",
+            [],
+        )?;
+        let storage = Self {
+            conn: Mutex::new(conn),
+            id: ID::new(),
+            _temp: temp,
+        };
+        storage.check_version()?;
+        Ok(storage)
+    }
+
+    /// Mark a new database with the current
+    /// [format version](serial::FORMAT_VERSION), and refuse to open
+    /// a database with any other
+    fn check_version(&self) -> Result<(), StorageError> {
+        let empty = !self
+            .conn()
+            .prepare_cached("SELECT 1 FROM items LIMIT 1")?
+            .exists([])?;
+        let version = match self.range(range_slice(&serial::format_range()))?.next() {
+            Some(item) => item?.get(1).copied().unwrap_or(0),
+            None if empty => return self.insert(&[serial::format_marker().to_vec()]),
+            None => 0,
+        };
+        if version != serial::FORMAT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "database format version {} isn't {}",
+                    version,
+                    serial::FORMAT_VERSION
+                ),
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Create a connection to a temporary database. When the
+    /// [SqliteStorage] is dropped, the temporary database will be
+    /// removed from the disk. This is useful for tests.
+    pub fn connect_temp() -> Result<Self, StorageError> {
+        let mut path = temp_dir();
+        path.push(Uuid::new_v4().to_string());
+        path.set_extension("sqlite3");
+        let conn = Connection::open(&path)?;
+        Self::from_conn(conn, Some(TempFile(path)))
+    }
+
+    /// Create a connection to a database, creating the file if it
+    /// doesn't exist
+    pub fn connect(path: &str) -> Result<Self, StorageError> {
+        let conn = Connection::open(path)?;
+        Self::from_conn(conn, None)
+    }
+}
@@ -2,22 +2,85 @@
 // SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
 // SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
 
-use std::{cmp::Ordering, ops::Range};
+use std::{
+    cmp::Ordering,
+    collections::BTreeMap,
+    ops::{Bound, Range},
+    sync::{Mutex, MutexGuard, PoisonError},
+};
 
 use crate::{
-    merge_iters::MergeIters,
+    merge_iters::{MergeIters, OriginIter},
     storage::{DurableStorage, Item, ItemIterator, Storage},
     StorageError, ID,
 };
 
+/// Which items a [TieredStorage] copies into A after reading them
+/// from B
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Promotion {
+    /// Never copy items into A
+    Never,
+    /// Copy every item read from B but not found in A
+    All,
+    /// Copy at most this many of the items read from B but not found
+    /// in A for each read, so large scans don't flood A
+    UpTo(usize),
+}
+
+/// Ranges of keys whose items in B have all been copied into A, as a
+/// map from each range's start to its end. The ranges never overlap.
+#[derive(Default)]
+struct Covered(BTreeMap<Item, Item>);
+
+impl Covered {
+    fn contains(&self, r: &Range<&[u8]>) -> bool {
+        self.0
+            .range::<[u8], _>((Bound::Unbounded, Bound::Included(r.start)))
+            .next_back()
+            .map_or(false, |(_, end)| r.end <= end.as_slice())
+    }
+
+    /// Mark a range as covered, merging it with the ranges it touches
+    fn cover(&mut self, mut start: Item, mut end: Item) {
+        if let Some((s, e)) = self.0.range(..=start.clone()).next_back() {
+            if *e >= start {
+                start = s.to_owned();
+                end = end.max(e.to_owned());
+            }
+        }
+        let touched: Vec<Item> = self
+            .0
+            .range(start.clone()..=end.clone())
+            .map(|(s, _)| s.to_owned())
+            .collect();
+        for s in touched {
+            if let Some(e) = self.0.remove(&s) {
+                end = end.max(e);
+            }
+        }
+        self.0.insert(start, end);
+    }
+}
+
 /// A storage backend backed by two other storage backends
+///
 /// Inserts are sent to both backends.
-/// Reads come from both backends, and any items which are read from B
-/// but not found in A will persisted to A in a future update.
+/// Reads come from both backends, and by default any items which are
+/// read from B but not found in A are copied to A, in one batch once
+/// the read's iterator is dropped. The [Promotion] policy controls
+/// which items are copied. Once a read has been run to the end and
+/// every item it found only in B has been copied, later reads within
+/// its range are served from A alone, so a fast storage in front of a
+/// durable one acts as a warm cache. Writes made to B by other means
+/// aren't noticed in those ranges until a conditional insert conflicts
+/// with them.
 pub struct TieredStorage<A: Storage, B: Storage> {
     a: A,
     b: B,
     id: ID,
+    promotion: Promotion,
+    covered: Mutex<Covered>,
 }
 
 impl<A: Storage, B: Storage> TieredStorage<A, B> {
@@ -27,6 +90,89 @@ impl<A: Storage, B: Storage> TieredStorage<A, B> {
             a,
             b,
             id: ID::new(),
+            promotion: Promotion::All,
+            covered: Mutex::default(),
+        }
+    }
+
+    fn covered(&self) -> MutexGuard<'_, Covered> {
+        self.covered.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Set which items read from B are copied into A
+    #[allow(clippy::missing_const_for_fn)]
+    pub fn promotion(mut self, promotion: Promotion) -> Self {
+        self.promotion = promotion;
+        self
+    }
+}
+
+/**
+An iterator over a [TieredStorage] read, which collects the items
+only found in B and inserts them into A when it's dropped. If the read
+was run to the end and every one of them was inserted, its range is
+marked as covered.
+
+Failing to insert them is ignored, since A only holds copies.
+*/
+struct PromotingIter<'s, A: Storage, B: Storage> {
+    merged: MergeIters<Result<Item, StorageError>, ItemIterator<'s>, ItemIterator<'s>>,
+    storage: &'s TieredStorage<A, B>,
+    range: (Item, Item),
+    limit: usize,
+    promoted: Vec<Item>,
+    /// Whether every item read so far was read without error and
+    /// promoted if it was only in B
+    complete: bool,
+    exhausted: bool,
+}
+
+impl<'s, A: Storage, B: Storage> PromotingIter<'s, A, B> {
+    fn promote(
+        &mut self,
+        next: Option<(Result<Item, StorageError>, OriginIter)>,
+    ) -> Option<Result<Item, StorageError>> {
+        let (item, origin) = match next {
+            Some(next) => next,
+            None => {
+                self.exhausted = true;
+                return None;
+            }
+        };
+        match (&item, origin) {
+            (Err(_), _) => self.complete = false,
+            (Ok(item), OriginIter::B) if self.promoted.len() < self.limit => {
+                self.promoted.push(item.to_owned());
+            }
+            (Ok(_), OriginIter::B) => self.complete = false,
+            (Ok(_), _) => {}
+        }
+        Some(item)
+    }
+}
+
+impl<'s, A: Storage, B: Storage> Iterator for PromotingIter<'s, A, B> {
+    type Item = Result<Item, StorageError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.merged.next();
+        self.promote(next)
+    }
+}
+
+impl<'s, A: Storage, B: Storage> DoubleEndedIterator for PromotingIter<'s, A, B> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let next = self.merged.next_back();
+        self.promote(next)
+    }
+}
+
+impl<'s, A: Storage, B: Storage> Drop for PromotingIter<'s, A, B> {
+    fn drop(&mut self) {
+        let inserted = self.promoted.is_empty() || self.storage.a.insert(&self.promoted).is_ok();
+        if inserted && self.complete && self.exhausted {
+            let (start, end) = std::mem::take(&mut self.range);
+            self.storage.covered().cover(start, end);
         }
     }
 }
@@ -66,8 +212,24 @@ impl Ord for StorageError {
 
 impl<A: Storage, B: Storage> Storage for TieredStorage<A, B> {
     fn range(&self, r: Range<&[u8]>) -> Result<ItemIterator<'_>, StorageError> {
+        if self.covered().contains(&r) {
+            return self.a.range(r);
+        }
         let merged = MergeIters::new(self.a.range(r.clone())?, self.b.range(r.clone())?);
-        Ok(Box::new(merged.map(|x| x.0)))
+        let limit = match self.promotion {
+            Promotion::Never => return Ok(Box::new(merged.map(|x| x.0))),
+            Promotion::All => usize::MAX,
+            Promotion::UpTo(limit) => limit,
+        };
+        Ok(Box::new(PromotingIter {
+            merged,
+            storage: self,
+            range: (r.start.to_vec(), r.end.to_vec()),
+            limit,
+            promoted: vec![],
+            complete: true,
+            exhausted: false,
+        }))
     }
 
     fn insert(&self, is: &[Item]) -> Result<(), StorageError> {
@@ -76,6 +238,24 @@ impl<A: Storage, B: Storage> Storage for TieredStorage<A, B> {
         Ok(())
     }
 
+    /// The condition is checked against B, which has every item. If
+    /// it fails, B was written by other means, so no range is served
+    /// from A alone until it's read again. Once B has committed the
+    /// items, failing to copy them into A doesn't fail the insert, as
+    /// retrying it would conflict with the items already in B.
+    fn insert_if_empty(&self, guard: Range<&[u8]>, is: &[Item]) -> Result<(), StorageError> {
+        if let Err(e) = self.b.insert_if_empty(guard, is) {
+            if e == StorageError::ConcurrencyError {
+                *self.covered() = Covered::default();
+            }
+            return Err(e);
+        }
+        if self.a.insert(is).is_err() {
+            *self.covered() = Covered::default();
+        }
+        Ok(())
+    }
+
     fn id(&self) -> ID {
         self.id
     }
@@ -18,8 +18,8 @@ pub const IDENT: TID = TID::from_u128(265682209113858765770461024079827500234u12
 /// entity
 pub const CARDINALITY: TID = TID::from_u128(110064945635332807503383834157761461043u128);
 
-/// The intended type for an attribute. Note that this is not
-/// (currently) strictly checked.
+/// The type of an attribute's values, which is checked when
+/// transacting
 pub const VALUE_TYPE: TID = TID::from_u128(276059213908560386420175049892299151374u128);
 
 /// A documentation string for an entity
@@ -28,20 +28,32 @@ pub const DOC: TID = TID::from_u128(303289866496710859530474533904741988829u128)
 /// Whether there can only be one entity per value for this attribute
 pub const UNIQUE: TID = TID::from_u128(307615836394596470679724073561969695989);
 
+/// Whether the values of this [UNIQUE] attribute identify their
+/// entities, so that asserting an existing value for a temporary ID
+/// upserts into the entity which already holds it
+pub const UNIQUE_IDENTITY: TID = TID::from_u128(169658498083983698312919209072791315743u128);
+
+/// Whether this attribute's values are kept in the
+/// [AVET index](crate::Index::AVET), so that they can be scanned in
+/// order. [UNIQUE] attributes are always indexed.
+pub const INDEX: TID = TID::from_u128(190134064687856713507407252178118939850u128);
+
 /// Whether the entity referred to in this [TYPE_REF] attribute is a
 /// sub-component. When you retract an entity, all sub-components will
 /// also be retracted.
 pub const IS_COMPONENT: TID = TID::from_u128(308724514559417715856375983930347810391u128);
 
+/// When a transaction was committed, in milliseconds since the Unix
+/// epoch. Every transaction entity has this attribute.
+pub const TX_INSTANT: TID = TID::from_u128(132074560804799629937439672430125266225u128);
+
 /// A value for the [CARDINALITY](self::CARDINALITY) attribute
 pub const CARDINALITY_ONE: TID = TID::from_u128(143444949937465711736574828873158396909u128);
 
 /// A value for the [CARDINALITY](self::CARDINALITY) attribute
-
 pub const CARDINALITY_MANY: TID = TID::from_u128(11338831660433813835424721536043447369u128);
 
 /// A value for the [VALUE_TYPE](self::VALUE_TYPE) attribute
-
 pub const TYPE_STRING: TID = TID::from_u128(301439516182801820546961599694687577507u128);
 
 /// A value for the [VALUE_TYPE](self::VALUE_TYPE) attribute
@@ -57,7 +69,7 @@ pub const TYPE_ID: TID = TID::from_u128(339681506578292470250558610134765439055u
 pub const TYPE_REF: TID = TID::from_u128(149893903729185565330222631892178876560u128);
 
 /// A value for the [VALUE_TYPE](self::VALUE_TYPE) attribute
-pub const TYPE_BOOLEAN: TID = TID::from_u128(149893903729185565330222631892178876560u128);
+pub const TYPE_BOOLEAN: TID = TID::from_u128(85987264280680422115116930010228123493u128);
 
 /// The data behind a built-in entity
 pub type BuiltinEntity = HashMap<TID, Value>;
@@ -84,6 +96,7 @@ pub static BUILTIN_ENTITIES: Lazy<BuiltinEntities> = Lazy::new(|| {
         entity.insert(ID, IDENT.into());
         entity.insert(IDENT, Value::from("db/ident"));
         entity.insert(UNIQUE, Value::from(true));
+        entity.insert(UNIQUE_IDENTITY, Value::from(true));
         entity.insert(VALUE_TYPE, Value::from(TYPE_STRING));
         entity.insert(CARDINALITY, Value::from(CARDINALITY_ONE));
         entity
@@ -120,6 +133,22 @@ pub static BUILTIN_ENTITIES: Lazy<BuiltinEntities> = Lazy::new(|| {
         entity.insert(CARDINALITY, Value::from(CARDINALITY_ONE));
         entity
     });
+    entities.insert(UNIQUE_IDENTITY, {
+        let mut entity = BuiltinEntity::new();
+        entity.insert(ID, UNIQUE_IDENTITY.into());
+        entity.insert(IDENT, Value::from("db/unique-identity"));
+        entity.insert(VALUE_TYPE, Value::from(TYPE_BOOLEAN));
+        entity.insert(CARDINALITY, Value::from(CARDINALITY_ONE));
+        entity
+    });
+    entities.insert(INDEX, {
+        let mut entity = BuiltinEntity::new();
+        entity.insert(ID, INDEX.into());
+        entity.insert(IDENT, Value::from("db/index"));
+        entity.insert(VALUE_TYPE, Value::from(TYPE_BOOLEAN));
+        entity.insert(CARDINALITY, Value::from(CARDINALITY_ONE));
+        entity
+    });
     entities.insert(IS_COMPONENT, {
         let mut entity = BuiltinEntity::new();
         entity.insert(ID, IS_COMPONENT.into());
@@ -128,6 +157,14 @@ pub static BUILTIN_ENTITIES: Lazy<BuiltinEntities> = Lazy::new(|| {
         entity.insert(CARDINALITY, Value::from(CARDINALITY_ONE));
         entity
     });
+    entities.insert(TX_INSTANT, {
+        let mut entity = BuiltinEntity::new();
+        entity.insert(ID, TX_INSTANT.into());
+        entity.insert(IDENT, Value::from("db/tx-instant"));
+        entity.insert(VALUE_TYPE, Value::from(TYPE_INTEGER));
+        entity.insert(CARDINALITY, Value::from(CARDINALITY_ONE));
+        entity
+    });
     entities.insert(CARDINALITY_ONE, {
         let mut entity = BuiltinEntity::new();
         entity.insert(ID, CARDINALITY_ONE.into());
@@ -205,13 +242,17 @@ mod tests {
             CARDINALITY_ONE,
             DOC,
             IDENT,
+            INDEX,
             IS_COMPONENT,
+            TYPE_BOOLEAN,
             TYPE_DECIMAL,
             TYPE_ID,
             TYPE_INTEGER,
             TYPE_REF,
             TYPE_STRING,
+            TX_INSTANT,
             UNIQUE,
+            UNIQUE_IDENTITY,
             VALUE_TYPE,
         ];
         for id in ids {
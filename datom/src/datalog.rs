@@ -0,0 +1,24 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+use std::collections::HashMap;
+
+use edn_rs::Edn;
+
+use crate::{QueryError, Value};
+
+/// The values bound to each variable in a row of a query's working
+/// relation
+pub type Bindings = HashMap<String, Value>;
+
+/// Parse a `?variable` symbol
+pub fn parse_variable(edn: Edn) -> Result<String, QueryError> {
+    match edn {
+        Edn::Symbol(s) if s.starts_with('?') => Ok(s),
+        other => Err(QueryError::InvalidQuery(format!(
+            "`{}` is not a variable",
+            other
+        ))),
+    }
+}
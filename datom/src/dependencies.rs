@@ -0,0 +1,27 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+use crate::{Datom, ID};
+
+/// The entity-attribute pairs a read depends on, where [None] stands
+/// for any entity or attribute
+#[derive(Clone, Debug, Default)]
+pub struct Dependencies(Vec<(Option<ID>, Option<ID>)>);
+
+impl Dependencies {
+    /// Depend on datoms with the given entity and attribute
+    pub fn add(&mut self, entity: Option<ID>, attribute: Option<ID>) {
+        self.0.push((entity, attribute));
+    }
+
+    /// Whether any of a transaction's datoms could change the read
+    pub fn touched_by(&self, data: &[Datom]) -> bool {
+        data.iter().any(|datom| {
+            self.0.iter().any(|(entity, attribute)| {
+                entity.map_or(true, |e| e == datom.entity)
+                    && attribute.map_or(true, |a| a == datom.attribute)
+            })
+        })
+    }
+}
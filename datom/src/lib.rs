@@ -73,12 +73,22 @@ pub mod builtin_idents;
 
 mod merge_iters;
 
+mod datalog;
+
+mod schema_cache;
+
+mod dependencies;
+
 /// API for storage backends
 pub mod storage;
 
 /// Storage backends
 pub mod backends;
 
+/// Stores of immutable blobs, which
+/// [ObjectStorage](backends::ObjectStorage) keeps its segments in
+pub mod object_store;
+
 /// Get the version of this datom build
 pub const fn version() -> &'static str {
     env!("CARGO_PKG_VERSION")
@@ -1,9 +1,9 @@
-use std::mem;
-
 // SPDX-FileCopyrightText: 2022 Lutris, Inc
 // SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
 // SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
 
+use std::cmp::Ordering;
+
 pub enum OriginIter {
     A,
     B,
@@ -22,22 +22,22 @@ impl<T: Ord, A: Iterator<Item = T>, B: Iterator<Item = T>> Iterator for MergeIte
     type Item = (T, OriginIter);
 
     fn next(&mut self) -> Option<Self::Item> {
-        let a = if let Some(a_val) = mem::replace(&mut self.a_front, None) {
+        let a = if let Some(a_val) = self.a_front.take() {
             a_val
         } else {
             self.a.next()
         };
-        let b = if let Some(b_val) = mem::replace(&mut self.b_front, None) {
+        let b = if let Some(b_val) = self.b_front.take() {
             b_val
         } else {
             self.b.next()
         };
         let (res, a, b) = if let Some(a) = a {
             if let Some(b) = b {
-                if a < b {
-                    (Some((a, OriginIter::A)), self.a.next(), Some(b))
-                } else {
-                    (Some((b, OriginIter::B)), Some(a), self.b.next())
+                match a.cmp(&b) {
+                    Ordering::Less => (Some((a, OriginIter::A)), self.a.next(), Some(b)),
+                    Ordering::Equal => (Some((a, OriginIter::A)), self.a.next(), self.b.next()),
+                    Ordering::Greater => (Some((b, OriginIter::B)), Some(a), self.b.next()),
                 }
             } else {
                 (Some((a, OriginIter::A)), self.a.next(), None)
@@ -60,22 +60,26 @@ impl<
     > DoubleEndedIterator for MergeIters<T, A, B>
 {
     fn next_back(&mut self) -> Option<Self::Item> {
-        let a = if let Some(a_val) = mem::replace(&mut self.a_back, None) {
+        let a = if let Some(a_val) = self.a_back.take() {
             a_val
         } else {
             self.a.next_back()
         };
-        let b = if let Some(b_val) = mem::replace(&mut self.b_back, None) {
+        let b = if let Some(b_val) = self.b_back.take() {
             b_val
         } else {
             self.b.next_back()
         };
         let (res, a, b) = if let Some(a) = a {
             if let Some(b) = b {
-                if a > b {
-                    (Some((a, OriginIter::A)), self.a.next_back(), Some(b))
-                } else {
-                    (Some((b, OriginIter::B)), Some(a), self.b.next_back())
+                match a.cmp(&b) {
+                    Ordering::Greater => (Some((a, OriginIter::A)), self.a.next_back(), Some(b)),
+                    Ordering::Equal => (
+                        Some((a, OriginIter::A)),
+                        self.a.next_back(),
+                        self.b.next_back(),
+                    ),
+                    Ordering::Less => (Some((b, OriginIter::B)), Some(a), self.b.next_back()),
                 }
             } else {
                 (Some((a, OriginIter::A)), self.a.next_back(), None)
@@ -92,7 +96,8 @@ impl<
 }
 
 impl<T: Ord, A: Iterator<Item = T>, B: Iterator<Item = T>> MergeIters<T, A, B> {
-    /// Merge n sorted iterators
+    /// Merge two sorted iterators. Items which are in both are only
+    /// yielded once, from A.
     pub const fn new(a: A, b: B) -> Self {
         Self {
             a,
@@ -126,4 +131,16 @@ mod tests {
         let results: Vec<u64> = merged.rev().map(|x| x.0).collect();
         assert_eq!(vec![53326, 2754, 431, 76, 62, 53, 1], results);
     }
+
+    #[test]
+    fn shared_items_test() {
+        let first: [u64; 4] = [1, 53, 76, 431];
+        let second: [u64; 3] = [53, 62, 431];
+        let merged = MergeIters::new(first.iter().cloned(), second.iter().cloned());
+        let results: Vec<u64> = merged.map(|x| x.0).collect();
+        assert_eq!(vec![1, 53, 62, 76, 431], results);
+        let merged = MergeIters::new(first.iter().cloned(), second.iter().cloned());
+        let results: Vec<u64> = merged.rev().map(|x| x.0).collect();
+        assert_eq!(vec![431, 76, 62, 53, 1], results);
+    }
 }
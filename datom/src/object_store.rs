@@ -0,0 +1,180 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+use std::{
+    env::temp_dir,
+    fs::{self, File},
+    io::{self, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use uuid::Uuid;
+
+use crate::StorageError;
+
+/// A store of blobs by key, like an S3 bucket
+pub trait ObjectStore: Send + Sync {
+    /// Get an object's contents, or [None] if there's no object with
+    /// that key
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError>;
+
+    /// Write an object, replacing any object with the same key
+    fn put(&self, key: &str, value: &[u8]) -> Result<(), StorageError>;
+
+    /**
+    Write an object, but only if its current contents are `expected`,
+    or if there's no object with that key and `expected` is [None].
+    Returns whether the object was written.
+
+    The default implementation checks and then writes, so another
+    writer could write in between. Stores which can be written to
+    concurrently should make this atomic.
+    */
+    fn compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<&[u8]>,
+        value: &[u8],
+    ) -> Result<bool, StorageError> {
+        if self.get(key)?.as_deref() != expected {
+            return Ok(false);
+        }
+        self.put(key, value)?;
+        Ok(true)
+    }
+}
+
+impl<O: ObjectStore + ?Sized> ObjectStore for Box<O> {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        (**self).get(key)
+    }
+
+    fn put(&self, key: &str, value: &[u8]) -> Result<(), StorageError> {
+        (**self).put(key, value)
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<&[u8]>,
+        value: &[u8],
+    ) -> Result<bool, StorageError> {
+        (**self).compare_and_swap(key, expected, value)
+    }
+}
+
+impl<O: ObjectStore + ?Sized> ObjectStore for Arc<O> {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        (**self).get(key)
+    }
+
+    fn put(&self, key: &str, value: &[u8]) -> Result<(), StorageError> {
+        (**self).put(key, value)
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<&[u8]>,
+        value: &[u8],
+    ) -> Result<bool, StorageError> {
+        (**self).compare_and_swap(key, expected, value)
+    }
+}
+
+/**
+An [ObjectStore] which keeps each object in a file in a directory,
+standing in for a cloud object store
+
+Keys are paths relative to the directory. Objects are written to a
+temporary file and renamed into place, so readers never see part of
+an object. [ObjectStore::compare_and_swap] is only atomic between
+users of the same [LocalObjectStore].
+*/
+pub struct LocalObjectStore {
+    dir: PathBuf,
+    write_lock: Mutex<()>,
+    temporary: bool,
+}
+
+impl LocalObjectStore {
+    /// Create a store in a directory, creating the directory if it
+    /// doesn't exist
+    pub fn new(path: &str) -> Result<Self, StorageError> {
+        fs::create_dir_all(path)?;
+        Ok(Self {
+            dir: PathBuf::from(path),
+            write_lock: Mutex::new(()),
+            temporary: false,
+        })
+    }
+
+    /// Create a store in a temporary directory. When the
+    /// [LocalObjectStore] is dropped, the directory will be removed
+    /// from the disk. This is useful for tests.
+    pub fn temp() -> Result<Self, StorageError> {
+        let mut dir = temp_dir();
+        dir.push(Uuid::new_v4().to_string());
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            write_lock: Mutex::new(()),
+            temporary: true,
+        })
+    }
+
+    fn write(&self, key: &str, value: &[u8]) -> Result<(), StorageError> {
+        let path = self.dir.join(key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut temporary = path.clone();
+        temporary.set_file_name(format!(".{}.tmp", Uuid::new_v4()));
+        let mut file = File::create(&temporary)?;
+        file.write_all(value)?;
+        file.sync_all()?;
+        fs::rename(&temporary, &path)?;
+        Ok(())
+    }
+}
+
+impl ObjectStore for LocalObjectStore {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        match fs::read(self.dir.join(key)) {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn put(&self, key: &str, value: &[u8]) -> Result<(), StorageError> {
+        self.write(key, value)
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<&[u8]>,
+        value: &[u8],
+    ) -> Result<bool, StorageError> {
+        let _lock = self
+            .write_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if self.get(key)?.as_deref() != expected {
+            return Ok(false);
+        }
+        self.write(key, value)?;
+        Ok(true)
+    }
+}
+
+impl Drop for LocalObjectStore {
+    fn drop(&mut self) {
+        if self.temporary {
+            fs::remove_dir_all(&self.dir).ok();
+        }
+    }
+}
@@ -0,0 +1,25 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+use std::collections::HashMap;
+
+use crate::{storage::Storage, AttributeSchema, Database, QueryError, ID};
+
+/// Attribute schemas which have already been looked up in a
+/// [Database]
+pub type SchemaCache = HashMap<ID, AttributeSchema>;
+
+/// Look up an attribute's schema, consulting the cache first
+pub fn schema<S: Storage>(
+    db: &Database<'_, S>,
+    attribute: ID,
+    cache: &mut SchemaCache,
+) -> Result<AttributeSchema, QueryError> {
+    if let Some(schema) = cache.get(&attribute) {
+        return Ok(schema.to_owned());
+    }
+    let schema = db.attribute_schema(attribute.into())?;
+    cache.insert(attribute, schema.clone());
+    Ok(schema)
+}
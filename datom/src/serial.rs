@@ -2,11 +2,15 @@
 // SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
 // SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
 
-use std::{convert::TryInto, ops::Range};
+use std::{
+    collections::HashSet,
+    convert::TryInto,
+    ops::{Bound, Range},
+};
 
 use chrono::{TimeZone, Utc};
 
-use crate::{Datom, DatomType, Index, TransactionRecord, Value, ID};
+use crate::{builtin_idents, Datom, DatomType, Index, TransactionRecord, Value, ID};
 
 const fn u64_byte_count() -> usize {
     0u64.to_be_bytes().len()
@@ -16,12 +20,24 @@ const fn i64_byte_count() -> usize {
     0i64.to_be_bytes().len()
 }
 
+/**
+The version of the key format written by this module. Storage
+written before the format was versioned has no marker and is version
+0, where values were prefixed with their length instead of using
+[Value::sortable_bytes]. Version 1 had no transaction log. Version 2
+gave [TYPE_BOOLEAN](builtin_idents::TYPE_BOOLEAN) the same ID as
+[TYPE_REF](builtin_idents::TYPE_REF).
+*/
+pub const FORMAT_VERSION: u8 = 3;
+
+/// The prefix byte of the transaction log's keys
+const LOG_PREFIX: u8 = 253;
+
+/// The prefix byte of the key recording a storage's format version
+const FORMAT_PREFIX: u8 = 254;
+
 fn serialize_v(v: &Value) -> Vec<u8> {
-    let mut v_bytes = v.bytes();
-    let byte_count = v_bytes.len() as u64;
-    let mut vec = byte_count.to_be_bytes().to_vec();
-    vec.append(&mut v_bytes);
-    vec
+    v.sortable_bytes()
 }
 
 fn deserialize_byte(bytes: &[u8]) -> (u8, &[u8]) {
@@ -29,24 +45,28 @@ fn deserialize_byte(bytes: &[u8]) -> (u8, &[u8]) {
 }
 
 fn deserialize_id(bytes: &[u8]) -> Option<(ID, &[u8])> {
-    let sized_bytes: [u8; 16] = bytes[0..16].try_into().ok()?;
+    let sized_bytes: [u8; 16] = bytes.get(0..16)?.try_into().ok()?;
     Some((sized_bytes.into(), &bytes[16..]))
 }
 
 fn deserialize_u64(bytes: &[u8]) -> Option<(u64, &[u8])> {
-    let u = u64::from_be_bytes(bytes[0..u64_byte_count()].try_into().ok()?);
+    let u = u64::from_be_bytes(bytes.get(0..u64_byte_count())?.try_into().ok()?);
     Some((u, &bytes[u64_byte_count()..]))
 }
 
 fn deserialize_i64(bytes: &[u8]) -> Option<(i64, &[u8])> {
-    let u = i64::from_be_bytes(bytes[0..i64_byte_count()].try_into().ok()?);
+    let u = i64::from_be_bytes(bytes.get(0..i64_byte_count())?.try_into().ok()?);
     Some((u, &bytes[i64_byte_count()..]))
 }
 
 fn deserialize_v(bytes: &[u8]) -> Option<(Value, &[u8])> {
+    Value::from_sortable_bytes(bytes)
+}
+
+fn deserialize_v0_v(bytes: &[u8]) -> Option<(Value, &[u8])> {
     let (byte_count, bytes) = deserialize_u64(bytes)?;
     let byte_count = byte_count as usize;
-    let v = Value::from_bytes(&bytes[0..byte_count])?;
+    let v = Value::from_bytes(bytes.get(0..byte_count)?)?;
     Some((v, &bytes[byte_count..]))
 }
 
@@ -56,6 +76,20 @@ fn deserialize_datom_type(bytes: &[u8]) -> (DatomType, &[u8]) {
     (datom_type, &bytes[1..])
 }
 
+/// Get the first key after every key starting with `prefix`. Trailing
+/// 255 bytes can't be incremented, so they're dropped, and if every
+/// byte after the index byte is 255 this is the start of the next
+/// index.
+fn prefix_end(mut prefix: Vec<u8>) -> Vec<u8> {
+    while prefix.len() > 1 && prefix.last() == Some(&u8::MAX) {
+        prefix.pop();
+    }
+    if let Some(last) = prefix.last_mut() {
+        *last += 1;
+    }
+    prefix
+}
+
 /// Serialize a [datom](crate::Datom) in entity-attribute-value-t order
 pub fn serialize_eavt(datom: &Datom) -> Vec<u8> {
     let mut v = vec![Index::EAVT.byte()];
@@ -111,6 +145,34 @@ pub fn serialize_tr(tr: &TransactionRecord) -> Vec<u8> {
     v.to_vec()
 }
 
+/**
+Serialize a [datom](crate::Datom) for the transaction log, in
+t-entity-attribute-value order
+
+```
+use datom::{serial, Datom, DatomType, ID};
+let datom = Datom {
+    entity: ID::new(),
+    attribute: ID::new(),
+    value: "Val".into(),
+    t: 7,
+    datom_type: DatomType::Retraction,
+};
+let item = serial::serialize_log(&datom);
+assert!(serial::vec_range_slice(&serial::log_t_range(7)).contains(&item.as_slice()));
+assert_eq!(serial::deserialize_log(&item), Some(datom));
+```
+*/
+pub fn serialize_log(datom: &Datom) -> Vec<u8> {
+    let mut v = vec![LOG_PREFIX];
+    v.append(&mut datom.t.to_be_bytes().to_vec());
+    v.append(&mut <[u8; 16]>::from(datom.entity).to_vec());
+    v.append(&mut <[u8; 16]>::from(datom.attribute).to_vec());
+    v.append(&mut serialize_v(&datom.value));
+    v.push(datom.datom_type.byte());
+    v
+}
+
 /// Create a range encompassing an entire index
 ///
 /// ```
@@ -127,39 +189,45 @@ pub const fn index_range(index: Index) -> Range<[u8; 1]> {
 /// ```
 /// use datom::{serial, ID};
 /// let id = ID::null();
-/// let from = [0u8; 17];
-/// let mut to = from;
+/// let from = vec![0u8; 17];
+/// let mut to = from.clone();
 /// to[16] = 1;
 /// assert_eq!(serial::eavt_entity_range(id), from..to);
 /// ```
-pub fn eavt_entity_range(eid: ID) -> Range<[u8; 17]> {
-    let mut from = [0; 17];
-    let mut to = [0; 17];
-    from[0] = Index::EAVT.byte();
-    to[0] = Index::EAVT.byte();
-    let eid_bytes: [u8; 16] = eid.into();
-    let eid_u128 = u128::from_be_bytes(eid_bytes);
-    let to_u128 = eid_u128 + 1;
-    let to_bytes = to_u128.to_be_bytes();
-    from[1..].copy_from_slice(&eid_bytes);
-    to[1..].copy_from_slice(&to_bytes);
+pub fn eavt_entity_range(eid: ID) -> Range<Vec<u8>> {
+    let mut from = vec![Index::EAVT.byte()];
+    from.extend_from_slice(&<[u8; 16]>::from(eid));
+    let to = prefix_end(from.clone());
+    from..to
+}
+
+/// Create a range encompassing every possible datom for a given
+/// attribute in the [AEVT index](crate::Index::AEVT)
+///
+/// ```
+/// use datom::{serial, ID};
+/// let id = ID::null();
+/// let from = vec![1u8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+/// let mut to = from.clone();
+/// to[16] = 1;
+/// assert_eq!(serial::aevt_attribute_range(id), from..to);
+/// let last = serial::aevt_attribute_range(ID::from_u128(u128::MAX));
+/// assert_eq!(last.end, vec![2]);
+/// ```
+pub fn aevt_attribute_range(aid: ID) -> Range<Vec<u8>> {
+    let mut from = vec![Index::AEVT.byte()];
+    from.extend_from_slice(&<[u8; 16]>::from(aid));
+    let to = prefix_end(from.clone());
     from..to
 }
 
 /// Create a range encompassing every possible [datom](crate::Datom) for
 /// a given entity and attribute in the [EAVT index](crate::Index::EAVT)
-pub fn eavt_entity_attribute_range(eid: ID, aid: ID) -> Range<[u8; 33]> {
-    let mut base = [0; 33];
-    base[0] = Index::EAVT.byte();
-    base[1..17].copy_from_slice(&<[u8; 16]>::from(eid));
-    let aid_bytes = <[u8; 16]>::from(aid);
-    let aid_u128 = u128::from_be_bytes(aid_bytes);
-    let to_u128 = aid_u128 + 1;
-    let to_bytes = to_u128.to_be_bytes();
-    let mut from = base;
-    from[17..].copy_from_slice(&aid_bytes);
-    let mut to = base;
-    to[17..].copy_from_slice(&to_bytes);
+pub fn eavt_entity_attribute_range(eid: ID, aid: ID) -> Range<Vec<u8>> {
+    let mut from = vec![Index::EAVT.byte()];
+    from.extend_from_slice(&<[u8; 16]>::from(eid));
+    from.extend_from_slice(&<[u8; 16]>::from(aid));
+    let to = prefix_end(from.clone());
     from..to
 }
 
@@ -172,19 +240,97 @@ pub fn avet_attribute_value_range(eid: ID, val: Value) -> Range<Vec<u8>> {
     let eid_bytes: [u8; 16] = eid.into();
     from[1..17].copy_from_slice(&eid_bytes);
     from.append(&mut val_serialized);
-    let mut to = from.clone();
-    let mut i = to.len() - 1;
-    while i > 0 {
-        match to[i].checked_add(1) {
-            Some(x) => {
-                to[i] = x;
-                break;
-            }
-            None => {
-                i -= 1;
-            }
+    let to = prefix_end(from.clone());
+    from..to
+}
+
+/**
+Create a range encompassing every possible datom for a given
+attribute whose value is within the given bounds in the
+[AVET index](crate::Index::AVET)
+
+```
+use std::ops::Bound;
+use datom::{serial, Value, ID};
+let range = serial::avet_attribute_bounds_range(
+    ID::null(),
+    Bound::Included(&Value::from(18)),
+    Bound::Excluded(&Value::from(30)),
+);
+let value_range = serial::avet_attribute_value_range(ID::null(), 18.into());
+assert_eq!(range.start, value_range.start);
+assert!(range.start < range.end);
+```
+*/
+pub fn avet_attribute_bounds_range(
+    aid: ID,
+    start: Bound<&Value>,
+    end: Bound<&Value>,
+) -> Range<Vec<u8>> {
+    let mut prefix = vec![Index::AVET.byte()];
+    prefix.extend_from_slice(&<[u8; 16]>::from(aid));
+    let with_value = |v: &Value| [prefix.as_slice(), &serialize_v(v)].concat();
+    let from = match start {
+        Bound::Included(v) => with_value(v),
+        Bound::Excluded(v) => prefix_end(with_value(v)),
+        Bound::Unbounded => prefix.clone(),
+    };
+    let to = match end {
+        Bound::Included(v) => prefix_end(with_value(v)),
+        Bound::Excluded(v) => with_value(v),
+        Bound::Unbounded => prefix_end(prefix.clone()),
+    };
+    from..to
+}
+
+/**
+Create the smallest key in an [index](crate::Index) which starts with
+the given components, in the index's order. Entities and attributes
+must be [IDs](Value::ID). Returns [None] if there are more than three
+components, or one of them is of the wrong type.
+
+```
+use datom::{serial, Datom, DatomType, Index, ID};
+let datom = Datom {
+    entity: ID::new(),
+    attribute: ID::new(),
+    value: "Val".into(),
+    t: 0,
+    datom_type: DatomType::Addition,
+};
+let key = serial::seek_key(Index::AEVT, &[datom.attribute.into(), datom.entity.into()]);
+assert!(key.unwrap() <= serial::serialize_aevt(&datom));
+```
+*/
+pub fn seek_key(index: Index, components: &[Value]) -> Option<Vec<u8>> {
+    if components.len() > 3 {
+        return None;
+    }
+    // Whether each position in the index holds an ID
+    let ids = match index {
+        Index::EAVT | Index::AEVT => [true, true, false],
+        Index::AVET => [true, false, true],
+        Index::VAET => [false, true, true],
+    };
+    let mut key = vec![index.byte()];
+    for (component, id) in components.iter().zip(ids) {
+        match (component, id) {
+            (Value::ID(id), true) => key.extend_from_slice(&<[u8; 16]>::from(*id)),
+            (_, true) => return None,
+            (v, false) => key.append(&mut serialize_v(v)),
         }
     }
+    Some(key)
+}
+
+/// Create a range encompassing every possible datom for a given
+/// value in the [VAET index](crate::Index::VAET)
+pub fn vaet_value_range(val: Value) -> Range<Vec<u8>> {
+    let mut val_serialized = serialize_v(&val);
+    let mut from = Vec::with_capacity(1 + val_serialized.len());
+    from.push(Index::VAET.byte());
+    from.append(&mut val_serialized);
+    let to = prefix_end(from.clone());
     from..to
 }
 
@@ -197,27 +343,73 @@ pub fn vaet_value_attribute_range(val: Value, eid: ID) -> Range<Vec<u8>> {
     let eid_bytes: [u8; 16] = eid.into();
     from.append(&mut val_serialized);
     from.extend_from_slice(&eid_bytes);
-    let mut to = from.clone();
-    let mut i = to.len() - 1;
-    while i > 0 {
-        match to[i].checked_add(1) {
-            Some(x) => {
-                to[i] = x;
-                break;
-            }
-            None => {
-                i -= 1;
-            }
-        }
-    }
+    let to = prefix_end(from.clone());
     from..to
 }
 
+/// Create a range encompassing the transaction result for a given t
+///
+/// ```
+/// use datom::serial;
+/// let range = serial::tr_t_range(1);
+/// assert_eq!(range.start, [255, 0, 0, 0, 0, 0, 0, 0, 1]);
+/// assert_eq!(range.end, [255, 0, 0, 0, 0, 0, 0, 0, 2]);
+/// let last = serial::tr_t_range(u64::MAX);
+/// assert_eq!(last.end, serial::tr_range().end);
+/// ```
+pub fn tr_t_range(t: u64) -> Range<Vec<u8>> {
+    tr_bounds_range(Bound::Included(&t), Bound::Included(&t))
+}
+
 /// Create a range encompassing every transaction result
 pub fn tr_range() -> Range<Vec<u8>> {
     vec![255]..vec![255; 1 + u64_byte_count() + i64_byte_count()]
 }
 
+/**
+Create a range encompassing the transaction results whose t-values
+are within the given bounds
+
+```
+use std::ops::Bound;
+use datom::serial;
+let range = serial::tr_bounds_range(Bound::Excluded(&1), Bound::Included(&3));
+assert_eq!(range.start, serial::tr_t_range(2).start);
+assert_eq!(range.end, serial::tr_t_range(3).end);
+let range = serial::tr_bounds_range(Bound::Included(&255), Bound::Included(&u64::MAX));
+assert_eq!(range.start, serial::tr_t_range(255).start);
+assert_eq!(range.end, serial::tr_range().end);
+```
+*/
+pub fn tr_bounds_range(start: Bound<&u64>, end: Bound<&u64>) -> Range<Vec<u8>> {
+    let with_t = |t: &u64| [&[255], &t.to_be_bytes()[..]].concat();
+    // The first key of the next transaction, or the end of the last
+    let after = |t: &u64| {
+        t.checked_add(1)
+            .map_or_else(|| tr_range().end, |t| with_t(&t))
+    };
+    let from = match start {
+        Bound::Included(t) => with_t(t),
+        Bound::Excluded(t) => after(t),
+        Bound::Unbounded => vec![255],
+    };
+    let to = match end {
+        Bound::Included(t) => after(t),
+        Bound::Excluded(t) => with_t(t),
+        Bound::Unbounded => tr_range().end,
+    };
+    from..to
+}
+
+/// Create a range encompassing the transaction log's datoms for a
+/// given t
+pub fn log_t_range(t: u64) -> Range<Vec<u8>> {
+    let mut from = vec![LOG_PREFIX];
+    from.extend_from_slice(&t.to_be_bytes());
+    let to = prefix_end(from.clone());
+    from..to
+}
+
 /// Convert a range of arrays to a range of slices
 pub fn range_slice<T, const N: usize>(r: &'_ Range<[T; N]>) -> Range<&'_ [T]> {
     &r.start..&r.end
@@ -316,7 +508,24 @@ pub fn deserialize_tr(bytes: &[u8]) -> Option<TransactionRecord> {
     let (ts_millis, _) = deserialize_i64(bytes)?;
     Some(TransactionRecord {
         t,
-        timestamp: Utc.timestamp_millis(ts_millis),
+        timestamp: Utc.timestamp_millis_opt(ts_millis).single()?,
+    })
+}
+
+/// Deserialize a [datom](crate::Datom) from the transaction log
+pub fn deserialize_log(bytes: &[u8]) -> Option<Datom> {
+    let (_, bytes) = deserialize_byte(bytes);
+    let (t, bytes) = deserialize_u64(bytes)?;
+    let (entity, bytes) = deserialize_id(bytes)?;
+    let (attribute, bytes) = deserialize_id(bytes)?;
+    let (value, bytes) = deserialize_v(bytes)?;
+    let (datom_type, _) = deserialize_datom_type(bytes);
+    Some(Datom {
+        entity,
+        attribute,
+        value,
+        t,
+        datom_type,
     })
 }
 
@@ -356,3 +565,223 @@ pub fn deserialize_unknown(bytes: &[u8]) -> Option<(Datom, Index)> {
     let index = Index::from_byte(index_byte);
     Some((deserialize(bytes, index)?, index))
 }
+
+/// Create the item which marks a storage as using the current
+/// [format version](FORMAT_VERSION)
+pub const fn format_marker() -> [u8; 2] {
+    [FORMAT_PREFIX, FORMAT_VERSION]
+}
+
+/// Create a range encompassing every format version marker
+pub const fn format_range() -> Range<[u8; 1]> {
+    [FORMAT_PREFIX]..[FORMAT_PREFIX + 1]
+}
+
+fn deserialize_v0(bytes: &[u8]) -> Option<(Datom, Index)> {
+    let (index_byte, bytes) = deserialize_byte(bytes);
+    let (index, entity, attribute, value, bytes) = match index_byte {
+        0 => {
+            let (entity, bytes) = deserialize_id(bytes)?;
+            let (attribute, bytes) = deserialize_id(bytes)?;
+            let (value, bytes) = deserialize_v0_v(bytes)?;
+            (Index::EAVT, entity, attribute, value, bytes)
+        }
+        1 => {
+            let (attribute, bytes) = deserialize_id(bytes)?;
+            let (entity, bytes) = deserialize_id(bytes)?;
+            let (value, bytes) = deserialize_v0_v(bytes)?;
+            (Index::AEVT, entity, attribute, value, bytes)
+        }
+        2 => {
+            let (attribute, bytes) = deserialize_id(bytes)?;
+            let (value, bytes) = deserialize_v0_v(bytes)?;
+            let (entity, bytes) = deserialize_id(bytes)?;
+            (Index::AVET, entity, attribute, value, bytes)
+        }
+        3 => {
+            let (value, bytes) = deserialize_v0_v(bytes)?;
+            let (attribute, bytes) = deserialize_id(bytes)?;
+            let (entity, bytes) = deserialize_id(bytes)?;
+            (Index::VAET, entity, attribute, value, bytes)
+        }
+        _ => return None,
+    };
+    let (t, bytes) = deserialize_u64(bytes)?;
+    let datom_type = match bytes {
+        [0] => DatomType::Addition,
+        [1] => DatomType::Retraction,
+        _ => return None,
+    };
+    let datom = Datom {
+        entity,
+        attribute,
+        value,
+        t,
+        datom_type,
+    };
+    Some((datom, index))
+}
+
+/**
+Rewrite an item written in format version 0 in format version 1.
+Items which aren't datoms, like transaction records, are returned
+unchanged. Returns [None] if the item is a malformed datom.
+
+Storage backends use this to upgrade existing stores, since values
+in version 0 keys didn't sort in their natural order:
+
+```
+use datom::{serial, Datom, DatomType, Index, ID};
+# fn serialize_v0_vaet(datom: &Datom) -> Vec<u8> {
+#     let v = datom.value.bytes();
+#     let mut item = vec![3];
+#     item.extend_from_slice(&(v.len() as u64).to_be_bytes());
+#     item.extend_from_slice(&v);
+#     item.extend_from_slice(&<[u8; 16]>::from(datom.attribute));
+#     item.extend_from_slice(&<[u8; 16]>::from(datom.entity));
+#     item.extend_from_slice(&datom.t.to_be_bytes());
+#     item.push(datom.datom_type.byte());
+#     item
+# }
+let datom = Datom {
+    entity: ID::new(),
+    attribute: ID::new(),
+    value: (-5).into(),
+    t: 3,
+    datom_type: DatomType::Addition,
+};
+let upgraded = serial::upgrade_v0(&serialize_v0_vaet(&datom));
+assert_eq!(upgraded, Some(serial::serialize(&datom, Index::VAET)));
+```
+*/
+pub fn upgrade_v0(item: &[u8]) -> Option<Vec<u8>> {
+    match item.first() {
+        Some(0..=3) => {
+            let (datom, index) = deserialize_v0(item)?;
+            Some(serialize(&datom, index))
+        }
+        _ => Some(item.to_vec()),
+    }
+}
+
+/**
+Rewrite an item written in format version 1 in format version 2,
+which adds the transaction log. Each datom in the
+[EAVT index](crate::Index::EAVT) is returned along with its log
+entry, and other items are returned unchanged. Returns [None] if the
+item is a malformed datom.
+
+```
+use datom::{serial, Datom, DatomType, Index, ID};
+let datom = Datom {
+    entity: ID::new(),
+    attribute: ID::new(),
+    value: (-5).into(),
+    t: 3,
+    datom_type: DatomType::Addition,
+};
+let eavt = serial::serialize(&datom, Index::EAVT);
+let aevt = serial::serialize(&datom, Index::AEVT);
+assert_eq!(
+    serial::upgrade_v1(&eavt),
+    Some(vec![eavt.clone(), serial::serialize_log(&datom)])
+);
+assert_eq!(serial::upgrade_v1(&aevt), Some(vec![aevt.clone()]));
+```
+*/
+pub fn upgrade_v1(item: &[u8]) -> Option<Vec<Vec<u8>>> {
+    if item.first() == Some(&Index::EAVT.byte()) {
+        let datom = deserialize_eavt(item)?;
+        Some(vec![item.to_vec(), serialize_log(&datom)])
+    } else {
+        Some(vec![item.to_vec()])
+    }
+}
+
+/// Get the attribute of an [EAVT](Index::EAVT) item with a
+/// [boolean](Value::Boolean) value, to find the attributes whose
+/// value type [upgrade_v2] rewrites
+pub fn boolean_attribute(item: &[u8]) -> Option<ID> {
+    if item.first() != Some(&Index::EAVT.byte()) {
+        return None;
+    }
+    match deserialize_eavt(item)? {
+        Datom {
+            attribute,
+            value: Value::Boolean(_),
+            ..
+        } => Some(attribute),
+        _ => None,
+    }
+}
+
+/**
+Rewrite an item written in format version 2 in the current format,
+where the [boolean type](builtin_idents::TYPE_BOOLEAN) no longer shares
+its ID with the [reference type](builtin_idents::TYPE_REF). The
+[value type](builtin_idents::VALUE_TYPE) datoms of the given
+attributes, which have boolean values according to
+[boolean_attribute], are rewritten to the boolean type in every index
+and in the log, and other items are returned unchanged. Attributes
+without any values can't be told apart from references, so they stay
+references. Returns [None] if the item is a malformed datom.
+
+```
+use std::collections::HashSet;
+use datom::{builtin_idents, serial, Datom, DatomType, Index, ID};
+let admin = ID::new();
+let user = ID::new();
+let flag = Datom {
+    entity: user,
+    attribute: admin,
+    value: true.into(),
+    t: 4,
+    datom_type: DatomType::Addition,
+};
+let mut value_type = Datom {
+    entity: admin,
+    attribute: builtin_idents::VALUE_TYPE,
+    value: builtin_idents::TYPE_REF.into(),
+    t: 3,
+    datom_type: DatomType::Addition,
+};
+let booleans: HashSet<ID> = serial::boolean_attribute(&serial::serialize(&flag, Index::EAVT))
+    .into_iter()
+    .collect();
+let vaet = serial::serialize(&value_type, Index::VAET);
+let upgraded = serial::upgrade_v2(&vaet, &booleans);
+value_type.value = builtin_idents::TYPE_BOOLEAN.into();
+assert_eq!(upgraded, Some(serial::serialize(&value_type, Index::VAET)));
+assert_eq!(serial::upgrade_v2(&vaet, &HashSet::new()), Some(vaet));
+```
+*/
+pub fn upgrade_v2(item: &[u8], boolean_attributes: &HashSet<ID>) -> Option<Vec<u8>> {
+    let retype = |datom: &mut Datom| {
+        let boolean = datom.attribute == builtin_idents::VALUE_TYPE
+            && datom.value == builtin_idents::TYPE_REF.into()
+            && boolean_attributes.contains(&datom.entity);
+        if boolean {
+            datom.value = builtin_idents::TYPE_BOOLEAN.into();
+        }
+        boolean
+    };
+    match item.first() {
+        Some(0..=3) => {
+            let (mut datom, index) = deserialize_unknown(item)?;
+            Some(if retype(&mut datom) {
+                serialize(&datom, index)
+            } else {
+                item.to_vec()
+            })
+        }
+        Some(&LOG_PREFIX) => {
+            let mut datom = deserialize_log(item)?;
+            Some(if retype(&mut datom) {
+                serialize_log(&datom)
+            } else {
+                item.to_vec()
+            })
+        }
+        _ => Some(item.to_vec()),
+    }
+}
@@ -2,7 +2,7 @@
 // SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
 // SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
 
-use std::ops::Range;
+use std::{ops::Range, sync::Arc};
 
 use crate::{StorageError, ID};
 
@@ -20,6 +20,23 @@ pub trait Storage: Send + Sync {
     /// Insert many new items into the backend (in one transaction, if possible)
     fn insert(&self, is: &[Item]) -> Result<(), StorageError>;
 
+    /**
+    Insert many new items into the backend in one transaction, but
+    only if there are no items within `guard` yet. If there are,
+    nothing is inserted and [StorageError::ConcurrencyError] is
+    returned.
+
+    The default implementation checks and then inserts, so another
+    writer could insert into `guard` in between. Backends which can
+    be written to concurrently should make this atomic.
+    */
+    fn insert_if_empty(&self, guard: Range<&[u8]>, is: &[Item]) -> Result<(), StorageError> {
+        if self.range(guard)?.next().is_some() {
+            return Err(StorageError::ConcurrencyError);
+        }
+        self.insert(is)
+    }
+
     /// Get a unique ID for this instance
     fn id(&self) -> ID;
 }
@@ -33,6 +50,28 @@ impl<S: Storage + ?Sized> Storage for Box<S> {
         (**self).insert(is)
     }
 
+    fn insert_if_empty(&self, guard: Range<&[u8]>, is: &[Item]) -> Result<(), StorageError> {
+        (**self).insert_if_empty(guard, is)
+    }
+
+    fn id(&self) -> ID {
+        (**self).id()
+    }
+}
+
+impl<S: Storage + ?Sized> Storage for Arc<S> {
+    fn range(&self, r: Range<&[u8]>) -> Result<ItemIterator<'_>, StorageError> {
+        (**self).range(r)
+    }
+
+    fn insert(&self, is: &[Item]) -> Result<(), StorageError> {
+        (**self).insert(is)
+    }
+
+    fn insert_if_empty(&self, guard: Range<&[u8]>, is: &[Item]) -> Result<(), StorageError> {
+        (**self).insert_if_empty(guard, is)
+    }
+
     fn id(&self) -> ID {
         (**self).id()
     }
@@ -4,12 +4,13 @@
 
 use std::collections::HashSet;
 
-use crate::{DatomIterator, DatomType, QueryError, ID};
+use crate::{ConnectionError, DatomIterator, DatomType, QueryError, Value, ID};
 
 /// An iterator over attributes in a sled-backed database
 pub struct AttributeIterator<'d> {
     iter: DatomIterator<'d>,
     seen: HashSet<ID>,
+    seen_values: HashSet<(ID, Value)>,
 }
 
 impl<'d> AttributeIterator<'d> {
@@ -17,21 +18,30 @@ impl<'d> AttributeIterator<'d> {
         Ok(Self {
             iter,
             seen: HashSet::new(),
+            seen_values: HashSet::new(),
         })
     }
 }
 
 impl<'s> Iterator for AttributeIterator<'s> {
-    type Item = ID;
+    type Item = Result<ID, ConnectionError>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        // Going backwards through the index, the first datom seen for
+        // each value is its latest, so an attribute is present if any
+        // of those is an addition.
         for datom in (&mut self.iter).rev() {
+            let datom = match datom {
+                Ok(datom) => datom,
+                Err(e) => return Some(Err(e)),
+            };
             let attr = datom.attribute;
-            if !self.seen.contains(&attr) {
+            if self.seen.contains(&attr) || !self.seen_values.insert((attr, datom.value)) {
+                continue;
+            }
+            if datom.datom_type == DatomType::Addition {
                 self.seen.insert(attr);
-                if datom.datom_type == DatomType::Addition {
-                    return Some(attr);
-                }
+                return Some(Ok(attr));
             }
         }
         None
@@ -2,10 +2,12 @@
 // SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
 // SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
 
+use std::convert::TryFrom;
+
 use crate::{builtin_idents, Transactable, Transaction, Value, ID};
 
 /// The type of an attribute's values
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum AttributeType {
     /// A [Value::String](crate::Value::String)
     String,
@@ -21,6 +23,26 @@ pub enum AttributeType {
     Boolean,
 }
 
+impl AttributeType {
+    /// Whether a [Value] can be stored in an attribute of this type
+    ///
+    /// ```
+    /// use datom::{AttributeType, Value, ID};
+    /// assert!(AttributeType::Ref.matches(&ID::new().into()));
+    /// assert!(!AttributeType::Integer.matches(&"1".into()));
+    /// ```
+    pub const fn matches(&self, value: &Value) -> bool {
+        matches!(
+            (self, value),
+            (Self::String, Value::String(_))
+                | (Self::Integer, Value::Integer(_))
+                | (Self::Decimal, Value::Decimal(_))
+                | (Self::ID | Self::Ref, Value::ID(_))
+                | (Self::Boolean, Value::Boolean(_))
+        )
+    }
+}
+
 impl From<AttributeType> for ID {
     fn from(t: AttributeType) -> Self {
         use builtin_idents::*;
@@ -35,8 +57,33 @@ impl From<AttributeType> for ID {
     }
 }
 
+impl TryFrom<ID> for AttributeType {
+    type Error = ID;
+
+    /// ```
+    /// use std::convert::TryFrom;
+    /// use datom::{builtin_idents, AttributeType};
+    /// assert_eq!(
+    ///     AttributeType::try_from(builtin_idents::TYPE_STRING),
+    ///     Ok(AttributeType::String)
+    /// );
+    /// ```
+    fn try_from(id: ID) -> Result<Self, Self::Error> {
+        use builtin_idents::*;
+        match id {
+            TYPE_STRING => Ok(Self::String),
+            TYPE_INTEGER => Ok(Self::Integer),
+            TYPE_DECIMAL => Ok(Self::Decimal),
+            TYPE_ID => Ok(Self::ID),
+            TYPE_REF => Ok(Self::Ref),
+            TYPE_BOOLEAN => Ok(Self::Boolean),
+            _ => Err(id),
+        }
+    }
+}
+
 /// An imperative way to generate an attribute's schema
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct AttributeSchema {
     /// The attribute's ID
     pub id: ID,
@@ -51,6 +98,15 @@ pub struct AttributeSchema {
     /// Whether there can only be one entity of each value for this
     /// attribute
     pub unique: bool,
+    /**
+    Whether this attribute's unique values identify their entities, so
+    that a [temporary ID](crate::EID::Temp) asserting an existing value
+    resolves to the entity which already holds it
+    */
+    pub identity: bool,
+    /// Whether this attribute's values are kept in the
+    /// [AVET index](crate::Index::AVET)
+    pub index: bool,
     /// Whether this attribute refers to a component
     pub component: bool,
 }
@@ -65,6 +121,8 @@ impl AttributeSchema {
             value_type: None,
             doc: None,
             unique: false,
+            identity: false,
+            index: false,
             component: false,
         }
     }
@@ -107,6 +165,21 @@ impl AttributeSchema {
         self
     }
 
+    /// Set the attribute as unique, and as identifying its entities
+    pub const fn identity(mut self) -> Self {
+        self.unique = true;
+        self.identity = true;
+        self
+    }
+
+    /// Set the attribute's values to be kept in the
+    /// [AVET index](crate::Index::AVET), for
+    /// [Database::index_range](crate::Database::index_range)
+    pub const fn index(mut self) -> Self {
+        self.index = true;
+        self
+    }
+
     /// Set the attribute as being a component reference
     pub const fn component(mut self) -> Self {
         self.value_type = Some(AttributeType::Ref);
@@ -151,6 +224,16 @@ impl Transactable for AttributeSchema {
         if self.unique {
             tx.add(self.id.into(), builtin_idents::UNIQUE.into(), true.into());
         }
+        if self.identity {
+            tx.add(
+                self.id.into(),
+                builtin_idents::UNIQUE_IDENTITY.into(),
+                true.into(),
+            );
+        }
+        if self.index {
+            tx.add(self.id.into(), builtin_idents::INDEX.into(), true.into());
+        }
         if self.component {
             tx.add(
                 self.id.into(),
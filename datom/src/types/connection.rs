@@ -2,25 +2,196 @@
 // SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
 // SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
 
-use std::fmt::Debug;
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet},
+    fmt::Debug,
+    iter,
+    ops::RangeBounds,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{sync_channel, SyncSender, TrySendError},
+        Arc, Mutex, PoisonError,
+    },
+};
 
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 
 use crate::{
     builtin_idents,
+    dependencies::Dependencies,
+    schema_cache::{schema, SchemaCache},
     serial::{
-        deserialize_tr, serialize_aevt, serialize_avet, serialize_eavt, serialize_tr,
-        serialize_vaet, tr_range, vec_range_slice,
+        deserialize_tr, serialize_aevt, serialize_avet, serialize_eavt, serialize_log,
+        serialize_tr, serialize_vaet, tr_bounds_range, tr_range, tr_t_range, vec_range_slice,
     },
-    storage::Storage,
-    ConnectionError, Database, Datom, EntityResult, Index, Transactable, Transaction,
-    TransactionError, TransactionRecord, TransactionResult, Value, ID,
+    storage::{Item, Storage},
+    AttributeType, ConnectionError, Database, Datom, DatomType, Index, LogIterator, QueryError,
+    Subscription, TempIDs, Transactable, Transaction, TransactionError, TransactionRecord,
+    TransactionResult, TxReport, Value, Watch, WatchIterator, ID,
 };
 
+/// How many reports a [Subscription] buffers by default
+pub const DEFAULT_SUBSCRIPTION_CAPACITY: usize = 1024;
+
+/// The transactor's end of a [Subscription]
+struct Subscriber {
+    sender: SyncSender<TxReport>,
+    fell_behind: Arc<AtomicBool>,
+    /// Only transactions touching these are reported, if given
+    dependencies: Option<Dependencies>,
+}
+
+impl Subscriber {
+    /// Send a report without blocking, returning whether the
+    /// subscription is still connected
+    fn send(&self, report: &TxReport) -> bool {
+        if let Some(dependencies) = &self.dependencies {
+            if !dependencies.touched_by(&report.data) {
+                return true;
+            }
+        }
+        match self.sender.try_send(report.clone()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                self.fell_behind.store(true, Ordering::Release);
+                false
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    }
+}
+
+/// Ensure no two entities would hold the same value for a
+/// [unique](crate::builtin_idents::UNIQUE) attribute after a
+/// transaction
+fn check_unique<S: Storage>(
+    before: &Database<'_, S>,
+    data: &[Datom],
+    cache: &mut SchemaCache,
+) -> Result<(), TransactionError> {
+    let retracted: HashSet<(ID, ID, &Value)> = data
+        .iter()
+        .filter(|datom| datom.datom_type == DatomType::Retraction)
+        .map(|datom| (datom.entity, datom.attribute, &datom.value))
+        .collect();
+    let mut holders: HashMap<(ID, &Value), ID> = HashMap::new();
+    for datom in data.iter() {
+        if datom.datom_type != DatomType::Addition
+            || !schema(before, datom.attribute, cache)?.unique
+        {
+            continue;
+        }
+        let conflict = |holder: ID| {
+            TransactionError::UniqueConflict(
+                datom.entity,
+                datom.attribute,
+                datom.value.to_owned(),
+                holder,
+            )
+        };
+        match holders.insert((datom.attribute, &datom.value), datom.entity) {
+            Some(holder) if holder != datom.entity => return Err(conflict(holder)),
+            _ => {}
+        }
+        let existing =
+            before.current_datoms(None, Some(datom.attribute), Some(&datom.value), cache)?;
+        for holder in existing {
+            if holder.entity != datom.entity
+                && !retracted.contains(&(holder.entity, holder.attribute, &holder.value))
+            {
+                return Err(conflict(holder.entity));
+            }
+        }
+    }
+    Ok(())
+}
+
+/**
+Reconcile a transaction's [Datom]s with the current state of the
+database
+
+Assertions of values which are already current and retractions of
+values which aren't are dropped, and for cardinality-one attributes
+the value being replaced is retracted, so that every index has exactly
+one current value for each cardinality-one attribute.
+*/
+fn reconcile<S: Storage>(
+    before: &Database<'_, S>,
+    data: Vec<Datom>,
+    cache: &mut SchemaCache,
+) -> Result<Vec<Datom>, TransactionError> {
+    let mut asserted: HashSet<(ID, ID, &Value)> = HashSet::new();
+    let mut retracted: HashSet<(ID, ID, &Value)> = HashSet::new();
+    let mut replacements: HashMap<(ID, ID), &Value> = HashMap::new();
+    for datom in data.iter() {
+        let key = (datom.entity, datom.attribute, &datom.value);
+        let conflict = if datom.datom_type == DatomType::Addition {
+            asserted.insert(key);
+            retracted.contains(&key)
+                || (!schema(before, datom.attribute, cache)?.many
+                    && *replacements
+                        .entry((datom.entity, datom.attribute))
+                        .or_insert(&datom.value)
+                        != &datom.value)
+        } else {
+            retracted.insert(key);
+            asserted.contains(&key)
+        };
+        if conflict {
+            return Err(TransactionError::ConflictingValues(
+                datom.entity,
+                datom.attribute,
+            ));
+        }
+    }
+    let mut current: HashMap<(ID, ID), Vec<Datom>> = HashMap::new();
+    let mut written: HashSet<(ID, ID, Value)> = HashSet::new();
+    let mut res = vec![];
+    for datom in data.iter() {
+        let values = match current.entry((datom.entity, datom.attribute)) {
+            Entry::Occupied(o) => o.into_mut(),
+            Entry::Vacant(v) => v.insert(before.current_datoms(
+                Some(datom.entity),
+                Some(datom.attribute),
+                None,
+                cache,
+            )?),
+        };
+        let is_current = values.iter().any(|d| d.value == datom.value);
+        if datom.datom_type == DatomType::Addition {
+            if is_current {
+                continue;
+            }
+            if !schema(before, datom.attribute, cache)?.many {
+                for replaced in values.iter() {
+                    if written.insert((replaced.entity, replaced.attribute, replaced.value.clone()))
+                    {
+                        res.push(Datom {
+                            t: datom.t,
+                            datom_type: DatomType::Retraction,
+                            ..replaced.to_owned()
+                        });
+                    }
+                }
+            }
+        } else if !is_current {
+            continue;
+        }
+        if written.insert((datom.entity, datom.attribute, datom.value.clone())) {
+            res.push(datom.to_owned());
+        }
+    }
+    Ok(res)
+}
+
 /// A persistent connection to a database
 pub struct Connection<S: Storage> {
     pub(crate) storage: S,
     pub(crate) id: ID,
+    /// Held while transacting, so that each transaction sees the
+    /// result of the previous one
+    transactor: Mutex<()>,
+    subscribers: Mutex<Vec<Subscriber>>,
 }
 
 impl<S: Storage> PartialEq<Self> for Connection<S> {
@@ -71,6 +242,8 @@ impl<S: Storage> Connection<S> {
         Self {
             storage,
             id: ID::new(),
+            transactor: Mutex::new(()),
+            subscribers: Mutex::new(vec![]),
         }
     }
 
@@ -86,10 +259,7 @@ impl<S: Storage> Connection<S> {
 
     /// Fetch the t-value for the latest transaction
     pub const fn as_of(&self, t: u64) -> Result<Database<'_, S>, ConnectionError> {
-        Ok(Database {
-            connection: self,
-            t,
-        })
+        Ok(Database::new(self, t))
     }
 
     /// Get a [database](crate::Database) for the current
@@ -98,55 +268,287 @@ impl<S: Storage> Connection<S> {
         self.as_of(self.latest_t()?)
     }
 
-    /// Run a transaction on the database
-    pub fn transact_tx(
+    /// Fetch the record of the transaction with a given t-value
+    fn record(&self, t: u64) -> Result<Option<TransactionRecord>, ConnectionError> {
+        match self.storage.range(vec_range_slice(&tr_t_range(t)))?.next() {
+            Some(item) => deserialize_tr(&item?)
+                .map(Some)
+                .ok_or(ConnectionError::InvalidData),
+            None => Ok(None),
+        }
+    }
+
+    /// Fetch when the transaction with a given t-value was transacted,
+    /// or [None] if there is no such transaction
+    pub fn instant_for_t(&self, t: u64) -> Result<Option<DateTime<Utc>>, ConnectionError> {
+        Ok(self.record(t)?.map(|record| record.timestamp))
+    }
+
+    /**
+    Find the t-value of the latest transaction transacted at or
+    before `instant`, or 0 if there is none
+
+    ```
+    use datom::{backends::SledStorage, Connection, Transaction, ID};
+
+    let conn = Connection::new(SledStorage::connect_temp()?);
+    let mut tx = Transaction::new();
+    tx.add(ID::new().into(), "db/doc".into(), "first".into());
+    let t = conn.transact(tx)?.after.t();
+    let instant = conn.instant_for_t(t)?.unwrap();
+    assert_eq!(conn.t_for_instant(instant)?, t);
+    assert_eq!(conn.t_for_instant(instant - chrono::Duration::days(1))?, 0);
+    # Ok::<(), Box<dyn std::error::Error>>(())
+    ```
+    */
+    pub fn t_for_instant(&self, instant: DateTime<Utc>) -> Result<u64, ConnectionError> {
+        let (mut low, mut high) = (0, self.latest_t()?);
+        while low < high {
+            let mid = low + (high - low + 1) / 2;
+            let timestamp = self
+                .instant_for_t(mid)?
+                .ok_or(ConnectionError::InvalidData)?;
+            if timestamp <= instant {
+                low = mid;
+            } else {
+                high = mid - 1;
+            }
+        }
+        Ok(low)
+    }
+
+    /// Get a [database](crate::Database) as it was at a given instant
+    pub fn as_of_instant(
         &self,
-        tx: Transaction,
-    ) -> Result<TransactionResult<'_, S>, TransactionError> {
-        let t_before = self.latest_t()?;
-        let t = t_before + 1;
-        let before = self.as_of(t_before)?;
-        let data = tx.datoms(t, &before)?;
-        let mut items: Vec<Vec<u8>> = vec![];
+        instant: DateTime<Utc>,
+    ) -> Result<Database<'_, S>, ConnectionError> {
+        self.as_of(self.t_for_instant(instant)?)
+    }
+
+    /**
+    Read the transaction log for the transactions whose t-values are
+    within `range`, in the order they were transacted, each with the
+    datoms it asserted and retracted
+
+    ```
+    use datom::{backends::SledStorage, Connection, Transaction, ID};
+
+    let conn = Connection::new(SledStorage::connect_temp()?);
+    let mut tx = Transaction::new();
+    tx.add(ID::new().into(), "db/doc".into(), "first".into());
+    let t = conn.transact(tx)?.after.t();
+    let entries = conn.log(t..)?.collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].record.t, t);
+    # Ok::<(), Box<dyn std::error::Error>>(())
+    ```
+    */
+    pub fn log(&self, range: impl RangeBounds<u64>) -> Result<LogIterator<'_, S>, ConnectionError> {
+        let r = tr_bounds_range(range.start_bound(), range.end_bound());
+        if r.start >= r.end {
+            return Ok(LogIterator::new(self, Box::new(iter::empty())));
+        }
+        Ok(LogIterator::new(
+            self,
+            self.storage.range(vec_range_slice(&r))?,
+        ))
+    }
+
+    /**
+    Subscribe to reports of the transactions committed through this
+    connection from now on, buffering up to
+    [DEFAULT_SUBSCRIPTION_CAPACITY] of them. See [Subscription] for
+    what happens to a subscriber which falls behind.
+
+    Only transactions run through this [Connection] are reported,
+    not those committed by other writers to the same storage.
+
+    ```
+    use datom::{backends::SledStorage, Connection, Transaction, ID};
+
+    let conn = Connection::new(SledStorage::connect_temp()?);
+    let mut reports = conn.subscribe();
+    let mut tx = Transaction::new();
+    tx.add(ID::new().into(), "db/doc".into(), "first".into());
+    let res = conn.transact(tx)?;
+    let report = reports.next().unwrap();
+    assert_eq!(report.after_t, res.after.t());
+    assert_eq!(report.data, res.data);
+    # Ok::<(), Box<dyn std::error::Error>>(())
+    ```
+    */
+    pub fn subscribe(&self) -> Subscription {
+        self.subscribe_with_capacity(DEFAULT_SUBSCRIPTION_CAPACITY)
+    }
+
+    /// Subscribe to reports of the transactions committed through this
+    /// connection from now on, buffering up to `capacity` of them
+    pub fn subscribe_with_capacity(&self, capacity: usize) -> Subscription {
+        self.add_subscriber(capacity, None)
+    }
+
+    /// Subscribe to reports of the transactions which touch
+    /// `dependencies`, or of every transaction if there are none
+    pub(crate) fn add_subscriber(
+        &self,
+        capacity: usize,
+        dependencies: Option<Dependencies>,
+    ) -> Subscription {
+        let (sender, receiver) = sync_channel(capacity.max(1));
+        let fell_behind = Arc::new(AtomicBool::new(false));
+        self.subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Subscriber {
+                sender,
+                fell_behind: fell_behind.clone(),
+                dependencies,
+            });
+        Subscription::new(receiver, fell_behind)
+    }
+
+    /**
+    Watch a read, getting its result now and again each time a
+    transaction touching the entities or attributes it depends on is
+    committed through this connection. Unrelated transactions don't
+    re-run the read.
+
+    The entities and attributes a [Watch] depends on are resolved
+    when it's registered.
+
+    ```
+    use datom::{backends::SledStorage, Connection, Transaction, Watch, WatchResult, ID};
+
+    let conn = Connection::new(SledStorage::connect_temp()?);
+    let (page, other) = (ID::new(), ID::new());
+    let mut docs = conn.watch(Watch::Attribute(page.into(), "db/doc".into()))?;
+    assert_eq!(docs.next().transpose()?, Some(WatchResult::Values(vec![])));
+
+    let mut tx = Transaction::new();
+    tx.add(other.into(), "db/doc".into(), "unrelated".into());
+    conn.transact(tx)?;
+    assert!(docs.try_next().is_none());
+
+    let mut tx = Transaction::new();
+    tx.add(page.into(), "db/doc".into(), "a page".into());
+    conn.transact(tx)?;
+    assert_eq!(
+        docs.try_next().transpose()?,
+        Some(WatchResult::Values(vec!["a page".into()]))
+    );
+    # Ok::<(), Box<dyn std::error::Error>>(())
+    ```
+    */
+    pub fn watch(&self, watch: Watch) -> Result<WatchIterator<'_, S>, QueryError> {
+        WatchIterator::new(self, watch)
+    }
+
+    /// Send a report to every subscriber it's relevant to, dropping
+    /// those which have disconnected or fallen behind
+    fn publish(&self, report: &TxReport) {
+        self.subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|subscriber| subscriber.send(report));
+    }
+
+    /// Resolve and validate a transaction on top of a database,
+    /// returning the datoms it asserts and retracts, the items to
+    /// write for them, and the IDs its temporary IDs resolved to
+    pub(crate) fn prepare(
+        before: &Database<'_, S>,
+        record: &TransactionRecord,
+        tx: &Transaction,
+    ) -> Result<(Vec<Datom>, Vec<Item>, TempIDs), TransactionError> {
+        let t = record.t;
+        let mut tempids = TempIDs::new();
+        let mut data = vec![Datom {
+            entity: record.id(),
+            attribute: builtin_idents::TX_INSTANT,
+            value: record.timestamp.timestamp_millis().into(),
+            t,
+            datom_type: DatomType::Addition,
+        }];
+        data.append(&mut tx.datoms(t, before, &mut tempids)?);
+        let mut cache = SchemaCache::new();
+        let data = reconcile(before, data, &mut cache)?;
+        let mut items: Vec<Item> = vec![];
         for datom in data.iter() {
+            let schema = schema(before, datom.attribute, &mut cache)?;
+            if let Some(value_type) = schema.value_type {
+                if datom.datom_type == DatomType::Addition && !value_type.matches(&datom.value) {
+                    return Err(TransactionError::InvalidValueType(
+                        datom.entity,
+                        datom.attribute,
+                        value_type,
+                        datom.value.to_owned(),
+                    ));
+                }
+            }
             items.push(serialize_eavt(datom));
             items.push(serialize_aevt(datom));
-            let attr_entity = before.entity(datom.attribute.into())?;
-            let unique_value =
-                attr_entity.get_with_options(builtin_idents::UNIQUE.into(), true, true)?;
-            let type_value =
-                attr_entity.get_with_options(builtin_idents::VALUE_TYPE.into(), true, true)?;
-            let is_unique = {
-                if let EntityResult::Value(Value::Boolean(x)) = unique_value {
-                    x
-                } else {
-                    false
-                }
-            };
-            let is_ref = {
-                if let EntityResult::Value(Value::ID(id)) = type_value {
-                    id == builtin_idents::TYPE_REF
-                } else {
-                    false
-                }
-            };
-            if is_unique {
+            if schema.unique || schema.index {
                 items.push(serialize_avet(datom));
             }
-            if is_ref {
+            if schema.value_type == Some(AttributeType::Ref) {
                 items.push(serialize_vaet(datom));
             }
+            items.push(serialize_log(datom));
         }
-        items.push(serialize_tr(&TransactionRecord {
-            t,
-            timestamp: Utc::now(),
-        }));
-        self.storage.insert(&items).map_err(ConnectionError::from)?;
+        check_unique(before, &data, &mut cache)?;
+        items.push(serialize_tr(record));
+        Ok((data, items, tempids))
+    }
+
+    /**
+    Run a transaction on the database
+
+    Once the transaction is committed, it's reported to every
+    [subscriber](Connection::subscribe). Transactions on a
+    [Connection] are run one at a time. If another
+    writer to the same storage commits a transaction with the same
+    t-value first, this fails with
+    [StorageError::ConcurrencyError](crate::StorageError::ConcurrencyError)
+    and nothing is written.
+    */
+    pub fn transact_tx(
+        &self,
+        tx: Transaction,
+    ) -> Result<TransactionResult<'_, S>, TransactionError> {
+        // A panic while transacting can't leave a partial write
+        // behind, so a poisoned lock is safe to reuse
+        let _transactor = self
+            .transactor
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let t_before = self.latest_t()?;
+        let t = t_before + 1;
+        let before = self.as_of(t_before)?;
+        // Timestamps never decrease with t, even if the clock goes
+        // backwards, so that instants can be searched for
+        let now = Utc::now();
+        let timestamp = self
+            .instant_for_t(t_before)?
+            .map_or(now, |previous| now.max(previous));
+        let record = TransactionRecord { t, timestamp };
+        let (data, items, tempids) = Self::prepare(&before, &record, &tx)?;
+        self.storage
+            .insert_if_empty(vec_range_slice(&tr_t_range(t)), &items)
+            .map_err(ConnectionError::from)?;
+        // Publishing before releasing the transactor keeps reports in
+        // the order their transactions were committed
+        self.publish(&TxReport {
+            before_t: t_before,
+            after_t: t,
+            timestamp: record.timestamp,
+            data: data.clone(),
+        });
         Ok(TransactionResult {
             connection: self,
             before,
             after: self.as_of(t)?,
             data,
+            tempids,
         })
     }
 
@@ -175,7 +577,7 @@ impl<S: Storage> Debug for Connection<S> {
                 "EAVT",
                 &(|| {
                     Ok::<Vec<Datom>, Box<dyn std::error::Error>>(
-                        self.db()?.datoms(Index::EAVT)?.collect(),
+                        self.db()?.datoms(Index::EAVT)?.collect::<Result<_, _>>()?,
                     )
                 })(),
             )
@@ -183,7 +585,7 @@ impl<S: Storage> Debug for Connection<S> {
                 "AEVT",
                 &(|| {
                     Ok::<Vec<Datom>, Box<dyn std::error::Error>>(
-                        self.db()?.datoms(Index::AEVT)?.collect(),
+                        self.db()?.datoms(Index::AEVT)?.collect::<Result<_, _>>()?,
                     )
                 })(),
             )
@@ -191,7 +593,7 @@ impl<S: Storage> Debug for Connection<S> {
                 "AVET",
                 &(|| {
                     Ok::<Vec<Datom>, Box<dyn std::error::Error>>(
-                        self.db()?.datoms(Index::AVET)?.collect(),
+                        self.db()?.datoms(Index::AVET)?.collect::<Result<_, _>>()?,
                     )
                 })(),
             )
@@ -199,7 +601,7 @@ impl<S: Storage> Debug for Connection<S> {
                 "VAET",
                 &(|| {
                     Ok::<Vec<Datom>, Box<dyn std::error::Error>>(
-                        self.db()?.datoms(Index::VAET)?.collect(),
+                        self.db()?.datoms(Index::VAET)?.collect::<Result<_, _>>()?,
                     )
                 })(),
             )
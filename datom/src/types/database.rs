@@ -2,42 +2,354 @@
 // SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
 // SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
 
+use std::{
+    collections::{hash_map::Entry, BTreeSet, HashMap},
+    convert::TryFrom,
+    fmt::{self, Debug, Formatter},
+    iter,
+    ops::{Bound, Range},
+    sync::Arc,
+};
+
+use chrono::Utc;
+
 use crate::{
+    builtin_idents,
+    merge_iters::MergeIters,
+    schema_cache::{schema, SchemaCache},
     serial::{
-        avet_attribute_value_range, eavt_entity_attribute_range, eavt_entity_range, index_range,
-        range_slice, vaet_value_attribute_range, vec_range_slice,
+        aevt_attribute_range, avet_attribute_bounds_range, avet_attribute_value_range,
+        deserialize_unknown, eavt_entity_attribute_range, eavt_entity_range, index_range,
+        range_slice, seek_key, vaet_value_attribute_range, vaet_value_range, vec_range_slice,
     },
-    storage::Storage,
-    Connection, DatomIterator, Entity, Index, QueryError, Value, EID, ID,
+    storage::{Item, ItemIterator, Storage},
+    AttributeSchema, AttributeType, Connection, Datom, DatomIterator, DatomType, Entity,
+    EntityResult, Index, PullMap, PullPattern, Query, QueryError, QueryInput, QueryResult,
+    StorageError, Transactable, TransactionError, TransactionRecord, TransactionResult, Value, EID,
+    ID,
 };
 
-/// A view of a database at a specific point in time
+/// Items from speculative transactions, layered over storage
 #[derive(Debug)]
+struct Overlay {
+    /// The t-value of the latest transaction in storage the
+    /// speculative transactions are based on
+    basis_t: u64,
+    items: BTreeSet<Item>,
+}
+
+/// A predicate deciding which datoms a [filtered](Database::filter)
+/// database includes
+type Filter<'connection, S> =
+    Arc<dyn Fn(&Database<'_, S>, &Datom) -> bool + Send + Sync + 'connection>;
+
+/// A view of a database at a specific point in time. Cloning one only
+/// copies references to its connection and to any speculative
+/// transactions or filters it has.
 pub struct Database<'connection, S: Storage> {
     pub(crate) connection: &'connection Connection<S>,
     pub(crate) t: u64,
+    pub(crate) since: Option<u64>,
+    pub(crate) history: bool,
+    overlay: Option<Arc<Overlay>>,
+    /// Every filter applied to this database, combined into one
+    filter: Option<Filter<'connection, S>>,
+}
+
+impl<'connection, S: Storage> Clone for Database<'connection, S> {
+    fn clone(&self) -> Self {
+        Self {
+            connection: self.connection,
+            t: self.t,
+            since: self.since,
+            history: self.history,
+            overlay: self.overlay.clone(),
+            filter: self.filter.clone(),
+        }
+    }
+}
+
+impl<'connection, S: Storage> Debug for Database<'connection, S> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Database")
+            .field("connection", &self.connection)
+            .field("t", &self.t)
+            .field("since", &self.since)
+            .field("history", &self.history)
+            .field("overlay", &self.overlay)
+            .field("filtered", &self.filter.is_some())
+            .finish()
+    }
 }
 
 impl<'connection, S: Storage> Database<'connection, S> {
+    /// Create a view of the data in a connection's storage as of `t`
+    pub(crate) const fn new(connection: &'connection Connection<S>, t: u64) -> Self {
+        Self {
+            connection,
+            t,
+            since: None,
+            history: false,
+            overlay: None,
+            filter: None,
+        }
+    }
+
+    /// The t-value of the latest transaction this database includes
+    pub const fn t(&self) -> u64 {
+        self.t
+    }
+
+    /**
+    Get a view of this database which includes every assertion and
+    retraction up to its t, rather than only the datoms which are
+    currently asserted.
+
+    [Queries](Query) against a history database match every
+    [datom](crate::Datom), and their data patterns can bind the
+    transaction entity and whether the datom was added as fourth and
+    fifth terms. Idents and [entities](Entity) are still resolved
+    against the current state of the database.
+
+    ```
+    use datom::{backends::SledStorage, Connection, Query, QueryResult, Transaction, Value, ID};
+    let conn = Connection::new(SledStorage::connect_temp()?);
+    let user = ID::new();
+    for doc in ["first", "second"] {
+        let mut tx = Transaction::new();
+        tx.add(user.into(), "db/doc".into(), doc.into());
+        conn.transact(tx)?;
+    }
+    let query: Query = "[:find ?doc ?added :in $ ?e :where [?e :db/doc ?doc _ ?added]]".parse()?;
+    let QueryResult::Relation(rows) = conn.db()?.history().query(&query, &[Value::from(user).into()])? else {
+        unreachable!()
+    };
+    // "first" was added, then retracted when "second" replaced it
+    assert_eq!(rows.len(), 3);
+    # Ok::<(), Box<dyn std::error::Error>>(())
+    ```
+    */
+    pub fn history(&self) -> Self {
+        Self {
+            history: true,
+            ..self.clone()
+        }
+    }
+
+    /// Whether this is a [history](Self::history) database
+    pub const fn is_history(&self) -> bool {
+        self.history
+    }
+
+    /**
+    Get a view of this database which only includes
+    [datoms](crate::Datom) from transactions after `t`. Index scans and
+    [queries](Query) only see those datoms, but idents and attribute
+    schemas are still resolved against the whole database, as are
+    [entities](Entity).
+
+    ```
+    use datom::{backends::SledStorage, builtin_idents, Connection, Transaction, ID};
+    let conn = Connection::new(SledStorage::connect_temp()?);
+    let (old, new) = (ID::new(), ID::new());
+    let mut tx = Transaction::new();
+    tx.add(old.into(), "db/doc".into(), "old".into());
+    let t = conn.transact(tx)?.after.t();
+    let mut tx = Transaction::new();
+    tx.add(new.into(), "db/doc".into(), "new".into());
+    conn.transact(tx)?;
+
+    let since = conn.db()?.since(t);
+    let entities: Vec<ID> = since
+        .datoms_for_attribute(builtin_idents::DOC)?
+        .map(|datom| datom.map(|datom| datom.entity))
+        .collect::<Result<_, _>>()?;
+    assert_eq!(entities, vec![new]);
+    # Ok::<(), Box<dyn std::error::Error>>(())
+    ```
+    */
+    pub fn since(&self, t: u64) -> Self {
+        Self {
+            since: Some(self.since.map_or(t, |since| since.max(t))),
+            ..self.clone()
+        }
+    }
+
+    /// The t-value this database only includes transactions after, if
+    /// it's a [since](Self::since) database
+    pub const fn since_t(&self) -> Option<u64> {
+        self.since
+    }
+
+    /**
+    Get a view of this database which hides every
+    [datom](crate::Datom) `pred` returns `false` for from index scans,
+    [queries](Query), and [entities](Entity). Filtering a filtered
+    database hides the datoms either filter would.
+
+    `pred` is given this database without any filters, so it can look
+    up other facts about the datom, such as which tenant its entity
+    belongs to. Idents, attribute schemas, and
+    [unique](crate::builtin_idents::UNIQUE) lookups are resolved
+    without filters too.
+
+    ```
+    use datom::{backends::SledStorage, Connection, EntityResult, Transaction, ID};
+    let conn = Connection::new(SledStorage::connect_temp()?);
+    let (public, secret) = (ID::new(), ID::new());
+    let mut tx = Transaction::new();
+    tx.add(public.into(), "db/doc".into(), "public".into());
+    tx.add(secret.into(), "db/doc".into(), "secret".into());
+    conn.transact(tx)?;
+
+    let db = conn.db()?.filter(move |_, datom| datom.entity != secret);
+    assert_eq!(
+        db.entity(secret.into())?.get("db/doc".into())?,
+        EntityResult::NotFound
+    );
+    assert_eq!(
+        db.entity(public.into())?.get("db/doc".into())?,
+        EntityResult::Value("public".into())
+    );
+    # Ok::<(), Box<dyn std::error::Error>>(())
+    ```
+    */
+    pub fn filter<F>(&self, pred: F) -> Self
+    where
+        F: Fn(&Database<'_, S>, &Datom) -> bool + Send + Sync + 'connection,
+    {
+        let filter: Filter<'connection, S> = match self.filter.clone() {
+            None => Arc::new(pred),
+            Some(outer) => Arc::new(move |db: &Database<'_, S>, datom: &Datom| {
+                outer(db, datom) && pred(db, datom)
+            }),
+        };
+        Self {
+            filter: Some(filter),
+            ..self.clone()
+        }
+    }
+
+    /// Whether this is a [filtered](Self::filter) database
+    pub fn is_filtered(&self) -> bool {
+        self.filter.is_some()
+    }
+
+    /// Get a view of the same point in time without any restrictions
+    /// on which datoms it includes, for resolving idents and schema
+    pub(crate) fn unfiltered(&self) -> Self {
+        Self {
+            since: None,
+            history: false,
+            filter: None,
+            ..self.clone()
+        }
+    }
+
+    /**
+    Run a transaction against this database without committing it.
+    The result's `after` database layers the transaction's datoms over
+    this one in memory, and can itself be transacted against with
+    [Database::with] to build up several speculative transactions.
+    Nothing is written to storage.
+
+    ```
+    use datom::{backends::SledStorage, Connection, EntityResult, Transaction, ID};
+    let conn = Connection::new(SledStorage::connect_temp()?);
+    let user = ID::new();
+    let mut tx = Transaction::new();
+    tx.add(user.into(), "db/doc".into(), "proposed".into());
+    let db = conn.db()?;
+    let res = db.with(tx)?;
+    assert_eq!(
+        res.after.entity(user.into())?.get("db/doc".into())?,
+        EntityResult::Value("proposed".into())
+    );
+    assert_eq!(
+        conn.db()?.entity(user.into())?.get("db/doc".into())?,
+        EntityResult::NotFound
+    );
+    # Ok::<(), Box<dyn std::error::Error>>(())
+    ```
+    */
+    pub fn with<T: Transactable>(
+        &self,
+        txable: T,
+    ) -> Result<TransactionResult<'connection, S>, TransactionError> {
+        let before = self.unfiltered();
+        let record = TransactionRecord {
+            t: self.t + 1,
+            timestamp: Utc::now(),
+        };
+        let (data, items, tempids) = Connection::prepare(&before, &record, &txable.tx())?;
+        let mut overlay = self.overlay.as_deref().map_or_else(
+            || Overlay {
+                basis_t: self.t,
+                items: BTreeSet::new(),
+            },
+            |overlay| Overlay {
+                basis_t: overlay.basis_t,
+                items: overlay.items.clone(),
+            },
+        );
+        overlay.items.extend(items);
+        Ok(TransactionResult {
+            connection: self.connection,
+            before: self.clone(),
+            after: Self {
+                t: record.t,
+                overlay: Some(Arc::new(overlay)),
+                ..self.clone()
+            },
+            data,
+            tempids,
+        })
+    }
+
+    /// Get the [datoms](crate::Datom) this database includes within a
+    /// range of keys
+    fn range(&self, r: Range<&[u8]>) -> Result<DatomIterator<'connection>, QueryError> {
+        let items = self.connection.storage.range(r.clone())?;
+        let items: ItemIterator<'connection> = match &self.overlay {
+            None => items,
+            Some(overlay) => {
+                // Later transactions in storage aren't part of the
+                // speculative history
+                let basis_t = overlay.basis_t;
+                let items = items.filter(move |item| {
+                    item.as_ref().map_or(true, |item| {
+                        deserialize_unknown(item).map_or(true, |(datom, _)| datom.t <= basis_t)
+                    })
+                });
+                // The overlay is owned by this database, so its items
+                // are copied out for the iterator to own
+                #[allow(clippy::needless_collect)]
+                let proposed: Vec<Item> = overlay
+                    .items
+                    .range::<[u8], _>((Bound::Included(r.start), Bound::Excluded(r.end)))
+                    .cloned()
+                    .collect();
+                let proposed = proposed.into_iter().map(Ok::<Item, StorageError>);
+                Box::new(MergeIters::new(items, proposed).map(|x| x.0))
+            }
+        };
+        let datoms = DatomIterator::new(items, self.since, self.t);
+        let Some(filter) = self.filter.clone() else {
+            return Ok(datoms);
+        };
+        let db = self.unfiltered();
+        Ok(datoms.filtered(Box::new(move |datom| filter(&db, datom))))
+    }
+
     /// Get all [datoms](crate::Datom) in the given index
     pub fn datoms(&self, index: Index) -> Result<DatomIterator<'connection>, QueryError> {
-        Ok(DatomIterator::new(
-            self.connection
-                .storage
-                .range(range_slice(&index_range(index)))?,
-            self.t,
-        ))
+        self.range(range_slice(&index_range(index)))
     }
 
     /// Get all [datoms](crate::Datom) in the
     /// [EAVT index](crate::Index::EAVT) for the given entity
     pub fn datoms_for_entity(&self, entity: ID) -> Result<DatomIterator<'connection>, QueryError> {
-        Ok(DatomIterator::new(
-            self.connection
-                .storage
-                .range(range_slice(&eavt_entity_range(entity)))?,
-            self.t,
-        ))
+        self.range(vec_range_slice(&eavt_entity_range(entity)))
     }
 
     /// Get all [datoms](crate::Datom) in the
@@ -48,12 +360,18 @@ impl<'connection, S: Storage> Database<'connection, S> {
         entity: ID,
         attribute: ID,
     ) -> Result<DatomIterator<'connection>, QueryError> {
-        Ok(DatomIterator::new(
-            self.connection
-                .storage
-                .range(range_slice(&eavt_entity_attribute_range(entity, attribute)))?,
-            self.t,
-        ))
+        self.range(vec_range_slice(&eavt_entity_attribute_range(
+            entity, attribute,
+        )))
+    }
+
+    /// Get all [datoms](crate::Datom) in the
+    /// [AEVT index](crate::Index::AEVT) for the given attribute
+    pub fn datoms_for_attribute(
+        &self,
+        attribute: ID,
+    ) -> Result<DatomIterator<'connection>, QueryError> {
+        self.range(vec_range_slice(&aevt_attribute_range(attribute)))
     }
 
     /// Get all [datoms](crate::Datom) in the
@@ -64,14 +382,15 @@ impl<'connection, S: Storage> Database<'connection, S> {
         attribute: ID,
         value: Value,
     ) -> Result<DatomIterator<'connection>, QueryError> {
-        Ok(DatomIterator::new(
-            self.connection
-                .storage
-                .range(vec_range_slice(&avet_attribute_value_range(
-                    attribute, value,
-                )))?,
-            self.t,
-        ))
+        self.range(vec_range_slice(&avet_attribute_value_range(
+            attribute, value,
+        )))
+    }
+
+    /// Get all [datoms](crate::Datom) in the
+    /// [VAET index](crate::Index::VAET) for the given value
+    pub fn datoms_for_value(&self, value: Value) -> Result<DatomIterator<'connection>, QueryError> {
+        self.range(vec_range_slice(&vaet_value_range(value)))
     }
 
     /// Get all [datoms](crate::Datom) in the
@@ -82,23 +401,333 @@ impl<'connection, S: Storage> Database<'connection, S> {
         value: Value,
         attribute: ID,
     ) -> Result<DatomIterator<'connection>, QueryError> {
-        Ok(DatomIterator::new(
-            self.connection
-                .storage
-                .range(vec_range_slice(&vaet_value_attribute_range(
-                    value, attribute,
-                )))?,
-            self.t,
-        ))
+        self.range(vec_range_slice(&vaet_value_attribute_range(
+            value, attribute,
+        )))
+    }
+
+    /**
+    Get all [datoms](crate::Datom) in the
+    [AVET index](crate::Index::AVET) for the given attribute whose
+    values are within the given bounds, in value order. The attribute
+    must be [unique](crate::builtin_idents::UNIQUE) or
+    [indexed](crate::builtin_idents::INDEX).
+
+    ```
+    use std::ops::Bound;
+    use datom::{backends::SledStorage, AttributeSchema, AttributeType, Connection, Transaction, Value, ID};
+    let conn = Connection::new(SledStorage::connect_temp()?);
+    let age = AttributeSchema::new()
+        .ident("user/age".into())
+        .value_type(AttributeType::Integer)
+        .index();
+    let age_id = age.id;
+    conn.transact(age)?;
+    let mut tx = Transaction::new();
+    for n in [-4, 17, 18, 25, 30] {
+        tx.add(ID::new().into(), "user/age".into(), n.into());
+    }
+    conn.transact(tx)?;
+    let db = conn.db()?;
+    let ages: Vec<Value> = db
+        .index_range(age_id, Bound::Included(18.into()), Bound::Excluded(30.into()))?
+        .map(|datom| datom.map(|datom| datom.value))
+        .collect::<Result<_, _>>()?;
+    assert_eq!(ages, vec![18.into(), 25.into()]);
+    # Ok::<(), Box<dyn std::error::Error>>(())
+    ```
+    */
+    pub fn index_range(
+        &self,
+        attribute: ID,
+        start: Bound<Value>,
+        end: Bound<Value>,
+    ) -> Result<DatomIterator<'connection>, QueryError> {
+        let schema = self.attribute_schema(attribute.into())?;
+        if !schema.unique && !schema.index {
+            return Err(QueryError::UnindexedAttribute(attribute));
+        }
+        let range = avet_attribute_bounds_range(attribute, start.as_ref(), end.as_ref());
+        if range.start >= range.end {
+            return Ok(DatomIterator::new(
+                Box::new(iter::empty()),
+                self.since,
+                self.t,
+            ));
+        }
+        self.range(vec_range_slice(&range))
+    }
+
+    /**
+    Get the [datoms](crate::Datom) in an [index](crate::Index),
+    starting at the first one which begins with the given components
+    and continuing to the end of the index. The components are given
+    in the index's order, so for [AEVT](crate::Index::AEVT) they're an
+    attribute, then optionally an entity and a value.
+
+    ```
+    use datom::{backends::SledStorage, builtin_idents, Connection, Index, Transaction, ID};
+    let conn = Connection::new(SledStorage::connect_temp()?);
+    let mut tx = Transaction::new();
+    for doc in ["a", "b", "c"] {
+        tx.add(ID::new().into(), builtin_idents::DOC.into(), doc.into());
+    }
+    conn.transact(tx)?;
+    let db = conn.db()?;
+    let first = db
+        .seek_datoms(Index::AEVT, &[builtin_idents::DOC.into()])?
+        .next()
+        .unwrap()?;
+    // Resume the scan after the first entity
+    let rest = db
+        .seek_datoms(Index::AEVT, &[builtin_idents::DOC.into(), first.entity.into()])?
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .skip(1)
+        .take_while(|datom| datom.attribute == builtin_idents::DOC)
+        .count();
+    assert_eq!(rest, 2);
+    # Ok::<(), Box<dyn std::error::Error>>(())
+    ```
+    */
+    pub fn seek_datoms(
+        &self,
+        index: Index,
+        components: &[Value],
+    ) -> Result<DatomIterator<'connection>, QueryError> {
+        let start = seek_key(index, components).ok_or_else(|| {
+            QueryError::InvalidInput(format!("{:?} can't be sought by {:?}", index, components))
+        })?;
+        let end = index_range(index).end;
+        self.range(&start[..]..&end[..])
     }
 
     /// Get an entity
     pub fn entity(&self, entity: EID) -> Result<Entity<'connection, S>, QueryError> {
         let entity = entity.resolve(self)?;
+        // Entities see every current datom, apart from those filtered
+        // out
         Ok(Entity {
-            connection: self.connection,
-            t: self.t,
+            db: Self {
+                since: None,
+                history: false,
+                ..self.clone()
+            },
             id: entity,
         })
     }
+
+    /// Get the schema of an attribute
+    pub fn attribute_schema(&self, attribute: EID) -> Result<AttributeSchema, QueryError> {
+        let entity = self.unfiltered().entity(attribute)?;
+        let get = |attr: ID| entity.get_with_options(attr.into(), true, true);
+        let mut schema = AttributeSchema::new().set_id(entity.id);
+        if let EntityResult::Value(Value::String(ident)) = get(builtin_idents::IDENT)? {
+            schema = schema.ident(ident);
+        }
+        if get(builtin_idents::CARDINALITY)? == Value::ID(builtin_idents::CARDINALITY_MANY) {
+            schema = schema.many();
+        }
+        if let EntityResult::Value(Value::ID(t)) = get(builtin_idents::VALUE_TYPE)? {
+            if let Ok(t) = AttributeType::try_from(t) {
+                schema = schema.value_type(t);
+            }
+        }
+        if let EntityResult::Value(Value::String(doc)) = get(builtin_idents::DOC)? {
+            schema = schema.doc(doc);
+        }
+        if get(builtin_idents::UNIQUE)? == Value::Boolean(true) {
+            schema = schema.unique();
+        }
+        if get(builtin_idents::UNIQUE_IDENTITY)? == Value::Boolean(true) {
+            schema = schema.identity();
+        }
+        if get(builtin_idents::INDEX)? == Value::Boolean(true) {
+            schema = schema.index();
+        }
+        if get(builtin_idents::IS_COMPONENT)? == Value::Boolean(true) {
+            schema = schema.component();
+        }
+        Ok(schema)
+    }
+
+    /// Scan the most selective index available for
+    /// [datoms](crate::Datom) which could match the given components,
+    /// returning the scan and the index it's of
+    fn scan(
+        &self,
+        entity: Option<ID>,
+        attribute: Option<ID>,
+        value: Option<&Value>,
+        cache: &mut SchemaCache,
+    ) -> Result<(DatomIterator<'connection>, Index), QueryError> {
+        let attribute_schema = attribute.map(|a| schema(self, a, cache)).transpose()?;
+        let indexed = attribute_schema
+            .as_ref()
+            .map_or(false, |s| s.unique || s.index);
+        let is_ref = attribute_schema
+            .as_ref()
+            .map_or(false, |s| s.value_type == Some(AttributeType::Ref));
+        Ok(match (entity, attribute, value) {
+            (Some(e), Some(a), _) => (self.datoms_for_entity_attribute(e, a)?, Index::EAVT),
+            (Some(e), None, _) => (self.datoms_for_entity(e)?, Index::EAVT),
+            (None, Some(a), Some(v)) if indexed => (
+                self.datoms_for_attribute_value(a, v.to_owned())?,
+                Index::AVET,
+            ),
+            (None, Some(a), Some(v @ Value::ID(_))) if is_ref => (
+                self.datoms_for_value_attribute(v.to_owned(), a)?,
+                Index::VAET,
+            ),
+            (None, Some(a), _) => (self.datoms_for_attribute(a)?, Index::AEVT),
+            (None, None, _) => (self.datoms(Index::EAVT)?, Index::EAVT),
+        })
+    }
+
+    /// Get every assertion and retraction in a database which matches
+    /// the given components
+    pub(crate) fn history_datoms(
+        &self,
+        entity: Option<ID>,
+        attribute: Option<ID>,
+        value: Option<&Value>,
+        cache: &mut SchemaCache,
+    ) -> Result<Vec<Datom>, QueryError> {
+        let (datoms, _) = self.scan(entity, attribute, value, cache)?;
+        let mut res = vec![];
+        for datom in datoms {
+            let datom = datom?;
+            if entity.map_or(true, |e| datom.entity == e)
+                && attribute.map_or(true, |a| datom.attribute == a)
+                && value.map_or(true, |v| &datom.value == v)
+            {
+                res.push(datom);
+            }
+        }
+        Ok(res)
+    }
+
+    /// Get the [datoms](crate::Datom) currently asserted in a database
+    /// which match the given components, using the most selective index
+    /// available
+    pub(crate) fn current_datoms(
+        &self,
+        entity: Option<ID>,
+        attribute: Option<ID>,
+        value: Option<&Value>,
+        cache: &mut SchemaCache,
+    ) -> Result<Vec<Datom>, QueryError> {
+        let (datoms, index) = self.scan(entity, attribute, value, cache)?;
+        // The EAVT and AEVT scans above see every value of each
+        // entity-attribute pair they touch, so they can tell when a
+        // cardinality-one value has been superseded. AVET and VAET scans
+        // only see the requested value, so that has to be checked
+        // separately.
+        let complete = matches!(index, Index::EAVT | Index::AEVT);
+        let mut latest_t: HashMap<(ID, ID), u64> = HashMap::new();
+        let mut positions: HashMap<(ID, ID, Value), usize> = HashMap::new();
+        let mut latest: Vec<Datom> = vec![];
+        for datom in datoms {
+            let datom = datom?;
+            if entity.map_or(false, |e| datom.entity != e)
+                || attribute.map_or(false, |a| datom.attribute != a)
+            {
+                continue;
+            }
+            let t = latest_t
+                .entry((datom.entity, datom.attribute))
+                .or_insert(datom.t);
+            *t = datom.t.max(*t);
+            if value.map_or(false, |v| &datom.value != v) {
+                continue;
+            }
+            match positions.entry((datom.entity, datom.attribute, datom.value.clone())) {
+                Entry::Occupied(o) => {
+                    let existing = &mut latest[*o.get()];
+                    if datom.t >= existing.t {
+                        *existing = datom;
+                    }
+                }
+                Entry::Vacant(v) => {
+                    v.insert(latest.len());
+                    latest.push(datom);
+                }
+            }
+        }
+        let mut res = vec![];
+        for datom in latest {
+            if datom.datom_type == DatomType::Retraction {
+                continue;
+            }
+            if !schema(self, datom.attribute, cache)?.many {
+                let t = if complete {
+                    latest_t.get(&(datom.entity, datom.attribute)).copied()
+                } else {
+                    self.datoms_for_entity_attribute(datom.entity, datom.attribute)?
+                        .try_fold(None, |t, d| d.map(|d| t.max(Some(d.t))))?
+                };
+                if t != Some(datom.t) {
+                    continue;
+                }
+            }
+            res.push(datom);
+        }
+        Ok(res)
+    }
+
+    /// Run a [Query] against this database, with one [QueryInput] for
+    /// each of the query's `:in` bindings
+    ///
+    /// ```
+    /// use datom::{backends::RedBlackTreeSetStorage, Connection, Query, QueryResult, Transaction, ID};
+    ///
+    /// let conn = Connection::new(RedBlackTreeSetStorage::new());
+    /// let username = ID::new();
+    /// let user = ID::new();
+    /// let mut tx = Transaction::new();
+    /// tx.add(user.into(), username.into(), "pmc".into());
+    /// conn.transact(tx)?;
+    ///
+    /// let query: Query = format!("[:find ?e . :where [?e #uuid \"{}\" \"pmc\"]]", username).parse()?;
+    /// let db = conn.db()?;
+    /// assert_eq!(db.query(&query, &[])?, QueryResult::Scalar(Some(user.into())));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn query(&self, query: &Query, inputs: &[QueryInput]) -> Result<QueryResult, QueryError> {
+        query.execute(self, inputs)
+    }
+
+    /// Pull a tree of attributes from an entity, as described by a
+    /// [PullPattern]
+    ///
+    /// ```
+    /// use datom::{backends::RedBlackTreeSetStorage, Connection, PullPattern, PullValue, Transaction, ID};
+    ///
+    /// let conn = Connection::new(RedBlackTreeSetStorage::new());
+    /// let user = ID::new();
+    /// let mut tx = Transaction::new();
+    /// tx.add(user.into(), "db/doc".into(), "A user".into());
+    /// conn.transact(tx)?;
+    ///
+    /// let pattern: PullPattern = "[:db/id :db/doc]".parse()?;
+    /// let pulled = conn.db()?.pull(&pattern, user.into())?;
+    /// assert_eq!(pulled["db/id"], PullValue::Value(user.into()));
+    /// assert_eq!(pulled["db/doc"], PullValue::Value("A user".into()));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn pull(&self, pattern: &PullPattern, entity: EID) -> Result<PullMap, QueryError> {
+        pattern.pull(self, entity.resolve(self)?)
+    }
+
+    /// Pull the same [PullPattern] from many entities
+    pub fn pull_many(
+        &self,
+        pattern: &PullPattern,
+        entities: Vec<EID>,
+    ) -> Result<Vec<PullMap>, QueryError> {
+        entities
+            .into_iter()
+            .map(|entity| self.pull(pattern, entity))
+            .collect()
+    }
 }
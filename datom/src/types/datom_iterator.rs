@@ -2,35 +2,67 @@
 // SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
 // SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
 
-use crate::{serial::deserialize_unknown, storage::ItemIterator, Datom};
+use crate::{
+    serial::deserialize_unknown,
+    storage::{Item, ItemIterator},
+    ConnectionError, Datom, StorageError,
+};
 
-/// An iterator over [Datom]s
+/// A predicate deciding which datoms a [DatomIterator] includes
+type DatomFilter<'s> = Box<dyn Fn(&Datom) -> bool + 's>;
+
+/// An iterator over [Datom]s, which yields an error and carries on if
+/// an item can't be read
 pub struct DatomIterator<'s> {
     iter: ItemIterator<'s>,
+    since: Option<u64>,
     t: u64,
+    filter: Option<DatomFilter<'s>>,
 }
 
 impl<'s> DatomIterator<'s> {
-    pub(crate) fn new(iter: ItemIterator<'s>, t: u64) -> Self {
-        Self { iter, t }
+    /// Iterate over the datoms in `iter` from transactions after
+    /// `since`, if given, up to and including `t`
+    pub(crate) fn new(iter: ItemIterator<'s>, since: Option<u64>, t: u64) -> Self {
+        Self {
+            iter,
+            since,
+            t,
+            filter: None,
+        }
+    }
+
+    /// Only include the datoms `filter` returns `true` for
+    pub(crate) fn filtered(self, filter: DatomFilter<'s>) -> Self {
+        Self {
+            filter: Some(filter),
+            ..self
+        }
+    }
+
+    /// Read the datom in an item, or [None] if it isn't included
+    fn datom(&self, item: Result<Item, StorageError>) -> Result<Option<Datom>, ConnectionError> {
+        let (datom, _) = deserialize_unknown(&item?).ok_or(ConnectionError::InvalidData)?;
+        Ok(self.includes(&datom).then_some(datom))
+    }
+
+    fn includes(&self, datom: &Datom) -> bool {
+        datom.t <= self.t
+            && self.since.map_or(true, |since| datom.t > since)
+            && self.filter.as_ref().map_or(true, |filter| filter(datom))
     }
 }
 
 impl<'s> Iterator for DatomIterator<'s> {
-    type Item = Datom;
+    type Item = Result<Datom, ConnectionError>;
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
-            match self.iter.next() {
-                None => return None,
-                Some(Err(_)) => continue,
-                Some(Ok(k)) => {
-                    let bytes: &[u8] = &k;
-                    let (datom, _) = deserialize_unknown(bytes)?;
-                    if datom.t <= self.t {
-                        return Some(datom);
-                    }
-                }
+            let item = self.iter.next()?;
+            match self.datom(item) {
+                Ok(Some(datom)) => return Some(Ok(datom)),
+                Ok(None) => continue,
+                Err(e) => return Some(Err(e)),
             }
         }
     }
@@ -39,16 +71,11 @@ impl<'s> Iterator for DatomIterator<'s> {
 impl<'s> DoubleEndedIterator for DatomIterator<'s> {
     fn next_back(&mut self) -> Option<Self::Item> {
         loop {
-            match self.iter.next_back() {
-                None => return None,
-                Some(Err(_)) => continue,
-                Some(Ok(k)) => {
-                    let bytes: &[u8] = &k;
-                    let (datom, _) = deserialize_unknown(bytes)?;
-                    if datom.t <= self.t {
-                        return Some(datom);
-                    }
-                }
+            let item = self.iter.next_back()?;
+            match self.datom(item) {
+                Ok(Some(datom)) => return Some(Ok(datom)),
+                Ok(None) => continue,
+                Err(e) => return Some(Err(e)),
             }
         }
     }
@@ -2,9 +2,10 @@
 // SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
 // SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
 
-use std::cmp::Ordering;
-
-use crate::{builtin_idents, storage::Storage, Database, Datom, QueryError, Value, ID};
+use crate::{
+    builtin_idents, schema_cache::SchemaCache, storage::Storage, Database, QueryError, TempID,
+    TempIDs, TransactionRecord, Value, ID,
+};
 
 /**
 An un-resolved entity [ID], which can be used to resolve entities by
@@ -28,10 +29,28 @@ pub enum EID {
         /// Value
         Value,
     ),
+    /// A [temporary ID](TempID) for an entity being created in a
+    /// [Transaction](crate::Transaction)
+    Temp(TempID),
+    /**
+    The entity for the transaction this [EID] is used in, which can be
+    given attributes such as who made the transaction and why
+    */
+    Tx,
 }
 
-fn by_t(a: &Datom, b: &Datom) -> Ordering {
-    a.t.cmp(&b.t)
+/// Find the entity which currently holds a value for a unique
+/// attribute
+fn current_holder<S: Storage>(
+    db: &Database<'_, S>,
+    attribute: ID,
+    value: &Value,
+) -> Result<Option<ID>, QueryError> {
+    Ok(db
+        .unfiltered()
+        .current_datoms(None, Some(attribute), Some(value), &mut SchemaCache::new())?
+        .first()
+        .map(|datom| datom.entity))
 }
 
 impl EID {
@@ -43,10 +62,56 @@ impl EID {
         Self::Unique(eid.into(), val)
     }
 
-    /// Resolve this [EID] into its [ID] according to a [Database]
+    /**
+    Create an [EID] for a [temporary ID](TempID)
+
+    ```
+    use datom::{TempID, EID};
+    assert_eq!(EID::temp("alice"), EID::Temp(TempID::Named("alice".to_string())));
+    ```
+    */
+    pub fn temp(id: impl Into<TempID>) -> Self {
+        Self::Temp(id.into())
+    }
+
+    /**
+    Resolve this [EID] into its [ID] according to a [Database]
+
+    [Temporary IDs](Self::Temp) and [the transaction entity](Self::Tx)
+    only exist within a transaction, and never resolve here.
+    */
     pub fn resolve<'c, S: Storage>(&self, db: &Database<'c, S>) -> Result<ID, QueryError> {
+        self.resolve_inner(db, None)
+    }
+
+    /**
+    Resolve this [EID] into its [ID] according to a [Database], as
+    part of the transaction with the given t-value, allocating a fresh
+    [ID] for any [temporary ID](Self::Temp) which isn't in `tempids`
+    yet
+    */
+    pub fn resolve_in_transaction<'c, S: Storage>(
+        &self,
+        db: &Database<'c, S>,
+        t: u64,
+        tempids: &mut TempIDs,
+    ) -> Result<ID, QueryError> {
+        self.resolve_inner(db, Some((t, tempids)))
+    }
+
+    fn resolve_inner<'c, S: Storage>(
+        &self,
+        db: &Database<'c, S>,
+        tx: Option<(u64, &mut TempIDs)>,
+    ) -> Result<ID, QueryError> {
         match self {
             Self::Resolved(id) => Ok(*id),
+            Self::Temp(tempid) => tx
+                .map(|(_, tempids)| *tempids.entry(tempid.to_owned()).or_insert_with(ID::new))
+                .ok_or_else(|| QueryError::UnresolvedEID(self.clone())),
+            Self::Tx => tx
+                .map(|(t, _)| TransactionRecord::id_for_t(t))
+                .ok_or_else(|| QueryError::UnresolvedEID(self.clone())),
             Self::Ident(ident_str) => {
                 if let Some(entity) = builtin_idents::BUILTIN_ENTITIES_BY_IDENT.get(ident_str) {
                     if let Some(Value::ID(id)) = entity.get(&builtin_idents::ID) {
@@ -54,17 +119,13 @@ impl EID {
                     }
                 }
                 let ident_val = Value::from(ident_str.as_str());
-                db.datoms_for_attribute_value(builtin_idents::IDENT, ident_val)?
-                    .max_by(by_t)
-                    .map(|datom| datom.entity)
+                current_holder(db, builtin_idents::IDENT, &ident_val)?
                     .ok_or_else(|| QueryError::UnresolvedEID(self.clone()))
             }
             Self::InternedIdent(ident_str) => Self::Ident(ident_str.to_string()).resolve(db),
             Self::Unique(attr_eid, val) => {
-                let attr_id = attr_eid.resolve(db)?;
-                db.datoms_for_attribute_value(attr_id, val.to_owned())?
-                    .max_by(by_t)
-                    .map(|datom| datom.entity)
+                let attr_id = attr_eid.resolve_inner(db, tx)?;
+                current_holder(db, attr_id, val)?
                     .ok_or_else(|| QueryError::UnresolvedEID(self.clone()))
             }
         }
@@ -5,24 +5,41 @@
 use std::{collections::HashSet, hash::Hash};
 
 use crate::{
-    builtin_idents, storage::Storage, AttributeIterator, Connection, Datom, DatomType,
-    EntityResult, QueryError, Value, EID, ID,
+    builtin_idents, storage::Storage, AttributeIterator, Database, Datom, DatomType, EntityResult,
+    QueryError, Value, EID, ID,
 };
 
-/// An entity in a database
-#[derive(Debug, Eq, Clone, Copy)]
+/// An entity in a database, which is as cheap to clone as the
+/// [Database] it's in
+///
+/// Entities compare by their connection, t-value, and ID alone. They
+/// can still reach the lock their [Connection](crate::Connection)
+/// holds while transacting, so clippy's `mutable_key_type` lint fires
+/// on collections keyed by them or by [EntityResult]s, even though
+/// nothing they compare can change.
+#[derive(Debug)]
 pub struct Entity<'connection, S: Storage> {
-    pub(crate) connection: &'connection Connection<S>,
-    pub(crate) t: u64,
+    pub(crate) db: Database<'connection, S>,
     pub(crate) id: ID,
 }
 
+impl<'connection, S: Storage> Clone for Entity<'connection, S> {
+    fn clone(&self) -> Self {
+        Self {
+            db: self.db.clone(),
+            id: self.id,
+        }
+    }
+}
+
 impl<'connection, S: Storage> PartialEq<Self> for Entity<'connection, S> {
     fn eq(&self, other: &Self) -> bool {
-        self.connection == other.connection && self.t == other.t && self.id == other.id
+        self.db.connection == other.db.connection && self.db.t == other.db.t && self.id == other.id
     }
 }
 
+impl<'connection, S: Storage> Eq for Entity<'connection, S> {}
+
 impl<'connection, S: Storage> Entity<'connection, S> {
     /// Get the ID of this entity
     pub const fn id(&self) -> &ID {
@@ -36,12 +53,12 @@ impl<'connection, S: Storage> Entity<'connection, S> {
         skip_cardinality: bool,
         skip_type: bool,
     ) -> Result<EntityResult<'connection, S>, QueryError> {
-        let db = self.connection.as_of(self.t)?;
-        let attribute = attribute.resolve(&db)?;
+        let db = &self.db;
+        let attribute = attribute.resolve(db)?;
         if attribute == builtin_idents::ID {
             return Ok(Value::from(self.id).into());
         }
-        let attribute_ent = db.entity(attribute.into())?;
+        let attribute_ent = db.unfiltered().entity(attribute.into())?;
         let is_repeated = !skip_cardinality
             && attribute_ent
                 .get_with_options(builtin_idents::CARDINALITY.into(), true, false)?
@@ -68,6 +85,7 @@ impl<'connection, S: Storage> Entity<'connection, S> {
             // all additions and retractions will be in time-order.
             let mut values = HashSet::new();
             for datom in datoms {
+                let datom = datom?;
                 if datom.datom_type == DatomType::Retraction {
                     values.remove(&datom.value);
                 } else {
@@ -90,8 +108,13 @@ impl<'connection, S: Storage> Entity<'connection, S> {
                 .collect();
             EntityResult::Repeated(res?)
         } else {
+            // A replaced value is retracted in the same transaction
+            // that asserts its replacement, so on a tie the addition
+            // is the current value.
             db.datoms_for_entity_attribute(self.id, attribute)?
-                .max_by(|a, b| a.t.cmp(&b.t))
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter()
+                .max_by_key(|datom| (datom.t, datom.datom_type == DatomType::Addition))
                 .map(|x| -> Result<EntityResult<'connection, S>, QueryError> {
                     if x.datom_type == DatomType::Retraction {
                         Ok(EntityResult::NotFound)
@@ -127,10 +150,10 @@ impl<'connection, S: Storage> Entity<'connection, S> {
     /// Get the entities with this entity as a value on an attribute
     /// (reverse lookup)
     pub fn reverse_get(&self, attribute: EID) -> Result<EntityResult<'connection, S>, QueryError> {
-        let db = self.connection.as_of(self.t)?;
-        let attribute = attribute.resolve(&db)?;
+        let db = &self.db;
+        let attribute = attribute.resolve(db)?;
         let datoms = db.datoms_for_value_attribute(self.id().to_owned().into(), attribute)?;
-        let datoms: Vec<Datom> = datoms.collect();
+        let datoms: Vec<Datom> = datoms.collect::<Result<_, _>>()?;
         // The index is sorted in AVET order, so for a given entity
         // all additions and retractions will be in time-order.
         let mut entities = HashSet::new();
@@ -150,14 +173,14 @@ impl<'connection, S: Storage> Entity<'connection, S> {
 
     /// Get the attributes on this entity
     pub fn attributes(&self) -> Result<AttributeIterator<'connection>, QueryError> {
-        let iter = self.connection.as_of(self.t)?.datoms_for_entity(self.id)?;
+        let iter = self.db.datoms_for_entity(self.id)?;
         AttributeIterator::new(iter)
     }
 }
 
 impl<'connection, S: Storage> Hash for Entity<'connection, S> {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        self.t.hash(state);
+        self.db.t.hash(state);
         self.id.hash(state);
     }
 }
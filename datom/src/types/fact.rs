@@ -2,14 +2,16 @@
 // SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
 // SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
 
-use std::str::FromStr;
+use std::{collections::HashSet, str::FromStr};
 
 use datom_bigdecimal::BigDecimal;
 use edn_rs::Edn;
 use num_bigint::BigInt;
 
 use crate::{
-    storage::Storage, Database, Datom, DatomType, EntityResult, TransactionError, Value, EID,
+    schema_cache::{schema, SchemaCache},
+    storage::Storage,
+    Database, Datom, DatomType, EntityResult, TempIDs, TransactionError, Value, EID, ID,
 };
 
 /**
@@ -27,6 +29,18 @@ pub enum Fact {
         /// Value
         Value,
     ),
+    /**
+    Adding a reference to another entity, which (unlike a [Value])
+    may be a [temporary ID](EID::Temp)
+    */
+    AddRef(
+        /// Entity ID
+        EID,
+        /// Attribute ID
+        EID,
+        /// Referenced entity ID
+        EID,
+    ),
     /// Retracting a specific attribute value from an entity
     RetractValue(
         /// Entity ID
@@ -43,49 +57,173 @@ pub enum Fact {
         /// Attribute ID
         EID,
     ),
+    /**
+    Retracting every attribute of an entity and every reference to it,
+    along with any [components](crate::builtin_idents::IS_COMPONENT)
+    of the entity
+    */
+    RetractEntity(
+        /// Entity ID
+        EID,
+    ),
+    /**
+    Setting an attribute's value on an entity, only if its current
+    value is the expected one (or it has no value, if `None` is
+    expected). For cardinality-many attributes, the expected value
+    only needs to be one of the current values, and is swapped for the
+    new one.
+    */
+    Cas(
+        /// Entity ID
+        EID,
+        /// Attribute ID
+        EID,
+        /// Expected current value
+        Option<Value>,
+        /// New value
+        Value,
+    ),
+}
+
+/// Retract an entity, everything referring to it, and its components
+fn retract_entity<S: Storage>(
+    entity: ID,
+    t: u64,
+    db: &Database<'_, S>,
+) -> Result<Vec<Datom>, TransactionError> {
+    let mut cache = SchemaCache::new();
+    let mut retracted = HashSet::new();
+    let mut stack = vec![entity];
+    let mut res = vec![];
+    while let Some(entity) = stack.pop() {
+        if !retracted.insert(entity) {
+            continue;
+        }
+        let mut current = db.current_datoms(Some(entity), None, None, &mut cache)?;
+        for datom in current.iter() {
+            if let Value::ID(component) = datom.value {
+                if schema(db, datom.attribute, &mut cache)?.component {
+                    stack.push(component);
+                }
+            }
+        }
+        let referrers: HashSet<(ID, ID)> = db
+            .datoms_for_value(entity.into())?
+            .map(|datom| datom.map(|datom| (datom.entity, datom.attribute)))
+            .collect::<Result<_, _>>()?;
+        for (referrer, attribute) in referrers {
+            current.append(&mut db.current_datoms(
+                Some(referrer),
+                Some(attribute),
+                Some(&entity.into()),
+                &mut cache,
+            )?);
+        }
+        res.extend(current.into_iter().map(|datom| Datom {
+            t,
+            datom_type: DatomType::Retraction,
+            ..datom
+        }));
+    }
+    Ok(res)
 }
 
 impl Fact {
-    /// Convert this [Fact] into a [Datom], given a [Database]
-    pub fn datom<S: Storage>(
+    /**
+    Convert this [Fact] into the [Datom]s it represents, given a
+    [Database] and the [ID]s already allocated to
+    [temporary IDs](EID::Temp) in this transaction
+    */
+    pub fn datoms<S: Storage>(
         self,
         t: u64,
         db: &Database<'_, S>,
-    ) -> Result<Datom, TransactionError> {
-        match self {
-            Self::Add(entity, attribute, value) => Ok(Datom {
-                entity: entity.resolve(db)?,
-                attribute: attribute.resolve(db)?,
+        tempids: &mut TempIDs,
+    ) -> Result<Vec<Datom>, TransactionError> {
+        let datom = match self {
+            Self::Add(entity, attribute, value) => Datom {
+                entity: entity.resolve_in_transaction(db, t, tempids)?,
+                attribute: attribute.resolve_in_transaction(db, t, tempids)?,
                 value,
                 t,
                 datom_type: DatomType::Addition,
-            }),
-            Self::RetractValue(entity, attribute, value) => Ok(Datom {
-                entity: entity.resolve(db)?,
-                attribute: attribute.resolve(db)?,
+            },
+            Self::AddRef(entity, attribute, value) => Datom {
+                entity: entity.resolve_in_transaction(db, t, tempids)?,
+                attribute: attribute.resolve_in_transaction(db, t, tempids)?,
+                value: value.resolve_in_transaction(db, t, tempids)?.into(),
+                t,
+                datom_type: DatomType::Addition,
+            },
+            Self::RetractValue(entity, attribute, value) => Datom {
+                entity: entity.resolve_in_transaction(db, t, tempids)?,
+                attribute: attribute.resolve_in_transaction(db, t, tempids)?,
                 value,
                 t,
                 datom_type: DatomType::Retraction,
-            }),
+            },
             Self::Retract(entity, attribute) => {
-                let entity = entity.resolve(db)?;
-                let attribute = attribute.resolve(db)?;
+                let entity = entity.resolve_in_transaction(db, t, tempids)?;
+                let attribute = attribute.resolve_in_transaction(db, t, tempids)?;
                 let value = db.entity(entity.into())?.get(attribute.into())?;
-                if let EntityResult::Value(value) = value {
-                    Ok(Datom {
-                        entity,
-                        attribute,
-                        value,
-                        t,
-                        datom_type: DatomType::Retraction,
-                    })
-                } else {
-                    Err(TransactionError::FailedToRetractRepeatedAttribute(
+                let EntityResult::Value(value) = value else {
+                    return Err(TransactionError::FailedToRetractRepeatedAttribute(
                         entity, attribute,
-                    ))
+                    ));
+                };
+                Datom {
+                    entity,
+                    attribute,
+                    value,
+                    t,
+                    datom_type: DatomType::Retraction,
                 }
             }
-        }
+            Self::RetractEntity(entity) => {
+                return retract_entity(entity.resolve_in_transaction(db, t, tempids)?, t, db)
+            }
+            Self::Cas(entity, attribute, expected, new) => {
+                let entity = entity.resolve_in_transaction(db, t, tempids)?;
+                let attribute = attribute.resolve_in_transaction(db, t, tempids)?;
+                let current = db.current_datoms(
+                    Some(entity),
+                    Some(attribute),
+                    None,
+                    &mut SchemaCache::new(),
+                )?;
+                let matches = expected.as_ref().map_or(current.is_empty(), |expected| {
+                    current.iter().any(|datom| &datom.value == expected)
+                });
+                if !matches {
+                    let actual = current.into_iter().next().map(|datom| datom.value);
+                    return Err(TransactionError::CasFailed(
+                        entity, attribute, expected, actual,
+                    ));
+                }
+                if expected.as_ref() == Some(&new) {
+                    return Ok(vec![]);
+                }
+                let addition = Datom {
+                    entity,
+                    attribute,
+                    value: new,
+                    t,
+                    datom_type: DatomType::Addition,
+                };
+                return Ok(match expected {
+                    Some(value) => vec![
+                        Datom {
+                            value,
+                            datom_type: DatomType::Retraction,
+                            ..addition.clone()
+                        },
+                        addition,
+                    ],
+                    None => vec![addition],
+                });
+            }
+        };
+        Ok(vec![datom])
     }
 
     /// Create a fact from an EDN fact representation
@@ -0,0 +1,109 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+use std::collections::HashSet;
+
+use edn_rs::Edn;
+
+use crate::{
+    datalog::{parse_variable, Bindings},
+    QueryError, QueryResult, Value,
+};
+
+/// The shape of a query's results, from its `:find` clause
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FindSpec {
+    /// `:find ?a ?b`, a set of tuples
+    Relation(Vec<String>),
+    /// `:find [?a ...]`, a set of values
+    Collection(String),
+    /// `:find [?a ?b]`, a single tuple
+    Tuple(Vec<String>),
+    /// `:find ?a .`, a single value
+    Scalar(String),
+}
+
+fn project(row: &Bindings, vars: &[String]) -> Vec<Value> {
+    vars.iter()
+        .map(|v| row.get(v).expect("find variables are checked").to_owned())
+        .collect()
+}
+
+impl FindSpec {
+    /// The variables this find spec returns
+    pub fn variables(&self) -> Vec<&str> {
+        match self {
+            Self::Relation(vars) | Self::Tuple(vars) => vars.iter().map(String::as_str).collect(),
+            Self::Collection(var) | Self::Scalar(var) => vec![var],
+        }
+    }
+
+    /// Shape the rows of a query's final relation into a
+    /// [QueryResult], removing duplicates while preserving order
+    pub(crate) fn project(&self, rows: Vec<Bindings>) -> QueryResult {
+        match self {
+            Self::Relation(vars) => {
+                let mut seen = HashSet::new();
+                QueryResult::Relation(
+                    rows.iter()
+                        .map(|row| project(row, vars))
+                        .filter(|tuple| seen.insert(tuple.clone()))
+                        .collect(),
+                )
+            }
+            Self::Collection(var) => {
+                let mut seen = HashSet::new();
+                QueryResult::Collection(
+                    rows.iter()
+                        .map(|row| project(row, std::slice::from_ref(var)).remove(0))
+                        .filter(|value| seen.insert(value.clone()))
+                        .collect(),
+                )
+            }
+            Self::Tuple(vars) => QueryResult::Tuple(rows.first().map(|row| project(row, vars))),
+            Self::Scalar(var) => QueryResult::Scalar(
+                rows.first()
+                    .map(|row| project(row, std::slice::from_ref(var)).remove(0)),
+            ),
+        }
+    }
+
+    /// Create a find spec from the EDN elements following `:find`
+    pub fn from_edn(elements: Vec<Edn>) -> Result<Self, QueryError> {
+        let mut it = elements.into_iter().peekable();
+        if let Some(Edn::Vector(v)) = it.peek() {
+            let v = v.to_owned().to_vec();
+            it.next();
+            if it.next().is_some() {
+                return Err(QueryError::InvalidQuery(
+                    "collection and tuple find specs must be the only element in :find".to_string(),
+                ));
+            }
+            return if v.len() == 2 && v[1] == Edn::Symbol("...".to_string()) {
+                Ok(Self::Collection(parse_variable(v[0].to_owned())?))
+            } else {
+                Ok(Self::Tuple(
+                    v.into_iter()
+                        .map(parse_variable)
+                        .collect::<Result<_, _>>()?,
+                ))
+            };
+        }
+        let elements: Vec<Edn> = it.collect();
+        if elements.len() == 2 && elements[1] == Edn::Symbol(".".to_string()) {
+            return Ok(Self::Scalar(parse_variable(elements[0].to_owned())?));
+        }
+        if elements.is_empty() {
+            return Err(QueryError::InvalidQuery(
+                ":find must name at least one variable".to_string(),
+            ));
+        }
+        Ok(Self::Relation(
+            elements
+                .into_iter()
+                .map(parse_variable)
+                .collect::<Result<_, _>>()?,
+        ))
+    }
+}
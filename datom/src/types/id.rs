@@ -3,6 +3,7 @@
 // SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
 
 use std::{
+    fmt::Display,
     str::FromStr,
     time::{SystemTime, UNIX_EPOCH},
 };
@@ -70,9 +71,9 @@ impl FromStr for ID {
     }
 }
 
-impl ToString for ID {
-    fn to_string(&self) -> String {
-        self.0.to_string()
+impl Display for ID {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
     }
 }
 
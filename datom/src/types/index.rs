@@ -29,7 +29,9 @@ pub enum Index {
     /**
     Attribute-Value-Entity-T index
 
-    Provides efficient access to unique entities
+    Provides efficient access to unique entities, and ordered access
+    to the values of [indexed](crate::builtin_idents::INDEX)
+    attributes.
     */
     AVET,
     /**
@@ -0,0 +1,94 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+use edn_rs::Edn;
+
+use crate::{
+    datalog::{parse_variable, Bindings},
+    QueryError, QueryInput, Value,
+};
+
+/// How a query's `:in` clause binds a [QueryInput] to variables
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InputBinding {
+    /// `?x`, binding a single value
+    Scalar(String),
+    /// `[?x ?y]`, binding each element of a single tuple
+    Tuple(Vec<String>),
+    /// `[?x ...]`, binding each value of a collection in turn
+    Collection(String),
+    /// `[[?x ?y]]`, binding each tuple of a relation in turn
+    Relation(Vec<String>),
+}
+
+fn bind_tuple(vars: &[String], tuple: &[Value]) -> Result<Bindings, QueryError> {
+    if vars.len() != tuple.len() {
+        return Err(QueryError::InvalidInput(format!(
+            "expected a tuple of {} values, got {}",
+            vars.len(),
+            tuple.len()
+        )));
+    }
+    Ok(vars.iter().cloned().zip(tuple.iter().cloned()).collect())
+}
+
+impl InputBinding {
+    /// The variables this binding binds
+    pub fn variables(&self) -> Vec<&str> {
+        match self {
+            Self::Scalar(var) | Self::Collection(var) => vec![var],
+            Self::Tuple(vars) | Self::Relation(vars) => vars.iter().map(String::as_str).collect(),
+        }
+    }
+
+    /// Turn an input into the rows it contributes to a query's
+    /// working relation
+    pub(crate) fn bind(&self, input: &QueryInput) -> Result<Vec<Bindings>, QueryError> {
+        match (self, input) {
+            (Self::Scalar(var), QueryInput::Scalar(value)) => {
+                Ok(vec![[(var.to_owned(), value.to_owned())].into()])
+            }
+            (Self::Tuple(vars), QueryInput::Tuple(tuple)) => Ok(vec![bind_tuple(vars, tuple)?]),
+            (Self::Collection(var), QueryInput::Collection(values)) => Ok(values
+                .iter()
+                .map(|value| [(var.to_owned(), value.to_owned())].into())
+                .collect()),
+            (Self::Relation(vars), QueryInput::Relation(tuples)) => {
+                tuples.iter().map(|tuple| bind_tuple(vars, tuple)).collect()
+            }
+            (binding, input) => Err(QueryError::InvalidInput(format!(
+                "{:?} can't be bound by {:?}",
+                input, binding
+            ))),
+        }
+    }
+
+    /// Create a binding from its EDN representation
+    pub fn from_edn(edn: Edn) -> Result<Self, QueryError> {
+        match edn {
+            Edn::Vector(v) => {
+                let v = v.to_vec();
+                match v.as_slice() {
+                    [Edn::Vector(inner)] => Ok(Self::Relation(
+                        inner
+                            .to_owned()
+                            .to_vec()
+                            .into_iter()
+                            .map(parse_variable)
+                            .collect::<Result<_, _>>()?,
+                    )),
+                    [var, Edn::Symbol(dots)] if dots == "..." => {
+                        Ok(Self::Collection(parse_variable(var.to_owned())?))
+                    }
+                    _ => Ok(Self::Tuple(
+                        v.into_iter()
+                            .map(parse_variable)
+                            .collect::<Result<_, _>>()?,
+                    )),
+                }
+            }
+            other => Ok(Self::Scalar(parse_variable(other)?)),
+        }
+    }
+}
@@ -0,0 +1,16 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+use crate::{Datom, TransactionRecord};
+
+/// A transaction in the [log](crate::Connection::log)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogEntry {
+    /// The transaction's record
+    pub record: TransactionRecord,
+    /// The datoms the transaction asserted and retracted, including
+    /// those on its [transaction entity](TransactionRecord::id), in
+    /// entity-attribute-value order
+    pub data: Vec<Datom>,
+}
@@ -0,0 +1,55 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+use crate::{
+    serial::{deserialize_log, deserialize_tr, log_t_range, vec_range_slice},
+    storage::{Item, ItemIterator, Storage},
+    Connection, ConnectionError, LogEntry, StorageError,
+};
+
+/// An iterator over the [transaction log](Connection::log)
+pub struct LogIterator<'connection, S: Storage> {
+    connection: &'connection Connection<S>,
+    records: ItemIterator<'connection>,
+}
+
+impl<'connection, S: Storage> LogIterator<'connection, S> {
+    pub(crate) fn new(
+        connection: &'connection Connection<S>,
+        records: ItemIterator<'connection>,
+    ) -> Self {
+        Self {
+            connection,
+            records,
+        }
+    }
+
+    /// Read the datoms for a transaction record
+    fn entry(&self, record: Result<Item, StorageError>) -> Result<LogEntry, ConnectionError> {
+        let record = deserialize_tr(&record?).ok_or(ConnectionError::InvalidData)?;
+        let data = self
+            .connection
+            .storage
+            .range(vec_range_slice(&log_t_range(record.t)))?
+            .map(|item| deserialize_log(&item?).ok_or(ConnectionError::InvalidData))
+            .collect::<Result<_, _>>()?;
+        Ok(LogEntry { record, data })
+    }
+}
+
+impl<'connection, S: Storage> Iterator for LogIterator<'connection, S> {
+    type Item = Result<LogEntry, ConnectionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = self.records.next()?;
+        Some(self.entry(record))
+    }
+}
+
+impl<'connection, S: Storage> DoubleEndedIterator for LogIterator<'connection, S> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let record = self.records.next_back()?;
+        Some(self.entry(record))
+    }
+}
@@ -40,18 +40,63 @@ pub use self::entity::*;
 mod fact;
 pub use self::fact::*;
 
+mod find_spec;
+pub use self::find_spec::*;
+
 mod id;
 pub use self::id::*;
 
 mod index;
 pub use self::index::*;
 
+mod input_binding;
+pub use self::input_binding::*;
+
+mod log_entry;
+pub use self::log_entry::*;
+
+mod log_iterator;
+pub use self::log_iterator::*;
+
+mod pattern;
+pub use self::pattern::*;
+
+mod pull_attribute;
+pub use self::pull_attribute::*;
+
+mod pull_pattern;
+pub use self::pull_pattern::*;
+
+mod pull_selector;
+pub use self::pull_selector::*;
+
+mod pull_value;
+pub use self::pull_value::*;
+
 mod query_error;
 pub use self::query_error::*;
 
+mod query_input;
+pub use self::query_input::*;
+
+mod query_result;
+pub use self::query_result::*;
+
+mod query;
+pub use self::query::*;
+
 mod storage_error;
 pub use self::storage_error::*;
 
+mod subscription;
+pub use self::subscription::*;
+
+mod temp_id;
+pub use self::temp_id::*;
+
+mod term;
+pub use self::term::*;
+
 mod transaction_error;
 pub use self::transaction_error::*;
 
@@ -64,5 +109,17 @@ pub use self::transaction_result::*;
 mod transaction;
 pub use self::transaction::*;
 
+mod tx_report;
+pub use self::tx_report::*;
+
 mod value;
 pub use self::value::*;
+
+mod watch_iterator;
+pub use self::watch_iterator::*;
+
+mod watch_result;
+pub use self::watch_result::*;
+
+mod watch;
+pub use self::watch::*;
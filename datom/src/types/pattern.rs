@@ -0,0 +1,154 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+use std::collections::HashMap;
+
+use edn_rs::Edn;
+
+use crate::{
+    datalog::Bindings, schema_cache::SchemaCache, storage::Storage, Database, Datom, DatomType,
+    QueryError, Term, TransactionRecord, Value, ID,
+};
+
+/// A data pattern in a query's `:where` clause, matching
+/// [datoms](crate::Datom) by entity, attribute, and value, and
+/// optionally by transaction and whether the datom was added
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pattern {
+    /// The entity position
+    pub entity: Term,
+    /// The attribute position
+    pub attribute: Term,
+    /// The value position
+    pub value: Term,
+    /// The transaction position, matched against the
+    /// [transaction entity](crate::TransactionRecord::id) of the datom
+    pub tx: Term,
+    /// The added position, matched against `true` for additions and
+    /// `false` for retractions
+    pub added: Term,
+}
+
+fn as_id(value: Option<Value>) -> Result<Option<ID>, ()> {
+    match value {
+        None => Ok(None),
+        Some(Value::ID(id)) => Ok(Some(id)),
+        Some(_) => Err(()),
+    }
+}
+
+impl Pattern {
+    /// Create a new [Pattern]
+    pub const fn new(entity: Term, attribute: Term, value: Term) -> Self {
+        Self {
+            entity,
+            attribute,
+            value,
+            tx: Term::Blank,
+            added: Term::Blank,
+        }
+    }
+
+    /// Set the pattern's transaction term
+    #[allow(clippy::missing_const_for_fn)]
+    pub fn tx(mut self, tx: Term) -> Self {
+        self.tx = tx;
+        self
+    }
+
+    /// Set the pattern's added term
+    #[allow(clippy::missing_const_for_fn)]
+    pub fn added(mut self, added: Term) -> Self {
+        self.added = added;
+        self
+    }
+
+    /// The variables this pattern binds
+    pub fn variables(&self) -> impl Iterator<Item = &str> {
+        [
+            &self.entity,
+            &self.attribute,
+            &self.value,
+            &self.tx,
+            &self.added,
+        ]
+        .into_iter()
+        .filter_map(Term::variable)
+    }
+
+    /// Join this pattern against the rows of a working relation,
+    /// producing a row for every matching datom. Against a
+    /// [history](Database::history) database, retractions match too.
+    pub(crate) fn join<S: Storage>(
+        &self,
+        db: &Database<'_, S>,
+        rows: Vec<Bindings>,
+        cache: &mut SchemaCache,
+    ) -> Result<Vec<Bindings>, QueryError> {
+        let entity = self.entity.resolve(db)?;
+        let attribute = self.attribute.resolve(db)?;
+        let value = self.value.resolve(db)?;
+        let tx = self.tx.resolve(db)?;
+        let added = self.added.resolve(db)?;
+        let mut matches: HashMap<[Option<Value>; 3], Vec<Datom>> = HashMap::new();
+        let mut res = vec![];
+        for row in rows {
+            let key = [
+                entity.lookup(&row),
+                attribute.lookup(&row),
+                value.lookup(&row),
+            ];
+            if !matches.contains_key(&key) {
+                let datoms = match (as_id(key[0].clone()), as_id(key[1].clone())) {
+                    (Ok(e), Ok(a)) if db.history => {
+                        db.history_datoms(e, a, key[2].as_ref(), cache)?
+                    }
+                    (Ok(e), Ok(a)) => db.current_datoms(e, a, key[2].as_ref(), cache)?,
+                    _ => vec![],
+                };
+                matches.insert(key.clone(), datoms);
+            }
+            for datom in &matches[&key] {
+                let mut row = row.clone();
+                let tx_value = Value::from(TransactionRecord::id_for_t(datom.t));
+                let added_value = Value::from(datom.datom_type == DatomType::Addition);
+                if entity.unify(&mut row, datom.entity.into())
+                    && attribute.unify(&mut row, datom.attribute.into())
+                    && value.unify(&mut row, datom.value.clone())
+                    && tx.lookup(&row).map_or(true, |v| v == tx_value)
+                    && tx.unify(&mut row, tx_value)
+                    && added.lookup(&row).map_or(true, |v| v == added_value)
+                    && added.unify(&mut row, added_value)
+                {
+                    res.push(row);
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    /// Create a pattern from its EDN representation
+    pub fn from_edn(edn: Edn) -> Result<Self, QueryError> {
+        let Edn::Vector(parts) = edn else {
+            return Err(QueryError::InvalidQuery(format!(
+                "`{}` is not a data pattern",
+                edn
+            )));
+        };
+        let parts = parts.to_vec();
+        if !(3..=5).contains(&parts.len()) {
+            return Err(QueryError::InvalidQuery(
+                "data patterns must have between 3 and 5 terms".to_string(),
+            ));
+        }
+        let mut it = parts.into_iter().map(Term::from_edn);
+        Ok(Self {
+            entity: it.next().expect("length checked")?,
+            attribute: it.next().expect("length checked")?,
+            value: it.next().expect("length checked")?,
+            tx: it.next().transpose()?.unwrap_or(Term::Blank),
+            added: it.next().transpose()?.unwrap_or(Term::Blank),
+        })
+    }
+}
@@ -0,0 +1,177 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+use std::str::FromStr;
+
+use datom_bigdecimal::BigDecimal;
+use edn_rs::Edn;
+use num_bigint::BigInt;
+
+use crate::{PullPattern, QueryError, Value, EID};
+
+/// What to pull from the entities an attribute refers to
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PullNested {
+    /// Pull a sub-pattern from each referenced entity
+    Pattern(PullPattern),
+    /// Pull the enclosing pattern again from each referenced entity, up
+    /// to the given depth, or until a cycle is found if [None]
+    Recurse(Option<usize>),
+}
+
+/// One attribute in a [PullPattern], with its options
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PullAttribute {
+    /// The attribute to pull
+    pub attribute: EID,
+    /// Whether to pull the entities referring to this entity through
+    /// the attribute, rather than the attribute's values
+    pub reverse: bool,
+    /// The maximum number of values to pull for a cardinality-many or
+    /// reverse attribute
+    pub limit: Option<usize>,
+    /// The value to use if the entity doesn't have this attribute
+    pub default: Option<Value>,
+    /// What to pull from referenced entities
+    pub nested: Option<PullNested>,
+}
+
+fn edn_value(edn: Edn) -> Result<Value, QueryError> {
+    match edn {
+        Edn::Str(s) => Ok(s.into()),
+        Edn::Int(i) => Ok(BigInt::from(i).into()),
+        Edn::UInt(u) => Ok(BigInt::from(u).into()),
+        Edn::Double(d) => BigDecimal::from_str(&d.to_string())
+            .map(Value::from)
+            .map_err(|_| QueryError::InvalidQuery(format!("invalid decimal `{}`", d))),
+        Edn::Bool(b) => Ok(b.into()),
+        other => Err(QueryError::InvalidQuery(format!(
+            "`{}` is not a valid default",
+            other
+        ))),
+    }
+}
+
+fn edn_count(edn: Edn) -> Result<Option<usize>, QueryError> {
+    match edn {
+        Edn::UInt(n) => Ok(Some(n)),
+        Edn::Nil => Ok(None),
+        Edn::Symbol(s) if s == "..." => Ok(None),
+        other => Err(QueryError::InvalidQuery(format!(
+            "`{}` is not a valid count",
+            other
+        ))),
+    }
+}
+
+impl PullAttribute {
+    /// Pull an attribute's values
+    pub const fn new(attribute: EID) -> Self {
+        Self {
+            attribute,
+            reverse: false,
+            limit: None,
+            default: None,
+            nested: None,
+        }
+    }
+
+    /// Pull the entities referring to an entity through an attribute
+    pub const fn reverse(attribute: EID) -> Self {
+        Self {
+            attribute,
+            reverse: true,
+            limit: None,
+            default: None,
+            nested: None,
+        }
+    }
+
+    /// Limit the number of values pulled
+    pub const fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Use a default value if the attribute is missing
+    #[allow(clippy::missing_const_for_fn)]
+    pub fn default(mut self, default: Value) -> Self {
+        self.default = Some(default);
+        self
+    }
+
+    /// Pull a sub-pattern from each referenced entity
+    #[allow(clippy::missing_const_for_fn)]
+    pub fn nested(mut self, pattern: PullPattern) -> Self {
+        self.nested = Some(PullNested::Pattern(pattern));
+        self
+    }
+
+    /// Recursively pull the enclosing pattern from each referenced
+    /// entity
+    #[allow(clippy::missing_const_for_fn)]
+    pub fn recurse(mut self, depth: Option<usize>) -> Self {
+        self.nested = Some(PullNested::Recurse(depth));
+        self
+    }
+
+    /// Parse an attribute keyword, such as `:user/friends` or the
+    /// reverse `:user/_friends`
+    pub fn from_keyword(keyword: &str) -> Self {
+        let ident = keyword.trim_start_matches(':');
+        match ident.split_once("/_") {
+            Some((ns, name)) => Self::reverse(EID::Ident(format!("{}/{}", ns, name))),
+            None => Self::new(EID::Ident(ident.to_owned())),
+        }
+    }
+
+    /// Create an attribute spec from its EDN representation, either a
+    /// keyword or an `(:attr :limit n :default v)` expression
+    pub fn from_edn(edn: Edn) -> Result<Self, QueryError> {
+        let parts = match edn {
+            Edn::Key(k) => return Ok(Self::from_keyword(&k)),
+            Edn::List(l) => l.to_vec(),
+            Edn::Vector(v) => v.to_vec(),
+            other => {
+                return Err(QueryError::InvalidQuery(format!(
+                    "`{}` is not a valid attribute spec",
+                    other
+                )))
+            }
+        };
+        let mut it = parts.into_iter();
+        let mut attr = match it.next() {
+            Some(Edn::Key(k)) => Self::from_keyword(&k),
+            _ => {
+                return Err(QueryError::InvalidQuery(
+                    "attribute specs must start with a keyword".into(),
+                ))
+            }
+        };
+        while let Some(option) = it.next() {
+            let arg = it.next().ok_or_else(|| {
+                QueryError::InvalidQuery(format!("`{}` is missing its argument", option))
+            })?;
+            match option {
+                Edn::Key(k) if k == ":limit" => attr.limit = edn_count(arg)?,
+                Edn::Key(k) if k == ":default" => attr.default = Some(edn_value(arg)?),
+                other => {
+                    return Err(QueryError::InvalidQuery(format!(
+                        "unsupported attribute option `{}`",
+                        other
+                    )))
+                }
+            }
+        }
+        Ok(attr)
+    }
+
+    /// Parse the value half of a map spec entry
+    pub(crate) fn nested_from_edn(edn: Edn) -> Result<PullNested, QueryError> {
+        match edn {
+            Edn::Vector(_) => Ok(PullNested::Pattern(PullPattern::from_edn(edn)?)),
+            other => Ok(PullNested::Recurse(edn_count(other)?)),
+        }
+    }
+}
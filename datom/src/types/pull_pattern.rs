@@ -0,0 +1,258 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+use std::{collections::HashMap, str::FromStr};
+
+use edn_rs::Edn;
+
+use crate::{
+    builtin_idents,
+    schema_cache::{schema, SchemaCache},
+    storage::Storage,
+    AttributeSchema, AttributeType, Database, EntityResult, PullAttribute, PullMap, PullNested,
+    PullSelector, PullValue, QueryError, Value, ID,
+};
+
+/**
+A declarative description of the attributes to pull from an entity,
+in the style of Datomic's pull patterns
+
+```
+use datom::{PullAttribute, PullPattern, PullSelector};
+
+let parsed: PullPattern = "[:user/username {:user/friends [:user/username]}]".parse()?;
+let built = PullPattern::new(vec![
+    PullAttribute::new("user/username".to_string().into()).into(),
+    PullAttribute::new("user/friends".to_string().into())
+        .nested(PullPattern::new(vec![
+            PullAttribute::new("user/username".to_string().into()).into(),
+        ]))
+        .into(),
+]);
+assert_eq!(parsed, built);
+# Ok::<(), datom::QueryError>(())
+```
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PullPattern {
+    /// The selectors making up this pattern
+    pub selectors: Vec<PullSelector>,
+}
+
+/// The state of a single pull as it walks the entity graph
+struct PullState<'a, 'c, S: Storage> {
+    db: &'a Database<'c, S>,
+    cache: SchemaCache,
+    /// The entities currently being pulled, to stop cycles
+    path: Vec<ID>,
+}
+
+/// The remaining depth of each recursive attribute in the current
+/// pattern, keyed by attribute and direction
+type Depths = HashMap<(ID, bool), Option<usize>>;
+
+fn id_map(id: ID) -> PullValue {
+    PullValue::Entity([("db/id".to_string(), PullValue::Value(id.into()))].into())
+}
+
+fn key(schema: &AttributeSchema, reverse: bool) -> String {
+    let name = schema
+        .ident
+        .clone()
+        .unwrap_or_else(|| schema.id.to_string());
+    if !reverse {
+        return name;
+    }
+    match name.rsplit_once('/') {
+        Some((ns, name)) => format!("{}/_{}", ns, name),
+        None => format!("_{}", name),
+    }
+}
+
+impl PullPattern {
+    /// Create a pattern from its selectors
+    pub const fn new(selectors: Vec<PullSelector>) -> Self {
+        Self { selectors }
+    }
+
+    /// A pattern consisting only of the wildcard
+    pub fn wildcard() -> Self {
+        Self::new(vec![PullSelector::Wildcard])
+    }
+
+    /// Pull this pattern from an entity in a [Database]
+    pub fn pull<S: Storage>(
+        &self,
+        db: &Database<'_, S>,
+        entity: ID,
+    ) -> Result<PullMap, QueryError> {
+        let mut state = PullState {
+            db,
+            cache: SchemaCache::new(),
+            path: vec![],
+        };
+        self.pull_entity(&mut state, entity, &Depths::new())
+    }
+
+    fn pull_entity<S: Storage>(
+        &self,
+        state: &mut PullState<'_, '_, S>,
+        entity: ID,
+        depths: &Depths,
+    ) -> Result<PullMap, QueryError> {
+        state.path.push(entity);
+        let mut map = PullMap::new();
+        for selector in self.selectors.iter() {
+            match selector {
+                PullSelector::Wildcard => {
+                    map.insert("db/id".to_string(), PullValue::Value(entity.into()));
+                    let attributes: Vec<ID> = state
+                        .db
+                        .entity(entity.into())?
+                        .attributes()?
+                        .collect::<Result<_, _>>()?;
+                    for attribute in attributes {
+                        let spec = PullAttribute::new(attribute.into());
+                        if let Some((k, v)) = self.pull_attribute(state, entity, &spec, depths)? {
+                            map.insert(k, v);
+                        }
+                    }
+                }
+                PullSelector::Attribute(spec) => {
+                    if let Some((k, v)) = self.pull_attribute(state, entity, spec, depths)? {
+                        map.insert(k, v);
+                    }
+                }
+            }
+        }
+        state.path.pop();
+        Ok(map)
+    }
+
+    fn pull_attribute<S: Storage>(
+        &self,
+        state: &mut PullState<'_, '_, S>,
+        entity: ID,
+        spec: &PullAttribute,
+        depths: &Depths,
+    ) -> Result<Option<(String, PullValue)>, QueryError> {
+        let attribute = spec.attribute.resolve(state.db)?;
+        if attribute == builtin_idents::ID {
+            return Ok(Some(("db/id".to_string(), PullValue::Value(entity.into()))));
+        }
+        let schema = schema(state.db, attribute, &mut state.cache)?;
+        let ent = state.db.entity(entity.into())?;
+        let result = if spec.reverse {
+            ent.reverse_get(attribute.into())?
+        } else {
+            ent.get(attribute.into())?
+        };
+        let value = match result {
+            EntityResult::NotFound => None,
+            EntityResult::Value(Value::ID(id)) if schema.value_type == Some(AttributeType::Ref) => {
+                Some(self.pull_ref(state, id, spec, &schema, depths)?)
+            }
+            EntityResult::Value(v) => Some(PullValue::Value(v)),
+            EntityResult::Ref(e) => Some(self.pull_ref(state, e.id, spec, &schema, depths)?),
+            EntityResult::Repeated(results) => {
+                let limit = spec.limit.unwrap_or(usize::MAX);
+                let mut values = vec![];
+                for result in results.into_iter().take(limit) {
+                    values.push(match result {
+                        EntityResult::Ref(e) => {
+                            self.pull_ref(state, e.id, spec, &schema, depths)?
+                        }
+                        EntityResult::Value(v) => PullValue::Value(v),
+                        _ => continue,
+                    });
+                }
+                if values.is_empty() {
+                    None
+                } else if spec.reverse && schema.component {
+                    // An entity can only be a component of one other
+                    // entity
+                    values.into_iter().next()
+                } else {
+                    Some(PullValue::Many(values))
+                }
+            }
+        };
+        Ok(value
+            .or_else(|| spec.default.clone().map(PullValue::Value))
+            .map(|v| (key(&schema, spec.reverse), v)))
+    }
+
+    fn pull_ref<S: Storage>(
+        &self,
+        state: &mut PullState<'_, '_, S>,
+        target: ID,
+        spec: &PullAttribute,
+        schema: &AttributeSchema,
+        depths: &Depths,
+    ) -> Result<PullValue, QueryError> {
+        if state.path.contains(&target) {
+            return Ok(id_map(target));
+        }
+        match &spec.nested {
+            Some(PullNested::Pattern(pattern)) => Ok(PullValue::Entity(pattern.pull_entity(
+                state,
+                target,
+                &Depths::new(),
+            )?)),
+            Some(PullNested::Recurse(limit)) => {
+                let k = (schema.id, spec.reverse);
+                let remaining = depths.get(&k).copied().unwrap_or(*limit);
+                if remaining == Some(0) {
+                    return Ok(id_map(target));
+                }
+                let mut depths = depths.clone();
+                depths.insert(k, remaining.map(|r| r - 1));
+                Ok(PullValue::Entity(self.pull_entity(state, target, &depths)?))
+            }
+            None if schema.component && !spec.reverse => Ok(PullValue::Entity(
+                Self::wildcard().pull_entity(state, target, &Depths::new())?,
+            )),
+            None => Ok(id_map(target)),
+        }
+    }
+
+    /// Create a pattern from its EDN representation
+    pub fn from_edn(edn: Edn) -> Result<Self, QueryError> {
+        let Edn::Vector(elements) = edn else {
+            return Err(QueryError::InvalidQuery(
+                "pull patterns must be a vector".to_string(),
+            ));
+        };
+        let mut selectors = vec![];
+        for element in elements.to_vec() {
+            match element {
+                Edn::Symbol(s) if s == "*" => selectors.push(PullSelector::Wildcard),
+                Edn::Map(m) => {
+                    for (k, v) in m.to_map() {
+                        if !k.starts_with(':') {
+                            return Err(QueryError::InvalidQuery(format!(
+                                "map specs must be keyed by attribute keywords, not `{}`",
+                                k
+                            )));
+                        }
+                        let mut attr = PullAttribute::from_keyword(&k);
+                        attr.nested = Some(PullAttribute::nested_from_edn(v)?);
+                        selectors.push(attr.into());
+                    }
+                }
+                other => selectors.push(PullAttribute::from_edn(other)?.into()),
+            }
+        }
+        Ok(Self { selectors })
+    }
+}
+
+impl FromStr for PullPattern {
+    type Err = QueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let edn = Edn::from_str(s).map_err(|e| QueryError::InvalidQuery(format!("{:?}", e)))?;
+        Self::from_edn(edn)
+    }
+}
@@ -0,0 +1,21 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+use crate::PullAttribute;
+
+/// One element of a [PullPattern](crate::PullPattern)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PullSelector {
+    /// `*`, pulling every attribute of the entity and expanding
+    /// [component](crate::builtin_idents::IS_COMPONENT) references
+    Wildcard,
+    /// A single attribute
+    Attribute(PullAttribute),
+}
+
+impl From<PullAttribute> for PullSelector {
+    fn from(attribute: PullAttribute) -> Self {
+        Self::Attribute(attribute)
+    }
+}
@@ -0,0 +1,45 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+use std::collections::BTreeMap;
+
+use crate::Value;
+
+/// The attributes pulled from an entity, keyed by attribute
+/// [ident](crate::builtin_idents::IDENT)
+pub type PullMap = BTreeMap<String, PullValue>;
+
+/// A value in the result of a [pull](crate::Database::pull)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PullValue {
+    /// A plain attribute value
+    Value(Value),
+    /// A referenced entity, with the attributes pulled from it
+    Entity(PullMap),
+    /// The values of a cardinality-many or reverse attribute
+    Many(Vec<Self>),
+}
+
+impl PullValue {
+    /// Get the nested map, if this is an [Entity](Self::Entity)
+    pub const fn as_entity(&self) -> Option<&PullMap> {
+        if let Self::Entity(map) = self {
+            Some(map)
+        } else {
+            None
+        }
+    }
+}
+
+impl From<Value> for PullValue {
+    fn from(value: Value) -> Self {
+        Self::Value(value)
+    }
+}
+
+impl From<PullMap> for PullValue {
+    fn from(map: PullMap) -> Self {
+        Self::Entity(map)
+    }
+}
@@ -0,0 +1,198 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+use std::{collections::HashSet, str::FromStr};
+
+use edn_rs::Edn;
+
+use crate::{
+    datalog::Bindings, schema_cache::SchemaCache, storage::Storage, Database, FindSpec,
+    InputBinding, Pattern, QueryError, QueryInput, QueryResult,
+};
+
+/**
+A Datalog query, in the style of Datomic's `:find`/`:in`/`:where`
+queries
+
+Queries can be built imperatively, or parsed from their EDN
+representation:
+
+```
+use datom::{FindSpec, InputBinding, Pattern, Query, Term, EID};
+
+let parsed: Query = "[:find ?e :in $ ?name :where [?e :user/username ?name]]".parse()?;
+let built = Query::new()
+    .find(FindSpec::Relation(vec!["?e".into()]))
+    .input(InputBinding::Scalar("?name".into()))
+    .pattern(Pattern::new(
+        Term::var("?e"),
+        EID::from("user/username".to_string()).into(),
+        Term::var("?name"),
+    ));
+assert_eq!(parsed, built);
+# Ok::<(), datom::QueryError>(())
+```
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Query {
+    /// The shape of the query's results
+    pub find: FindSpec,
+    /// How the query's inputs are bound, not including the database
+    pub inputs: Vec<InputBinding>,
+    /// The data patterns which must all match
+    pub patterns: Vec<Pattern>,
+}
+
+impl Query {
+    /// Start building a query which finds nothing
+    pub const fn new() -> Self {
+        Self {
+            find: FindSpec::Relation(vec![]),
+            inputs: vec![],
+            patterns: vec![],
+        }
+    }
+
+    /// Set the query's find spec
+    #[allow(clippy::missing_const_for_fn)]
+    pub fn find(mut self, find: FindSpec) -> Self {
+        self.find = find;
+        self
+    }
+
+    /// Add an input binding
+    pub fn input(mut self, input: InputBinding) -> Self {
+        self.inputs.push(input);
+        self
+    }
+
+    /// Add a data pattern
+    pub fn pattern(mut self, pattern: Pattern) -> Self {
+        self.patterns.push(pattern);
+        self
+    }
+
+    /// Ensure every variable the query returns is bound somewhere
+    fn check_variables(&self) -> Result<(), QueryError> {
+        let bound: HashSet<&str> = self
+            .inputs
+            .iter()
+            .flat_map(InputBinding::variables)
+            .chain(self.patterns.iter().flat_map(Pattern::variables))
+            .collect();
+        for var in self.find.variables() {
+            if !bound.contains(var) {
+                return Err(QueryError::UnboundVariable(var.to_owned()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Run this query against a [Database]
+    pub fn execute<S: Storage>(
+        &self,
+        db: &Database<'_, S>,
+        inputs: &[QueryInput],
+    ) -> Result<QueryResult, QueryError> {
+        self.check_variables()?;
+        if inputs.len() != self.inputs.len() {
+            return Err(QueryError::InvalidInput(format!(
+                "expected {} inputs, got {}",
+                self.inputs.len(),
+                inputs.len()
+            )));
+        }
+        let mut rows: Vec<Bindings> = vec![Bindings::new()];
+        for (binding, input) in self.inputs.iter().zip(inputs) {
+            let bound = binding.bind(input)?;
+            rows = rows
+                .iter()
+                .flat_map(|row| {
+                    bound.iter().filter_map(move |b| {
+                        let mut row = row.clone();
+                        for (var, value) in b {
+                            match row.get(var) {
+                                Some(existing) if existing != value => return None,
+                                _ => row.insert(var.to_owned(), value.to_owned()),
+                            };
+                        }
+                        Some(row)
+                    })
+                })
+                .collect();
+        }
+        let mut cache = SchemaCache::new();
+        for pattern in self.patterns.iter() {
+            rows = pattern.join(db, rows, &mut cache)?;
+        }
+        Ok(self.find.project(rows))
+    }
+
+    /// Create a query from its EDN representation
+    pub fn from_edn(edn: Edn) -> Result<Self, QueryError> {
+        let Edn::Vector(elements) = edn else {
+            return Err(QueryError::InvalidQuery(
+                "queries must be a vector".to_string(),
+            ));
+        };
+        let mut find = None;
+        let mut inputs = None;
+        let mut patterns = None;
+        let mut section: Option<&mut Option<Vec<Edn>>> = None;
+        for element in elements.to_vec() {
+            match element {
+                Edn::Key(k) if k == ":find" => section = Some(&mut find),
+                Edn::Key(k) if k == ":in" => section = Some(&mut inputs),
+                Edn::Key(k) if k == ":where" => section = Some(&mut patterns),
+                Edn::Key(k) => {
+                    return Err(QueryError::InvalidQuery(format!(
+                        "unsupported query section `{}`",
+                        k
+                    )))
+                }
+                other => match section {
+                    Some(ref mut s) => s.get_or_insert_with(Vec::new).push(other),
+                    None => {
+                        return Err(QueryError::InvalidQuery(
+                            "queries must start with :find".to_string(),
+                        ))
+                    }
+                },
+            }
+        }
+        let find = FindSpec::from_edn(find.unwrap_or_default())?;
+        let mut inputs = inputs.unwrap_or_default().into_iter().peekable();
+        if inputs.peek() == Some(&Edn::Symbol("$".to_string())) {
+            inputs.next();
+        }
+        let inputs = inputs
+            .map(InputBinding::from_edn)
+            .collect::<Result<_, _>>()?;
+        let patterns = patterns
+            .unwrap_or_default()
+            .into_iter()
+            .map(Pattern::from_edn)
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            find,
+            inputs,
+            patterns,
+        })
+    }
+}
+
+impl Default for Query {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FromStr for Query {
+    type Err = QueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let edn = Edn::from_str(s).map_err(|e| QueryError::InvalidQuery(format!("{:?}", e)))?;
+        Self::from_edn(edn)
+    }
+}
@@ -7,7 +7,7 @@
 use miette::Diagnostic;
 use thiserror::Error;
 
-use crate::{ConnectionError, StorageError, EID};
+use crate::{ConnectionError, StorageError, EID, ID};
 
 /// Errors during a [Database](crate::Database) query
 #[derive(Error, Debug, Diagnostic)]
@@ -16,6 +16,22 @@ pub enum QueryError {
     #[diagnostic(code(datom::query::unresolved_eid), url(docsrs))]
     UnresolvedEID(EID),
 
+    #[error("the query is malformed: {0}")]
+    #[diagnostic(code(datom::query::invalid_query), url(docsrs))]
+    InvalidQuery(String),
+
+    #[error("the variable `{0}` is returned but never bound")]
+    #[diagnostic(code(datom::query::unbound_variable), url(docsrs))]
+    UnboundVariable(String),
+
+    #[error("the query's inputs don't match its :in clause: {0}")]
+    #[diagnostic(code(datom::query::invalid_input), url(docsrs))]
+    InvalidInput(String),
+
+    #[error("the attribute `{0}` isn't in the AVET index")]
+    #[diagnostic(code(datom::query::unindexed_attribute), url(docsrs))]
+    UnindexedAttribute(ID),
+
     #[error("there was an error with the underlying connection")]
     #[diagnostic(code(datom::connection), url(docsrs))]
     ConnectionError(#[from] ConnectionError),
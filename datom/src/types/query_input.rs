@@ -0,0 +1,29 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+use crate::Value;
+
+/// A value passed to a [Query](crate::Query) for one of its
+/// [InputBinding](crate::InputBinding)s
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum QueryInput {
+    /// A single value, for an
+    /// [InputBinding::Scalar](crate::InputBinding::Scalar)
+    Scalar(Value),
+    /// A single tuple, for an
+    /// [InputBinding::Tuple](crate::InputBinding::Tuple)
+    Tuple(Vec<Value>),
+    /// Many values, for an
+    /// [InputBinding::Collection](crate::InputBinding::Collection)
+    Collection(Vec<Value>),
+    /// Many tuples, for an
+    /// [InputBinding::Relation](crate::InputBinding::Relation)
+    Relation(Vec<Vec<Value>>),
+}
+
+impl From<Value> for QueryInput {
+    fn from(value: Value) -> Self {
+        Self::Scalar(value)
+    }
+}
@@ -0,0 +1,20 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+use crate::Value;
+
+/// The result of running a [Query](crate::Query), shaped by its
+/// [FindSpec](crate::FindSpec)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum QueryResult {
+    /// A set of tuples, from a [FindSpec::Relation](crate::FindSpec::Relation)
+    Relation(Vec<Vec<Value>>),
+    /// A set of values, from a
+    /// [FindSpec::Collection](crate::FindSpec::Collection)
+    Collection(Vec<Value>),
+    /// A single tuple, from a [FindSpec::Tuple](crate::FindSpec::Tuple)
+    Tuple(Option<Vec<Value>>),
+    /// A single value, from a [FindSpec::Scalar](crate::FindSpec::Scalar)
+    Scalar(Option<Value>),
+}
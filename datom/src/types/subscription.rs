@@ -0,0 +1,57 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc::Receiver,
+    Arc,
+};
+
+use crate::TxReport;
+
+/**
+A stream of [TxReport]s from a
+[Connection](crate::Connection::subscribe)
+
+Iterating blocks until the next transaction is committed. Each
+subscription buffers a bounded number of reports, and the
+transactor never waits for a subscriber: if a report arrives while
+the buffer is full, the subscription is disconnected. The reports
+already buffered can still be read, after which iteration ends.
+[Subscription::fell_behind] tells this apart from the connection
+being dropped, which also ends iteration.
+*/
+pub struct Subscription {
+    receiver: Receiver<TxReport>,
+    fell_behind: Arc<AtomicBool>,
+}
+
+impl Subscription {
+    pub(crate) const fn new(receiver: Receiver<TxReport>, fell_behind: Arc<AtomicBool>) -> Self {
+        Self {
+            receiver,
+            fell_behind,
+        }
+    }
+
+    /// Iterate over the reports which are already buffered, without
+    /// blocking
+    pub fn try_iter(&self) -> impl Iterator<Item = TxReport> + '_ {
+        self.receiver.try_iter()
+    }
+
+    /// Whether this subscription was disconnected because its buffer
+    /// was full when a transaction was committed
+    pub fn fell_behind(&self) -> bool {
+        self.fell_behind.load(Ordering::Acquire)
+    }
+}
+
+impl Iterator for Subscription {
+    type Item = TxReport;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receiver.recv().ok()
+    }
+}
@@ -0,0 +1,43 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+use std::collections::HashMap;
+
+use crate::ID;
+
+/**
+A temporary entity ID, which is replaced with a fresh [ID] when the
+[Transaction](crate::Transaction) it's used in is transacted
+
+Every use of the same temporary ID within a single transaction refers
+to the same entity.
+*/
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TempID {
+    /// A temporary ID identified by a string
+    Named(String),
+    /// A temporary ID identified by a number
+    Numbered(u64),
+}
+
+/// The [ID]s each [TempID] in a transaction was resolved to
+pub type TempIDs = HashMap<TempID, ID>;
+
+impl From<String> for TempID {
+    fn from(name: String) -> Self {
+        Self::Named(name)
+    }
+}
+
+impl From<&str> for TempID {
+    fn from(name: &str) -> Self {
+        Self::Named(name.to_owned())
+    }
+}
+
+impl From<u64> for TempID {
+    fn from(n: u64) -> Self {
+        Self::Numbered(n)
+    }
+}
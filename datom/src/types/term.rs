@@ -0,0 +1,121 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+use std::str::FromStr;
+
+use datom_bigdecimal::BigDecimal;
+use edn_rs::Edn;
+use num_bigint::BigInt;
+
+use crate::{datalog::Bindings, storage::Storage, Database, QueryError, Value, EID, ID};
+
+/// One position in a [Pattern](crate::Pattern)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Term {
+    /// A logic variable, such as `?e`
+    Variable(String),
+    /// `_`, which matches anything without binding it
+    Blank,
+    /// An entity, resolved against the database when the query runs
+    Entity(EID),
+    /// A constant value
+    Value(Value),
+}
+
+impl Term {
+    /// Create a [Term::Variable]
+    ///
+    /// ```
+    /// use datom::Term;
+    /// assert_eq!(Term::var("?e"), Term::Variable("?e".to_string()));
+    /// ```
+    pub fn var(name: &str) -> Self {
+        Self::Variable(name.to_owned())
+    }
+
+    /// The name of this term's variable, if it is one
+    pub fn variable(&self) -> Option<&str> {
+        if let Self::Variable(name) = self {
+            Some(name)
+        } else {
+            None
+        }
+    }
+
+    /// Replace an [Entity](Self::Entity) with the
+    /// [Value::ID] it resolves to
+    pub(crate) fn resolve<S: Storage>(&self, db: &Database<'_, S>) -> Result<Self, QueryError> {
+        match self {
+            Self::Entity(eid) => Ok(Self::Value(eid.resolve(db)?.into())),
+            _ => Ok(self.to_owned()),
+        }
+    }
+
+    /// The value this term is constrained to in a given row, if any
+    pub(crate) fn lookup(&self, row: &Bindings) -> Option<Value> {
+        match self {
+            Self::Variable(name) => row.get(name).cloned(),
+            Self::Value(value) => Some(value.to_owned()),
+            Self::Blank | Self::Entity(_) => None,
+        }
+    }
+
+    /// Bind this term's variable to a value in a row, failing if it
+    /// is already bound to a different value
+    pub(crate) fn unify(&self, row: &mut Bindings, value: Value) -> bool {
+        match self {
+            Self::Variable(name) => match row.get(name) {
+                Some(bound) => bound == &value,
+                None => {
+                    row.insert(name.to_owned(), value);
+                    true
+                }
+            },
+            _ => true,
+        }
+    }
+
+    /// Create a term from its EDN representation
+    pub fn from_edn(edn: Edn) -> Result<Self, QueryError> {
+        match edn {
+            Edn::Symbol(s) if s == "_" => Ok(Self::Blank),
+            Edn::Symbol(s) if s.starts_with('?') => Ok(Self::Variable(s)),
+            Edn::Key(k) => Ok(Self::Entity(EID::Ident(
+                k.trim_start_matches(':').to_owned(),
+            ))),
+            Edn::Uuid(u) => ID::from_str(&u)
+                .map(|id| Self::Value(id.into()))
+                .map_err(|_| QueryError::InvalidQuery(format!("invalid UUID `{}`", u))),
+            Edn::Str(s) => Ok(Self::Value(s.into())),
+            Edn::Int(i) => Ok(Self::Value(BigInt::from(i).into())),
+            Edn::UInt(u) => Ok(Self::Value(BigInt::from(u).into())),
+            Edn::Double(d) => BigDecimal::from_str(&d.to_string())
+                .map(|d| Self::Value(d.into()))
+                .map_err(|_| QueryError::InvalidQuery(format!("invalid decimal `{}`", d))),
+            Edn::Bool(b) => Ok(Self::Value(b.into())),
+            other => Err(QueryError::InvalidQuery(format!(
+                "`{}` is not a valid term",
+                other
+            ))),
+        }
+    }
+}
+
+impl From<Value> for Term {
+    fn from(value: Value) -> Self {
+        Self::Value(value)
+    }
+}
+
+impl From<EID> for Term {
+    fn from(eid: EID) -> Self {
+        Self::Entity(eid)
+    }
+}
+
+impl From<ID> for Term {
+    fn from(id: ID) -> Self {
+        Self::Value(id.into())
+    }
+}
@@ -6,7 +6,11 @@ use std::collections::HashMap;
 
 use edn_rs::Edn;
 
-use crate::{storage::Storage, Database, Datom, Fact, TransactionError, Value, EID};
+use crate::{
+    schema_cache::{schema, SchemaCache},
+    storage::Storage,
+    Database, Datom, Fact, TempID, TempIDs, TransactionError, Value, EID, ID,
+};
 
 /// A type which can be appended to a transaction
 pub trait Transactable {
@@ -36,6 +40,14 @@ impl Transaction {
         self.push_fact(Fact::Add(entity, attribute, value));
     }
 
+    /**
+    Add a reference from an entity to another entity, either of which
+    may be a [temporary ID](EID::Temp)
+    */
+    pub fn add_ref(&mut self, entity: EID, attribute: EID, target: EID) {
+        self.push_fact(Fact::AddRef(entity, attribute, target));
+    }
+
     /// Add many attribute values to an entity
     pub fn add_many(&mut self, entity: EID, attr_value_pairs: HashMap<EID, Value>) {
         for (attr, val) in attr_value_pairs {
@@ -53,21 +65,104 @@ impl Transaction {
         self.push_fact(Fact::Retract(entity, attribute))
     }
 
+    /**
+    Retract an entity entirely: every attribute it has, every
+    reference to it from other entities, and, recursively, every
+    entity it refers to through a
+    [component](crate::builtin_idents::IS_COMPONENT) attribute
+    */
+    pub fn retract_entity(&mut self, entity: EID) {
+        self.push_fact(Fact::RetractEntity(entity))
+    }
+
+    /**
+    Set an attribute's value on an entity, failing the transaction
+    with [TransactionError::CasFailed] unless its current value is
+    `expected`
+    */
+    pub fn cas(&mut self, entity: EID, attribute: EID, expected: Option<Value>, new: Value) {
+        self.push_fact(Fact::Cas(entity, attribute, expected, new))
+    }
+
     /// Append a transactable to this transaction
     pub fn append<T: Transactable>(&mut self, txable: T) {
         self.facts.append(&mut txable.tx().facts);
     }
 
-    /// Convert the [Transaction] to a set of [Datom]s
+    /**
+    Resolve [temporary IDs](EID::Temp) which assert a value for a
+    [unique identity](crate::AttributeSchema::identity) attribute,
+    either to the entity which already holds that value or to the
+    same [ID] as any other temporary ID asserting it
+    */
+    fn upsert_tempids<S: Storage>(
+        &self,
+        db: &Database<'_, S>,
+        t: u64,
+        tempids: &mut TempIDs,
+    ) -> Result<(), TransactionError> {
+        let mut cache = SchemaCache::new();
+        let mut identities: Vec<(&TempID, ID, &Value)> = vec![];
+        for fact in self.facts.iter() {
+            if let Fact::Add(EID::Temp(tempid), attribute, value) = fact {
+                let attribute = attribute.resolve_in_transaction(db, t, tempids)?;
+                if schema(db, attribute, &mut cache)?.identity {
+                    identities.push((tempid, attribute, value));
+                }
+            }
+        }
+        for &(tempid, attribute, value) in identities.iter() {
+            let existing = db.current_datoms(None, Some(attribute), Some(value), &mut cache)?;
+            if let Some(existing) = existing.first() {
+                match tempids.get(tempid) {
+                    Some(&id) if id != existing.entity => {
+                        return Err(TransactionError::UniqueConflict(
+                            id,
+                            attribute,
+                            value.to_owned(),
+                            existing.entity,
+                        ))
+                    }
+                    _ => tempids.insert(tempid.to_owned(), existing.entity),
+                };
+            }
+        }
+        let mut claimed: HashMap<(ID, &Value), ID> = HashMap::new();
+        for &(tempid, attribute, value) in identities.iter() {
+            let id = match tempids.get(tempid) {
+                Some(&id) => id,
+                None => claimed
+                    .get(&(attribute, value))
+                    .copied()
+                    .unwrap_or_else(ID::new),
+            };
+            tempids.insert(tempid.to_owned(), id);
+            claimed.entry((attribute, value)).or_insert(id);
+        }
+        Ok(())
+    }
+
+    /**
+    Convert the [Transaction] to a set of [Datom]s
+
+    Each [temporary ID](EID::Temp) in the transaction is resolved to
+    the [ID] it has in `tempids`, to an existing entity if it asserts
+    that entity's value for a
+    [unique identity](crate::AttributeSchema::identity) attribute, or
+    to a fresh [ID]. Any new resolutions are added to `tempids`.
+    */
     pub fn datoms<'c, S: Storage>(
         &self,
         t: u64,
         db: &Database<'c, S>,
+        tempids: &mut TempIDs,
     ) -> Result<Vec<Datom>, TransactionError> {
-        self.facts
-            .iter()
-            .map(|f| f.to_owned().datom(t, db))
-            .collect()
+        self.upsert_tempids(db, t, tempids)?;
+        let mut datoms = vec![];
+        for fact in self.facts.iter() {
+            datoms.append(&mut fact.to_owned().datoms(t, db, tempids)?);
+        }
+        Ok(datoms)
     }
 
     /// Create a transaction from an EDN list of facts
@@ -7,7 +7,7 @@
 use miette::Diagnostic;
 use thiserror::Error;
 
-use crate::{ConnectionError, QueryError, EID, ID};
+use crate::{AttributeType, ConnectionError, QueryError, Value, EID, ID};
 
 /// Errors during a [Transaction](crate::Transaction)
 #[derive(Error, Debug, Diagnostic)]
@@ -26,6 +26,22 @@ pub enum TransactionError {
     #[diagnostic(code(datom::transaction::unresolved_eid), url(docsrs))]
     UnresolvedEID(EID),
 
+    #[error("entity {0:?} can't have the value {3:?} for attribute {1:?}, which has type {2:?}")]
+    #[diagnostic(code(datom::transaction::invalid_value_type), url(docsrs))]
+    InvalidValueType(ID, ID, AttributeType, Value),
+
+    #[error("entity {0:?} can't have the value {2:?} for unique attribute {1:?}, because entity {3:?} already has it")]
+    #[diagnostic(code(datom::transaction::unique_conflict), url(docsrs))]
+    UniqueConflict(ID, ID, Value, ID),
+
+    #[error("the transaction asserts conflicting values for attribute {1:?} on entity {0:?}")]
+    #[diagnostic(code(datom::transaction::conflicting_values), url(docsrs))]
+    ConflictingValues(ID, ID),
+
+    #[error("expected attribute {1:?} on entity {0:?} to be {2:?}, but it was {3:?}")]
+    #[diagnostic(code(datom::transaction::cas_failed), url(docsrs))]
+    CasFailed(ID, ID, Option<Value>, Option<Value>),
+
     #[error("a query executed during this transaction failed")]
     #[diagnostic(code(datom::query), url(docsrs))]
     QueryError(#[from] QueryError),
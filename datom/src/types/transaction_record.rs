@@ -4,10 +4,34 @@
 
 use chrono::{DateTime, Utc};
 
+use crate::ID;
+
 /// The record of a past transaction
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct TransactionRecord {
     /// The t-value of this transaction
     pub t: u64,
     /// When this transaction was transacted
     pub timestamp: DateTime<Utc>,
 }
+
+impl TransactionRecord {
+    /**
+    The [ID] of the entity for the transaction with a given t-value,
+    which holds its [tx-instant](crate::builtin_idents::TX_INSTANT)
+    and any other attributes asserted on [EID::Tx](crate::EID::Tx)
+
+    ```
+    use datom::TransactionRecord;
+    assert_ne!(TransactionRecord::id_for_t(1), TransactionRecord::id_for_t(2));
+    ```
+    */
+    pub const fn id_for_t(t: u64) -> ID {
+        ID::from_u128((0x7478_6964u128 << 64) | t as u128)
+    }
+
+    /// The [ID] of the entity for this transaction
+    pub const fn id(&self) -> ID {
+        Self::id_for_t(self.t)
+    }
+}
@@ -2,7 +2,7 @@
 // SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
 // SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
 
-use crate::{storage::Storage, Connection, Database, Datom};
+use crate::{storage::Storage, Connection, Database, Datom, TempIDs};
 
 /**
 The result of running a [Transaction](crate::Transaction) on a
@@ -20,4 +20,9 @@ pub struct TransactionResult<'connection, S: Storage> {
     pub after: Database<'connection, S>,
     /// The [Datom]s added to the database in the transaction
     pub data: Vec<Datom>,
+    /**
+    The [ID](crate::ID)s each [temporary ID](crate::EID::Temp) in the
+    transaction was resolved to
+    */
+    pub tempids: TempIDs,
 }
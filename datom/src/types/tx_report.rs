@@ -0,0 +1,27 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+use chrono::{DateTime, Utc};
+
+use crate::Datom;
+
+/**
+A report of a committed transaction, delivered to
+[subscribers](crate::Connection::subscribe)
+
+Unlike a [TransactionResult](crate::TransactionResult), a report
+doesn't borrow its [Connection](crate::Connection), so it can be
+sent to other threads.
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TxReport {
+    /// The t-value of the database before the transaction
+    pub before_t: u64,
+    /// The t-value of the database after the transaction
+    pub after_t: u64,
+    /// When the transaction was transacted
+    pub timestamp: DateTime<Utc>,
+    /// The [Datom]s added to the database in the transaction
+    pub data: Vec<Datom>,
+}
@@ -10,10 +10,14 @@ use std::{
 
 use datom_bigdecimal::{BigDecimal, ParseBigDecimalError, ToPrimitive};
 use edn_rs::Edn;
-use num_bigint::BigInt;
+use num_bigint::{BigInt, Sign};
 
 use crate::ID;
 
+const fn u64_byte_count() -> usize {
+    0u64.to_be_bytes().len()
+}
+
 /// An attribute value.
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Value {
@@ -113,6 +117,177 @@ impl Value {
         }
     }
 
+    /**
+    Serialize the [Value] so that comparing the bytes of two values of
+    the same type orders them the same way as the values themselves.
+    Values of different types are ordered by type. The encoding is
+    self-delimiting, so it's used for the value position of index keys.
+
+    Numerically equal decimals serialize identically, regardless of
+    their scale.
+
+    ```
+    use datom::Value;
+    let mut values: Vec<Value> = vec![(-300).into(), 12.into(), (-2).into(), 0.into()];
+    values.sort_by_key(Value::sortable_bytes);
+    assert_eq!(values, vec![(-300).into(), (-2).into(), 0.into(), 12.into()]);
+
+    let mut values: Vec<Value> = vec!["b".into(), "aa".into(), "a".into()];
+    values.sort_by_key(Value::sortable_bytes);
+    assert_eq!(values, vec!["a".into(), "aa".into(), "b".into()]);
+    ```
+    */
+    pub fn sortable_bytes(&self) -> Vec<u8> {
+        match self {
+            Self::String(str) => {
+                let mut v = Vec::with_capacity(str.len() + 3);
+                v.push(0);
+                for &byte in str.as_bytes() {
+                    v.push(byte);
+                    if byte == 0 {
+                        v.push(0xFF);
+                    }
+                }
+                v.extend_from_slice(&[0, 0]);
+                v
+            }
+            Self::Integer(int) => {
+                let (sign, magnitude) = int.to_bytes_be();
+                let len = (magnitude.len() as u64).to_be_bytes();
+                let mut v = vec![1];
+                match sign {
+                    Sign::NoSign => v.push(1),
+                    Sign::Plus => {
+                        v.push(2);
+                        v.extend_from_slice(&len);
+                        v.extend_from_slice(&magnitude);
+                    }
+                    // Larger magnitudes sort first, so every byte is inverted
+                    Sign::Minus => {
+                        v.push(0);
+                        v.extend(len.iter().chain(magnitude.iter()).map(|b| !b));
+                    }
+                }
+                v
+            }
+            Self::Decimal(dec) => {
+                let (int, scale) = dec.as_bigint_and_exponent();
+                let mut v = vec![2];
+                let (sign, digits) = int.to_radix_be(10);
+                let Some(last) = digits.iter().rposition(|&d| d != 0) else {
+                    v.push(1);
+                    return v;
+                };
+                // The decimal is 0.d₁d₂…dₙ × 10^exponent, with no
+                // trailing zero digits
+                let exponent = digits.len() as i64 - scale;
+                let mut rest = ((exponent as u64) ^ (1 << 63)).to_be_bytes().to_vec();
+                rest.extend(digits[..=last].iter().map(|d| d + 1));
+                rest.push(0);
+                if sign == Sign::Minus {
+                    v.push(0);
+                    v.extend(rest.iter().map(|b| !b));
+                } else {
+                    v.push(2);
+                    v.append(&mut rest);
+                }
+                v
+            }
+            Self::ID(_) | Self::Boolean(_) => self.bytes(),
+        }
+    }
+
+    /// Deserialize a [Value] from the start of a byte slice written by
+    /// [Value::sortable_bytes], returning it with the rest of the slice
+    pub fn from_sortable_bytes(bytes: &[u8]) -> Option<(Self, &[u8])> {
+        let (&valtype, bytes) = bytes.split_first()?;
+        match valtype {
+            0 => {
+                let mut str = vec![];
+                let mut i = 0;
+                loop {
+                    let byte = *bytes.get(i)?;
+                    i += 1;
+                    if byte == 0 {
+                        let escape = *bytes.get(i)?;
+                        i += 1;
+                        match escape {
+                            0 => break,
+                            0xFF => str.push(0),
+                            _ => return None,
+                        }
+                    } else {
+                        str.push(byte);
+                    }
+                }
+                Some((Self::String(String::from_utf8(str).ok()?), &bytes[i..]))
+            }
+            1 => {
+                let (&sign, bytes) = bytes.split_first()?;
+                let invert = |bytes: &[u8]| -> Vec<u8> {
+                    bytes
+                        .iter()
+                        .map(|b| if sign == 0 { !b } else { *b })
+                        .collect()
+                };
+                let sign = match sign {
+                    0 => Sign::Minus,
+                    1 => return Some((Self::Integer(BigInt::from(0)), bytes)),
+                    2 => Sign::Plus,
+                    _ => return None,
+                };
+                let len_bytes = invert(bytes.get(..u64_byte_count())?);
+                let len = u64::from_be_bytes(len_bytes.try_into().ok()?) as usize;
+                let bytes = &bytes[u64_byte_count()..];
+                let magnitude = invert(bytes.get(..len)?);
+                let int = BigInt::from_bytes_be(sign, &magnitude);
+                Some((Self::Integer(int), &bytes[len..]))
+            }
+            2 => {
+                let (&sign, bytes) = bytes.split_first()?;
+                let invert = |b: u8| if sign == 0 { !b } else { b };
+                let sign = match sign {
+                    0 => Sign::Minus,
+                    1 => return Some((Self::Decimal(BigDecimal::from(0)), bytes)),
+                    2 => Sign::Plus,
+                    _ => return None,
+                };
+                let exponent_bytes: Vec<u8> = bytes
+                    .get(..u64_byte_count())?
+                    .iter()
+                    .map(|&b| invert(b))
+                    .collect();
+                let exponent =
+                    (u64::from_be_bytes(exponent_bytes.try_into().ok()?) ^ (1 << 63)) as i64;
+                let bytes = &bytes[u64_byte_count()..];
+                let mut digits = vec![];
+                let mut i = 0;
+                loop {
+                    let digit = invert(*bytes.get(i)?);
+                    i += 1;
+                    match digit {
+                        0 => break,
+                        1..=10 => digits.push(digit - 1),
+                        _ => return None,
+                    }
+                }
+                let int = BigInt::from_radix_be(sign, &digits, 10)?;
+                let dec = BigDecimal::new(int, digits.len() as i64 - exponent);
+                Some((Self::Decimal(dec), &bytes[i..]))
+            }
+            3 => {
+                let id_bytes: [u8; 16] = bytes.get(..16)?.try_into().ok()?;
+                Some((Self::ID(id_bytes.into()), &bytes[16..]))
+            }
+            4 => match bytes.first()? {
+                0 => Some((Self::Boolean(false), &bytes[1..])),
+                1 => Some((Self::Boolean(true), &bytes[1..])),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
     /// Get the edn representation for this value
     pub fn into_edn(self) -> Edn {
         match self {
@@ -360,6 +535,92 @@ mod tests {
         test_failure(&[4, 0, 0]);
     }
 
+    /// Ensure the sortable encoding round-trips and sorts `vals`,
+    /// which must be in ascending order
+    fn test_sortable(vals: Vec<Value>) {
+        let encoded: Vec<Vec<u8>> = vals.iter().map(Value::sortable_bytes).collect();
+        for (val, bytes) in vals.iter().zip(&encoded) {
+            let mut trailing = bytes.clone();
+            trailing.push(42);
+            assert_eq!(
+                Value::from_sortable_bytes(&trailing),
+                Some((val.clone(), &[42][..]))
+            );
+        }
+        for pair in encoded.windows(2) {
+            assert!(
+                pair[0] < pair[1],
+                "{:?} should sort before {:?}",
+                pair[0],
+                pair[1]
+            );
+        }
+    }
+
+    #[test]
+    fn sortable_string() {
+        test_sortable(
+            [
+                "",
+                "\0",
+                "\0\0",
+                "a",
+                "a\0",
+                "aa",
+                "b",
+                "\u{ff}",
+                "\u{10ffff}",
+            ]
+            .map(Value::from)
+            .into(),
+        );
+    }
+
+    #[test]
+    fn sortable_integer() {
+        test_sortable(vec![
+            Value::from(BigInt::from(i128::MIN) * 2),
+            i128::MIN.into(),
+            (-65536).into(),
+            (-256).into(),
+            (-255).into(),
+            (-1).into(),
+            0.into(),
+            1.into(),
+            255.into(),
+            256.into(),
+            u128::MAX.into(),
+        ]);
+    }
+
+    #[test]
+    fn sortable_decimal() {
+        let decimals = [
+            "-1e20", "-10.5", "-10", "-0.5", "-0.05", "0", "0.001", "0.1", "1", "1.5", "10", "1e20",
+        ];
+        test_sortable(
+            decimals
+                .iter()
+                .map(|d| BigDecimal::from_str(d).unwrap().into())
+                .collect(),
+        );
+        // Scale doesn't affect the encoding of equal decimals
+        assert_eq!(
+            Value::from(BigDecimal::from_str("1.50").unwrap()).sortable_bytes(),
+            Value::from(BigDecimal::from_str("1.5").unwrap()).sortable_bytes()
+        );
+    }
+
+    #[test]
+    fn sortable_invalid() {
+        assert_eq!(Value::from_sortable_bytes(&[0, b'a', 0]), None);
+        assert_eq!(Value::from_sortable_bytes(&[0, b'a', 0, 1]), None);
+        assert_eq!(Value::from_sortable_bytes(&[1, 2, 0]), None);
+        assert_eq!(Value::from_sortable_bytes(&[2, 2]), None);
+        assert_eq!(Value::from_sortable_bytes(&[4, 2]), None);
+        assert_eq!(Value::from_sortable_bytes(&[5]), None);
+    }
+
     #[test]
     fn serialize_invalid() {
         test_failure(&[5]);
@@ -0,0 +1,111 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+use std::ops::Bound;
+
+use crate::{
+    dependencies::Dependencies, schema_cache::SchemaCache, storage::Storage, Database, Query,
+    QueryError, QueryInput, Term, Value, WatchResult, EID, ID,
+};
+
+/**
+A read which can be [watched](crate::Connection::watch), so that it's
+re-run whenever a transaction touches the entities and attributes it
+names
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Watch {
+    /// The current values of an attribute on an entity
+    Attribute(
+        /// Entity
+        EID,
+        /// Attribute
+        EID,
+    ),
+    /**
+    The [datoms](crate::Datom) for an attribute whose values are
+    within the given bounds, as returned by
+    [Database::index_range]
+    */
+    IndexRange(
+        /// Attribute
+        EID,
+        /// Start bound
+        Bound<Value>,
+        /// End bound
+        Bound<Value>,
+    ),
+    /// A [Query] and its inputs
+    Query(Query, Vec<QueryInput>),
+}
+
+/**
+The ID a term constrains its position to, if any. Entities which
+don't resolve yet might once a later transaction creates them, so
+they constrain nothing.
+*/
+fn term_id<S: Storage>(term: &Term, db: &Database<'_, S>) -> Option<ID> {
+    match term.resolve(db) {
+        Ok(Term::Value(Value::ID(id))) => Some(id),
+        _ => None,
+    }
+}
+
+impl Watch {
+    /**
+    Find the entity-attribute pairs the read depends on. For queries,
+    these are the entities and attributes named in each data
+    pattern; a pattern which names neither depends on every datom.
+    */
+    pub(crate) fn dependencies<S: Storage>(
+        &self,
+        db: &Database<'_, S>,
+    ) -> Result<Dependencies, QueryError> {
+        let mut dependencies = Dependencies::default();
+        match self {
+            Self::Attribute(entity, attribute) => {
+                dependencies.add(Some(entity.resolve(db)?), Some(attribute.resolve(db)?))
+            }
+            Self::IndexRange(attribute, _, _) => {
+                dependencies.add(None, Some(attribute.resolve(db)?))
+            }
+            Self::Query(query, _) => {
+                for pattern in query.patterns.iter() {
+                    dependencies.add(
+                        term_id(&pattern.entity, db),
+                        term_id(&pattern.attribute, db),
+                    );
+                }
+            }
+        }
+        Ok(dependencies)
+    }
+
+    /// Run the read against a database
+    pub(crate) fn evaluate<S: Storage>(
+        &self,
+        db: &Database<'_, S>,
+    ) -> Result<WatchResult, QueryError> {
+        match self {
+            Self::Attribute(entity, attribute) => {
+                let values = db
+                    .current_datoms(
+                        Some(entity.resolve(db)?),
+                        Some(attribute.resolve(db)?),
+                        None,
+                        &mut SchemaCache::new(),
+                    )?
+                    .into_iter()
+                    .map(|datom| datom.value)
+                    .collect();
+                Ok(WatchResult::Values(values))
+            }
+            Self::IndexRange(attribute, start, end) => Ok(WatchResult::Datoms(
+                db.index_range(attribute.resolve(db)?, start.clone(), end.clone())?
+                    .collect::<Result<_, _>>()?,
+            )),
+            Self::Query(query, inputs) => Ok(WatchResult::Query(db.query(query, inputs)?)),
+        }
+    }
+}
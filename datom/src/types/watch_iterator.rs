@@ -0,0 +1,120 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+use crate::{
+    dependencies::Dependencies, storage::Storage, Connection, QueryError, Subscription, TxReport,
+    Watch, WatchResult, DEFAULT_SUBSCRIPTION_CAPACITY,
+};
+
+/**
+An iterator over the results of a [Watch], as returned by
+[Connection::watch]
+
+The first item is the result as of when the watch was registered.
+After that, iterating blocks until a transaction touching the watch's
+dependencies is committed, and yields the result as of that
+transaction. If the watch falls behind, as a
+[Subscription] can, it catches up by yielding the result as of the
+latest transaction.
+*/
+pub struct WatchIterator<'connection, S: Storage> {
+    connection: &'connection Connection<S>,
+    watch: Watch,
+    dependencies: Dependencies,
+    subscription: Subscription,
+    t: u64,
+    initial: Option<Result<WatchResult, QueryError>>,
+}
+
+impl<'connection, S: Storage> WatchIterator<'connection, S> {
+    pub(crate) fn new(
+        connection: &'connection Connection<S>,
+        watch: Watch,
+    ) -> Result<Self, QueryError> {
+        let dependencies = watch.dependencies(&connection.db()?)?;
+        // Subscribing before reading the initial result means no
+        // transaction can fall between the two
+        let subscription =
+            connection.add_subscriber(DEFAULT_SUBSCRIPTION_CAPACITY, Some(dependencies.clone()));
+        let t = connection.latest_t()?;
+        let initial = Some(watch.evaluate(&connection.as_of(t)?));
+        Ok(Self {
+            connection,
+            watch,
+            dependencies,
+            subscription,
+            t,
+            initial,
+        })
+    }
+
+    /// The t-value of the database the latest result was read from
+    pub const fn t(&self) -> u64 {
+        self.t
+    }
+
+    /// Get the next result if a relevant transaction has already been
+    /// committed, without blocking
+    pub fn try_next(&mut self) -> Option<Result<WatchResult, QueryError>> {
+        if let Some(initial) = self.initial.take() {
+            return Some(initial);
+        }
+        loop {
+            let report = self.subscription.try_iter().next();
+            if report.is_none() && !self.subscription.fell_behind() {
+                return None;
+            }
+            if let Some(res) = self.advance(report) {
+                return Some(res);
+            }
+        }
+    }
+
+    /**
+    Re-run the read as of a reported transaction, or as of the latest
+    transaction if the subscription ended because it fell behind.
+    Returns [None] if the transaction was already accounted for.
+    */
+    fn advance(&mut self, report: Option<TxReport>) -> Option<Result<WatchResult, QueryError>> {
+        let t = match report {
+            Some(report) => report.after_t,
+            None => {
+                self.subscription = self.connection.add_subscriber(
+                    DEFAULT_SUBSCRIPTION_CAPACITY,
+                    Some(self.dependencies.clone()),
+                );
+                match self.connection.latest_t() {
+                    Ok(t) => t,
+                    Err(e) => return Some(Err(e.into())),
+                }
+            }
+        };
+        if t <= self.t {
+            return None;
+        }
+        self.t = t;
+        Some(
+            self.connection
+                .as_of(t)
+                .map_err(QueryError::from)
+                .and_then(|db| self.watch.evaluate(&db)),
+        )
+    }
+}
+
+impl<'connection, S: Storage> Iterator for WatchIterator<'connection, S> {
+    type Item = Result<WatchResult, QueryError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(initial) = self.initial.take() {
+            return Some(initial);
+        }
+        loop {
+            let report = self.subscription.next();
+            if let Some(res) = self.advance(report) {
+                return Some(res);
+            }
+        }
+    }
+}
@@ -0,0 +1,16 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+use crate::{Datom, QueryResult, Value};
+
+/// The result of a [Watch](crate::Watch)'s read
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WatchResult {
+    /// The values of a [Watch::Attribute](crate::Watch::Attribute)
+    Values(Vec<Value>),
+    /// The datoms of a [Watch::IndexRange](crate::Watch::IndexRange)
+    Datoms(Vec<Datom>),
+    /// The result of a [Watch::Query](crate::Watch::Query)
+    Query(QueryResult),
+}
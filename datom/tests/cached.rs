@@ -0,0 +1,81 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+#![cfg(feature = "redblacktreeset")]
+
+mod common;
+
+use std::ops::Range;
+
+use common::storage::read;
+use datom::{
+    backends::{CachedStorage, RedBlackTreeSetStorage},
+    storage::Storage,
+};
+use miette::Result;
+
+#[test]
+fn hits_and_invalidation() -> Result<()> {
+    let storage = CachedStorage::new(RedBlackTreeSetStorage::new(), 1024);
+    storage.insert(&[vec![1], vec![2, 0], vec![3]])?;
+    let r = &[2][..]..&[3][..];
+    assert_eq!(read(&storage, r.clone())?, vec![vec![2, 0]]);
+    assert_eq!(read(&storage, r.clone())?, vec![vec![2, 0]]);
+    assert_eq!((storage.hits(), storage.misses()), (1, 1));
+
+    // Reads from the back are cached in order
+    let all = &[0][..]..&[255][..];
+    assert_eq!(storage.range(all.clone())?.rev().count(), 3);
+    assert_eq!(read(&storage, all.clone())?.len(), 3);
+    assert_eq!((storage.hits(), storage.misses()), (2, 2));
+
+    // Inserts only drop the ranges they fall within
+    storage.insert(&[vec![2, 1]])?;
+    assert_eq!(read(&storage, r.clone())?, vec![vec![2, 0], vec![2, 1]]);
+    assert_eq!(read(&storage, all.clone())?.len(), 4);
+    assert_eq!((storage.hits(), storage.misses()), (2, 4));
+    storage.insert(&[vec![4]])?;
+    read(&storage, r)?;
+    assert_eq!((storage.hits(), storage.misses()), (3, 4));
+
+    // Partial reads aren't cached
+    let mut partial = storage.range(all.clone())?;
+    partial.next();
+    drop(partial);
+    read(&storage, all)?;
+    assert_eq!((storage.hits(), storage.misses()), (3, 6));
+    Ok(())
+}
+
+#[test]
+fn eviction() -> Result<()> {
+    let storage = CachedStorage::new(RedBlackTreeSetStorage::new(), 10);
+    storage.insert(&[vec![1, 1], vec![2, 2], vec![3, 3]])?;
+    // Each range takes 2 bytes of bounds and 2 of items
+    let ranges: Vec<Range<&[u8]>> = vec![&[1][..]..&[2][..], &[2][..]..&[3][..]];
+    for r in ranges.iter() {
+        read(&storage, r.clone())?;
+    }
+    assert_eq!(storage.cached_bytes(), 8);
+    // Using the first range makes the second the least recent
+    read(&storage, ranges[0].clone())?;
+    read(&storage, &[3][..]..&[4][..])?;
+    assert_eq!(storage.cached_bytes(), 8);
+    let misses = storage.misses();
+    read(&storage, ranges[0].clone())?;
+    assert_eq!(storage.misses(), misses);
+    read(&storage, ranges[1].clone())?;
+    assert_eq!(storage.misses(), misses + 1);
+
+    // Ranges larger than the whole budget aren't cached
+    storage.insert(&[vec![5; 20]])?;
+    let bytes = storage.cached_bytes();
+    let misses = storage.misses();
+    for _ in 0..2 {
+        read(&storage, &[5][..]..&[6][..])?;
+    }
+    assert_eq!(storage.misses(), misses + 2);
+    assert_eq!(storage.cached_bytes(), bytes);
+    Ok(())
+}
@@ -93,28 +93,32 @@ pub fn db_users_transacted_properly<S: Storage>(db: &Database<'_, S>) -> Result<
         let mut friends = HashSet::new();
         if let EntityResult::Repeated(results) = user_ent.get("user/friends".into())? {
             for res in results.into_iter() {
-                friends.insert(res);
+                match res {
+                    EntityResult::Ref(friend) => friends.insert(Value::ID(*friend.id())),
+                    EntityResult::Value(friend) => friends.insert(friend),
+                    _ => panic!(),
+                };
             }
         } else {
             panic!();
         }
         for friend in user.friends.iter() {
-            if let Value::ID(id) = friend {
-                assert!(friends.contains(&EntityResult::Ref(db.entity(id.to_owned().into())?)));
-            } else {
-                assert!(friends.contains(&EntityResult::Value(friend.to_owned())));
-            }
+            assert!(friends.contains(friend));
         }
         let mut numbers = HashSet::new();
         if let EntityResult::Repeated(results) = user_ent.get("user/repeated-numbers".into())? {
             for res in results.into_iter() {
-                numbers.insert(res);
+                if let EntityResult::Value(number) = res {
+                    numbers.insert(number);
+                } else {
+                    panic!();
+                }
             }
         } else {
             panic!();
         }
         for number in user.repeated_numbers.iter() {
-            assert!(numbers.contains(&EntityResult::Value(number.into())));
+            assert!(numbers.contains(&number.into()));
         }
     }
     Ok(())
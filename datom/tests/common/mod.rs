@@ -6,3 +6,5 @@
 pub mod data;
 #[allow(dead_code)]
 pub mod schema;
+#[allow(dead_code)]
+pub mod storage;
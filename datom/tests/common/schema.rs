@@ -2,13 +2,23 @@
 // SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
 // SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
 
+#[cfg(feature = "sled")]
+use datom::backends::CachedStorage;
 #[cfg(feature = "redblacktreeset")]
 use datom::backends::RedBlackTreeSetStorage;
+#[cfg(feature = "segment")]
+use datom::backends::SegmentStorage;
 #[cfg(feature = "sled")]
 use datom::backends::SledStorage;
+#[cfg(feature = "sqlite")]
+use datom::backends::SqliteStorage;
+#[cfg(all(feature = "sled", feature = "redblacktreeset"))]
+use datom::backends::TieredStorage;
+#[cfg(feature = "objectstore")]
+use datom::{backends::ObjectStorage, object_store::LocalObjectStore};
 use datom::{
-    backends::TieredStorage, builtin_idents, new_dynamic_connection, AttributeSchema,
-    AttributeType, DynamicConnection, EntityResult, Transaction,
+    builtin_idents, new_dynamic_connection, AttributeSchema, AttributeType, DynamicConnection,
+    EntityResult, Transaction,
 };
 use miette::Result;
 use once_cell::sync::Lazy;
@@ -20,6 +30,11 @@ static ATTRIBUTES: Lazy<Vec<AttributeSchema>> = Lazy::new(|| {
             .value_type(AttributeType::String)
             .doc("The user's unique username".into())
             .unique(),
+        AttributeSchema::new()
+            .ident("user/email".into())
+            .value_type(AttributeType::String)
+            .doc("The user's email address, which identifies them".into())
+            .identity(),
         AttributeSchema::new()
             .ident("user/admin?".into())
             .value_type(AttributeType::Boolean),
@@ -36,6 +51,10 @@ static ATTRIBUTES: Lazy<Vec<AttributeSchema>> = Lazy::new(|| {
             .ident("user/repeated-numbers".into())
             .value_type(AttributeType::Integer)
             .many(),
+        AttributeSchema::new()
+            .ident("user/age".into())
+            .value_type(AttributeType::Integer)
+            .index(),
     ]
     .into()
 });
@@ -79,6 +98,42 @@ pub fn tiered_connection_with_schema() -> Result<DynamicConnection> {
     Ok(conn)
 }
 
+#[cfg(feature = "sled")]
+pub fn cached_connection_with_schema() -> Result<DynamicConnection> {
+    use miette::IntoDiagnostic;
+
+    // Small enough that the tests evict ranges
+    let storage = CachedStorage::new(SledStorage::connect_temp().into_diagnostic()?, 1 << 14);
+    let conn = new_dynamic_connection(storage);
+    transact_schema(&conn)?;
+    Ok(conn)
+}
+
+#[cfg(feature = "segment")]
+pub fn segment_connection_with_schema() -> Result<DynamicConnection> {
+    let storage = SegmentStorage::connect_temp()?;
+    let conn = new_dynamic_connection(storage);
+    transact_schema(&conn)?;
+    Ok(conn)
+}
+
+#[cfg(feature = "sqlite")]
+pub fn sqlite_connection_with_schema() -> Result<DynamicConnection> {
+    let storage = SqliteStorage::connect_temp()?;
+    let conn = new_dynamic_connection(storage);
+    transact_schema(&conn)?;
+    Ok(conn)
+}
+
+#[cfg(feature = "objectstore")]
+pub fn object_connection_with_schema() -> Result<DynamicConnection> {
+    // Small enough that the tests build trees several levels deep
+    let storage = ObjectStorage::new(LocalObjectStore::temp()?)?.segment_size(1 << 10);
+    let conn = new_dynamic_connection(storage);
+    transact_schema(&conn)?;
+    Ok(conn)
+}
+
 pub fn with_connection<F: Fn(DynamicConnection) -> Result<()>>(f: F) -> Result<()> {
     #[cfg(feature = "sled")]
     f(sled_connection_with_schema()?)?;
@@ -86,6 +141,14 @@ pub fn with_connection<F: Fn(DynamicConnection) -> Result<()>>(f: F) -> Result<(
     f(redblacktreeset_connection_with_schema()?)?;
     #[cfg(all(feature = "sled", feature = "redblacktreeset"))]
     f(tiered_connection_with_schema()?)?;
+    #[cfg(feature = "sled")]
+    f(cached_connection_with_schema()?)?;
+    #[cfg(feature = "segment")]
+    f(segment_connection_with_schema()?)?;
+    #[cfg(feature = "sqlite")]
+    f(sqlite_connection_with_schema()?)?;
+    #[cfg(feature = "objectstore")]
+    f(object_connection_with_schema()?)?;
     Ok(())
 }
 
@@ -0,0 +1,94 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+use std::{
+    env::temp_dir,
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use datom::{
+    storage::{Item, ItemIterator, Storage},
+    StorageError, ID,
+};
+use miette::Result;
+
+/// A temporary directory, removed when it's dropped
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new() -> Self {
+        let path = temp_dir().join(ID::new().to_string());
+        fs::create_dir(&path).expect("a temporary directory");
+        Self(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.to_str().expect("a UTF-8 path")
+    }
+
+    pub fn join(&self, name: &str) -> String {
+        self.0.join(name).to_str().expect("a UTF-8 path").to_owned()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.0).ok();
+    }
+}
+
+/// Read every item in a range of a storage
+pub fn read(storage: &impl Storage, r: Range<&[u8]>) -> Result<Vec<Item>> {
+    Ok(storage.range(r)?.collect::<Result<_, _>>()?)
+}
+
+/// Read every item of a storage whose first byte isn't 255
+pub fn items(storage: &impl Storage) -> Result<Vec<Item>> {
+    read(storage, &[0][..]..&[255][..])
+}
+
+/// A storage which counts the range reads made of it
+pub struct CountingStorage<S: Storage> {
+    storage: S,
+    reads: AtomicUsize,
+}
+
+impl<S: Storage> CountingStorage<S> {
+    pub const fn new(storage: S) -> Self {
+        Self {
+            storage,
+            reads: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn reads(&self) -> usize {
+        self.reads.load(Ordering::SeqCst)
+    }
+}
+
+impl<S: Storage> Storage for CountingStorage<S> {
+    fn range(&self, r: Range<&[u8]>) -> Result<ItemIterator<'_>, StorageError> {
+        self.reads.fetch_add(1, Ordering::SeqCst);
+        self.storage.range(r)
+    }
+
+    fn insert(&self, is: &[Item]) -> Result<(), StorageError> {
+        self.storage.insert(is)
+    }
+
+    fn insert_if_empty(&self, guard: Range<&[u8]>, is: &[Item]) -> Result<(), StorageError> {
+        self.storage.insert_if_empty(guard, is)
+    }
+
+    fn id(&self) -> ID {
+        self.storage.id()
+    }
+}
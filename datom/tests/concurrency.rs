@@ -0,0 +1,120 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+mod common;
+
+use std::{
+    collections::HashSet,
+    ops::Range,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use common::schema::{transact_schema, with_connection};
+#[cfg(feature = "redblacktreeset")]
+use datom::backends::RedBlackTreeSetStorage;
+use datom::{
+    new_dynamic_connection,
+    storage::{Item, ItemIterator, Storage},
+    Connection, ConnectionError, EntityResult, StorageError, Transaction, TransactionError, ID,
+};
+use miette::Result;
+
+#[test]
+fn concurrent_transactions() -> Result<()> {
+    with_connection(|conn| {
+        let user = ID::new();
+        let t_before = conn.latest_t()?;
+        let ts = thread::scope(|s| {
+            let handles: Vec<_> = (0..8)
+                .map(|i| {
+                    let conn = &conn;
+                    s.spawn(move || {
+                        let mut tx = Transaction::new();
+                        tx.add(user.into(), "user/repeated-numbers".into(), i.into());
+                        conn.transact(tx).map(|res| res.after.t())
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|h| h.join().expect("transacting thread panicked"))
+                .collect::<Result<HashSet<u64>, _>>()
+        })?;
+        assert_eq!(ts, (t_before + 1..=t_before + 8).collect());
+        let EntityResult::Repeated(numbers) = conn
+            .db()?
+            .entity(user.into())?
+            .get("user/repeated-numbers".into())?
+        else {
+            panic!("repeated-numbers isn't repeated");
+        };
+        assert_eq!(numbers.len(), 8);
+        Ok(())
+    })
+}
+
+/// A storage which lets a rival connection commit a transaction just
+/// before its own next commit
+#[cfg(feature = "redblacktreeset")]
+struct RacingStorage {
+    inner: Arc<RedBlackTreeSetStorage>,
+    rival: Mutex<Option<Transaction>>,
+}
+
+#[cfg(feature = "redblacktreeset")]
+impl Storage for RacingStorage {
+    fn range(&self, r: Range<&[u8]>) -> Result<ItemIterator<'_>, StorageError> {
+        self.inner.range(r)
+    }
+
+    fn insert(&self, is: &[Item]) -> Result<(), StorageError> {
+        self.inner.insert(is)
+    }
+
+    fn insert_if_empty(&self, guard: Range<&[u8]>, is: &[Item]) -> Result<(), StorageError> {
+        if let Some(tx) = self.rival.lock().expect("poisoned").take() {
+            Connection::new(self.inner.clone())
+                .transact(tx)
+                .expect("rival transaction failed");
+        }
+        self.inner.insert_if_empty(guard, is)
+    }
+
+    fn id(&self) -> ID {
+        self.inner.id()
+    }
+}
+
+#[cfg(feature = "redblacktreeset")]
+#[test]
+fn external_writer_wins() -> Result<()> {
+    let inner = Arc::new(RedBlackTreeSetStorage::new());
+    transact_schema(&new_dynamic_connection(inner.clone()))?;
+    let user = ID::new();
+    let mut rival = Transaction::new();
+    rival.add(user.into(), "user/username".into(), "rival".into());
+    let conn = new_dynamic_connection(RacingStorage {
+        inner,
+        rival: Mutex::new(Some(rival)),
+    });
+
+    let t_before = conn.latest_t()?;
+    let mut tx = Transaction::new();
+    tx.add(user.into(), "user/username".into(), "loser".into());
+    assert!(matches!(
+        conn.transact(tx),
+        Err(TransactionError::ConnectionError(ConnectionError::Storage(
+            StorageError::ConcurrencyError
+        )))
+    ));
+    assert_eq!(conn.latest_t()?, t_before + 1);
+    assert_eq!(
+        conn.db()?
+            .entity(user.into())?
+            .get("user/username".into())?,
+        EntityResult::Value("rival".into())
+    );
+    Ok(())
+}
@@ -0,0 +1,217 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+mod common;
+
+use std::ops::Bound;
+
+use common::schema::with_connection;
+use datom::{DatomType, Index, QueryError, Transaction, Value, EID, ID};
+use miette::Result;
+
+#[test]
+fn index_range() -> Result<()> {
+    with_connection(|conn| {
+        let mut tx = Transaction::new();
+        for age in [30, -2, 18, 7, 1000, 29] {
+            tx.add(ID::new().into(), "user/age".into(), age.into());
+        }
+        for name in ["b", "aa", "a", "ab"] {
+            tx.add(ID::new().into(), "user/username".into(), name.into());
+        }
+        conn.transact(tx)?;
+        let before = conn.db()?;
+        let mut tx = Transaction::new();
+        tx.add(ID::new().into(), "user/age".into(), 20.into());
+        conn.transact(tx)?;
+        let db = conn.db()?;
+        let age = EID::from("user/age").resolve(&db)?;
+        let username = EID::from("user/username").resolve(&db)?;
+        let values = |start: Bound<Value>, end: Bound<Value>| -> Result<Vec<Value>> {
+            Ok(db
+                .index_range(age, start, end)?
+                .map(|datom| datom.map(|datom| datom.value))
+                .collect::<Result<_, _>>()?)
+        };
+
+        assert_eq!(
+            values(Bound::Included(18.into()), Bound::Excluded(30.into()))?,
+            vec![18.into(), 20.into(), 29.into()]
+        );
+        assert_eq!(
+            values(Bound::Excluded(18.into()), Bound::Included(30.into()))?,
+            vec![20.into(), 29.into(), 30.into()]
+        );
+        assert_eq!(
+            values(Bound::Unbounded, Bound::Excluded(18.into()))?,
+            vec![(-2).into(), 7.into()]
+        );
+        assert_eq!(
+            values(Bound::Included(30.into()), Bound::Unbounded)?,
+            vec![30.into(), 1000.into()]
+        );
+        assert_eq!(
+            values(Bound::Included(30.into()), Bound::Excluded(18.into()))?,
+            vec![]
+        );
+
+        // Scans respect the database's t
+        let before_values: Vec<Value> = before
+            .index_range(age, Bound::Unbounded, Bound::Unbounded)?
+            .map(|datom| datom.map(|datom| datom.value))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(
+            before_values,
+            vec![
+                (-2).into(),
+                7.into(),
+                18.into(),
+                29.into(),
+                30.into(),
+                1000.into()
+            ]
+        );
+
+        let names: Vec<Value> = db
+            .index_range(username, Bound::Included("a".into()), Bound::Unbounded)?
+            .map(|datom| datom.map(|datom| datom.value))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(
+            names,
+            vec!["a".into(), "aa".into(), "ab".into(), "b".into()]
+        );
+
+        let admin = EID::from("user/admin?").resolve(&db)?;
+        assert!(matches!(
+            db.index_range(admin, Bound::Unbounded, Bound::Unbounded),
+            Err(QueryError::UnindexedAttribute(id)) if id == admin
+        ));
+        Ok(())
+    })
+}
+
+#[test]
+fn keys_ending_in_ff() -> Result<()> {
+    with_connection(|conn| {
+        // The last byte of each ID and integer is 0xFF or its successor
+        let (alice, bob) = (ID::from_u128(0x1ff), ID::from_u128(0x200));
+        let mut tx = Transaction::new();
+        for age in [254, 255, 256, 300, 511, 512, 1000] {
+            tx.add(ID::new().into(), "user/age".into(), age.into());
+        }
+        tx.add(alice.into(), "user/friends".into(), bob.into());
+        tx.add(bob.into(), "user/friends".into(), alice.into());
+        conn.transact(tx)?;
+        let db = conn.db()?;
+        let age = EID::from("user/age").resolve(&db)?;
+        let values = |start: Bound<Value>, end: Bound<Value>| -> Result<Vec<Value>> {
+            Ok(db
+                .index_range(age, start, end)?
+                .map(|datom| datom.map(|datom| datom.value))
+                .collect::<Result<_, _>>()?)
+        };
+
+        assert_eq!(
+            values(Bound::Excluded(255.into()), Bound::Unbounded)?,
+            vec![256.into(), 300.into(), 511.into(), 512.into(), 1000.into()]
+        );
+        assert_eq!(
+            values(Bound::Unbounded, Bound::Included(255.into()))?,
+            vec![254.into(), 255.into()]
+        );
+        assert_eq!(
+            values(Bound::Excluded(255.into()), Bound::Included(511.into()))?,
+            vec![256.into(), 300.into(), 511.into()]
+        );
+        for n in [255, 511] {
+            let matching: Vec<Value> = db
+                .datoms_for_attribute_value(age, n.into())?
+                .map(|datom| datom.map(|datom| datom.value))
+                .collect::<Result<_, _>>()?;
+            assert_eq!(matching, vec![n.into()]);
+        }
+
+        let friends = EID::from("user/friends").resolve(&db)?;
+        let referrers: Vec<ID> = db
+            .datoms_for_value(alice.into())?
+            .map(|datom| datom.map(|datom| datom.entity))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(referrers, vec![bob]);
+        let referrers: Vec<ID> = db
+            .datoms_for_value_attribute(alice.into(), friends)?
+            .map(|datom| datom.map(|datom| datom.entity))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(referrers, vec![bob]);
+        Ok(())
+    })
+}
+
+#[test]
+fn seek_datoms() -> Result<()> {
+    with_connection(|conn| {
+        let users: Vec<ID> = (0..4).map(|_| ID::new()).collect();
+        let mut tx = Transaction::new();
+        for (n, user) in users.iter().enumerate() {
+            tx.add((*user).into(), "user/age".into(), n.into());
+        }
+        conn.transact(tx)?;
+        let mut tx = Transaction::new();
+        tx.retract_value(users[0].into(), "user/age".into(), 0.into());
+        conn.transact(tx)?;
+        let db = conn.db()?;
+        let age = EID::from("user/age").resolve(&db)?;
+
+        let mut sorted = users.clone();
+        sorted.sort();
+        let entities: Vec<ID> = db
+            .seek_datoms(Index::AEVT, &[age.into(), sorted[1].into()])?
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .take_while(|datom| datom.attribute == age)
+            .filter(|datom| datom.datom_type == DatomType::Addition)
+            .map(|datom| datom.entity)
+            .collect();
+        assert_eq!(entities, sorted[1..]);
+
+        // The retraction is visible when seeking to its entity
+        let datoms: Vec<DatomType> = db
+            .seek_datoms(Index::AEVT, &[age.into(), users[0].into()])?
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .take_while(|datom| datom.entity == users[0])
+            .map(|datom| datom.datom_type)
+            .collect();
+        assert_eq!(datoms, vec![DatomType::Addition, DatomType::Retraction]);
+
+        assert!(matches!(
+            db.seek_datoms(Index::AEVT, &["user/age".into()]),
+            Err(QueryError::InvalidInput(_))
+        ));
+        Ok(())
+    })
+}
+
+#[cfg(feature = "redblacktreeset")]
+#[test]
+fn unreadable_datoms() -> Result<()> {
+    use std::sync::Arc;
+
+    use datom::{backends::RedBlackTreeSetStorage, storage::Storage, Connection, ConnectionError};
+
+    let storage = Arc::new(RedBlackTreeSetStorage::new());
+    let conn = Connection::new(storage.clone());
+    let mut tx = Transaction::new();
+    tx.add(ID::new().into(), "db/doc".into(), "readable".into());
+    conn.transact(tx)?;
+    storage.insert(&[vec![Index::EAVT.byte(), 1, 2, 3]])?;
+
+    // An item which isn't a datom is an error, rather than being
+    // skipped or ending the scan
+    let datoms: Vec<_> = conn.db()?.datoms(Index::EAVT)?.collect();
+    assert!(datoms.iter().any(|datom| datom.is_ok()));
+    assert!(datoms
+        .iter()
+        .any(|datom| matches!(datom, Err(ConnectionError::InvalidData))));
+    Ok(())
+}
@@ -0,0 +1,96 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+mod common;
+
+use std::ops::Bound;
+
+use common::schema::with_connection;
+use datom::{
+    builtin_idents, DatomType, LogEntry, LogIterator, Transaction, TransactionRecord, Value, ID,
+};
+use miette::Result;
+
+#[test]
+fn log() -> Result<()> {
+    with_connection(|conn| {
+        let user = ID::new();
+        let mut tx = Transaction::new();
+        tx.add(user.into(), "user/username".into(), "alice".into());
+        let first = conn.transact(tx)?.after.t();
+        let mut tx = Transaction::new();
+        tx.add(user.into(), "user/username".into(), "alicia".into());
+        let second = conn.transact(tx)?.after.t();
+        let mut tx = Transaction::new();
+        tx.add(user.into(), "user/admin?".into(), true.into());
+        let third = conn.transact(tx)?.after.t();
+
+        let entries = conn
+            .log(first..=second)?
+            .collect::<Result<Vec<LogEntry>, _>>()?;
+        let ts: Vec<u64> = entries.iter().map(|entry| entry.record.t).collect();
+        assert_eq!(ts, vec![first, second]);
+        for entry in entries.iter() {
+            assert!(entry.data.iter().all(|datom| datom.t == entry.record.t));
+            assert!(entry.data.iter().any(|datom| {
+                datom.entity == TransactionRecord::id_for_t(entry.record.t)
+                    && datom.attribute == builtin_idents::TX_INSTANT
+                    && datom.value == Value::from(entry.record.timestamp.timestamp_millis())
+            }));
+        }
+        let mut changes: Vec<(Value, DatomType)> = entries[1]
+            .data
+            .iter()
+            .filter(|datom| datom.entity == user)
+            .map(|datom| (datom.value.clone(), datom.datom_type))
+            .collect();
+        changes.sort_by_key(|(_, datom_type)| *datom_type == DatomType::Addition);
+        assert_eq!(
+            changes,
+            vec![
+                ("alice".into(), DatomType::Retraction),
+                ("alicia".into(), DatomType::Addition),
+            ]
+        );
+
+        // The log can be read from either end
+        let latest = conn.log(first..)?.next_back().transpose()?;
+        assert_eq!(latest.map(|entry| entry.record.t), Some(third));
+        assert_eq!(conn.log(third + 1..)?.count(), 0);
+        assert_eq!(conn.log(second..first)?.count(), 0);
+        assert_eq!(conn.log(..)?.count() as u64, conn.latest_t()?);
+        Ok(())
+    })
+}
+
+#[test]
+fn long_log() -> Result<()> {
+    with_connection(|conn| {
+        let user = ID::new();
+        while conn.latest_t()? < 300 {
+            let mut tx = Transaction::new();
+            tx.add(user.into(), "user/age".into(), conn.latest_t()?.into());
+            conn.transact(tx)?;
+        }
+        let ts = |entries: LogIterator<'_, _>| -> Result<Vec<u64>> {
+            Ok(entries
+                .map(|entry| entry.map(|entry| entry.record.t))
+                .collect::<Result<_, _>>()?)
+        };
+
+        // Bounds whose low byte is 0xFF
+        assert_eq!(ts(conn.log(255..=256)?)?, vec![255, 256]);
+        assert_eq!(ts(conn.log(254..=255)?)?, vec![254, 255]);
+        assert_eq!(
+            ts(conn.log((Bound::Excluded(255), Bound::Included(257)))?)?,
+            vec![256, 257]
+        );
+        assert_eq!(ts(conn.log(298..)?)?, vec![298, 299, 300]);
+        assert_eq!(conn.log(..=255)?.count(), 255);
+        assert_eq!(conn.log(256..)?.count(), 45);
+        assert_eq!(conn.log(u64::MAX..)?.count(), 0);
+        assert_eq!(conn.log(..=u64::MAX)?.count(), 300);
+        Ok(())
+    })
+}
@@ -0,0 +1,97 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+#![cfg(feature = "objectstore")]
+
+mod common;
+
+use std::{fs, ops::Range};
+
+use common::storage::{read, TempDir};
+use datom::{
+    backends::ObjectStorage,
+    object_store::{LocalObjectStore, ObjectStore},
+    storage::{Item, Storage},
+    StorageError,
+};
+use miette::{IntoDiagnostic, Result};
+
+fn items(range: Range<u16>) -> Vec<Item> {
+    range
+        .map(|i| [&[1][..], &i.to_be_bytes(), &[0; 20]].concat())
+        .collect()
+}
+
+#[test]
+fn segment_trees() -> Result<()> {
+    let dir = TempDir::new();
+    let storage = ObjectStorage::new(LocalObjectStore::new(dir.as_str())?)?.segment_size(256);
+    // Inserting out of order and in several batches touches leaves
+    // all over the tree
+    let all = items(0..2000);
+    for chunk in all.chunks(100).rev() {
+        storage.insert(&chunk.iter().step_by(2).cloned().collect::<Vec<_>>())?;
+    }
+    for chunk in all.chunks(300) {
+        storage.insert(chunk)?;
+    }
+    let root = storage.store().get("root")?.expect("a root pointer");
+    let root_segment = storage
+        .store()
+        .get(std::str::from_utf8(&root).into_diagnostic()?)?
+        .expect("a root segment");
+    // A segment starts with its level in the tree
+    assert!(root_segment[0] >= 2);
+    let stored = &[1][..]..&[2][..];
+    assert_eq!(read(&storage, stored.clone())?, all);
+
+    // Inserting items which are already stored writes nothing
+    let segments = || -> Result<usize> {
+        Ok(fs::read_dir(dir.path().join("segments"))
+            .into_diagnostic()?
+            .count())
+    };
+    let before = segments()?;
+    storage.insert(&all[500..600])?;
+    assert_eq!(segments()?, before);
+    assert_eq!(storage.store().get("root")?, Some(root));
+
+    // Another reader sees the same database, from the segments and
+    // root pointer alone
+    let reader = ObjectStorage::new(LocalObjectStore::new(dir.as_str())?)?;
+    let r = all[250].as_slice()..all[1700].as_slice();
+    let mut rev: Vec<Item> = reader.range(r.clone())?.rev().collect::<Result<_, _>>()?;
+    rev.reverse();
+    assert_eq!(rev, all[250..1700]);
+    assert_eq!(read(&reader, r)?, all[250..1700]);
+
+    // Writes from one are visible to the other
+    reader.insert(&items(2000..2001))?;
+    assert_eq!(read(&storage, stored)?.len(), 2001);
+    assert!(matches!(
+        storage.insert_if_empty(&all[0]..&all[1], &[]),
+        Err(StorageError::ConcurrencyError)
+    ));
+    Ok(())
+}
+
+#[test]
+fn branch_cache() -> Result<()> {
+    let dir = TempDir::new();
+    let storage = ObjectStorage::new(LocalObjectStore::new(dir.as_str())?)?
+        .segment_size(256)
+        .branch_cache_size(512);
+    let all = items(0..2000);
+    for chunk in all.chunks(100).rev() {
+        storage.insert(chunk)?;
+    }
+    assert!(storage.cached_branch_bytes() > 0);
+    assert!(storage.cached_branch_bytes() <= 512);
+
+    // Evicted branches are read from the store again
+    let r = all[250].as_slice()..all[1700].as_slice();
+    assert_eq!(read(&storage, r)?, all[250..1700]);
+    assert!(storage.cached_branch_bytes() <= 512);
+    Ok(())
+}
@@ -0,0 +1,20 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+use datom::object_store::{LocalObjectStore, ObjectStore};
+use miette::Result;
+
+#[test]
+fn compare_and_swap() -> Result<()> {
+    let store = LocalObjectStore::temp()?;
+    assert_eq!(store.get("a/b")?, None);
+    assert!(store.compare_and_swap("a/b", None, b"1")?);
+    assert!(!store.compare_and_swap("a/b", None, b"2")?);
+    assert!(!store.compare_and_swap("a/b", Some(b"2"), b"3")?);
+    assert!(store.compare_and_swap("a/b", Some(b"1"), b"3")?);
+    assert_eq!(store.get("a/b")?, Some(b"3".to_vec()));
+    store.put("a/b", b"4")?;
+    assert_eq!(store.get("a/b")?, Some(b"4".to_vec()));
+    Ok(())
+}
@@ -0,0 +1,201 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+mod common;
+
+use common::schema::with_connection;
+use datom::{DynamicConnection, PullMap, PullPattern, PullValue, Transaction, Value, ID};
+use miette::Result;
+
+struct People {
+    alice: ID,
+    bob: ID,
+    carol: ID,
+    customer: ID,
+}
+
+fn transact_people(conn: &DynamicConnection) -> Result<People> {
+    let people = People {
+        alice: ID::new(),
+        bob: ID::new(),
+        carol: ID::new(),
+        customer: ID::new(),
+    };
+    let mut tx = Transaction::new();
+    for (id, name) in [
+        (people.alice, "alice"),
+        (people.bob, "bob"),
+        (people.carol, "carol"),
+    ] {
+        tx.add(id.into(), "user/username".into(), name.into());
+    }
+    tx.add(
+        people.alice.into(),
+        "user/friends".into(),
+        people.bob.into(),
+    );
+    tx.add(
+        people.bob.into(),
+        "user/friends".into(),
+        people.alice.into(),
+    );
+    tx.add(
+        people.bob.into(),
+        "user/friends".into(),
+        people.carol.into(),
+    );
+    tx.add(
+        people.alice.into(),
+        "user/stripe-customer".into(),
+        people.customer.into(),
+    );
+    tx.add(people.customer.into(), "db/doc".into(), "cus_1234".into());
+    conn.transact(tx)?;
+    Ok(people)
+}
+
+fn value(v: impl Into<Value>) -> PullValue {
+    PullValue::Value(v.into())
+}
+
+fn id_map(id: ID) -> PullValue {
+    PullValue::Entity([("db/id".to_string(), value(id))].into())
+}
+
+fn many(value: &PullValue) -> &[PullValue] {
+    if let PullValue::Many(values) = value {
+        values
+    } else {
+        panic!("expected many values, got {:?}", value);
+    }
+}
+
+#[test]
+fn wildcard_expands_components() -> Result<()> {
+    with_connection(|conn| {
+        let people = transact_people(&conn)?;
+        let db = conn.db()?;
+        let pulled = db.pull(&PullPattern::wildcard(), people.alice.into())?;
+        let expected: PullMap = [
+            ("db/id".to_string(), value(people.alice)),
+            ("user/username".to_string(), value("alice")),
+            (
+                "user/friends".to_string(),
+                PullValue::Many(vec![id_map(people.bob)]),
+            ),
+            (
+                "user/stripe-customer".to_string(),
+                PullValue::Entity(
+                    [
+                        ("db/id".to_string(), value(people.customer)),
+                        ("db/doc".to_string(), value("cus_1234")),
+                    ]
+                    .into(),
+                ),
+            ),
+        ]
+        .into();
+        assert_eq!(pulled, expected);
+        Ok(())
+    })
+}
+
+#[test]
+fn nested_limit_and_default() -> Result<()> {
+    with_connection(|conn| {
+        let people = transact_people(&conn)?;
+        let db = conn.db()?;
+        let pattern: PullPattern =
+            "[(:user/admin? :default false) {:user/friends [:user/username]}]".parse()?;
+        let pulled = db.pull(&pattern, people.bob.into())?;
+        assert_eq!(pulled["user/admin?"], value(false));
+        let friends = many(&pulled["user/friends"]);
+        assert_eq!(friends.len(), 2);
+        for friend in friends {
+            let friend = friend.as_entity().expect("friends are pulled as maps");
+            assert!(
+                friend["user/username"] == value("alice")
+                    || friend["user/username"] == value("carol")
+            );
+        }
+
+        let limited: PullPattern = "[(:user/friends :limit 1)]".parse()?;
+        let pulled = db.pull(&limited, people.bob.into())?;
+        assert_eq!(many(&pulled["user/friends"]).len(), 1);
+        Ok(())
+    })
+}
+
+#[test]
+fn reverse_attributes() -> Result<()> {
+    with_connection(|conn| {
+        let people = transact_people(&conn)?;
+        let db = conn.db()?;
+        let pattern: PullPattern = "[{:user/_friends [:user/username]}]".parse()?;
+        let pulled = db.pull(&pattern, people.carol.into())?;
+        assert_eq!(
+            pulled["user/_friends"],
+            PullValue::Many(vec![PullValue::Entity(
+                [("user/username".to_string(), value("bob"))].into()
+            )])
+        );
+
+        let component: PullPattern = "[:user/_stripe-customer]".parse()?;
+        let pulled = db.pull(&component, people.customer.into())?;
+        assert_eq!(pulled["user/_stripe-customer"], id_map(people.alice));
+        Ok(())
+    })
+}
+
+#[test]
+fn recursion() -> Result<()> {
+    with_connection(|conn| {
+        let people = transact_people(&conn)?;
+        let db = conn.db()?;
+
+        let unbounded: PullPattern = "[:user/username {:user/friends ...}]".parse()?;
+        let pulled = db.pull(&unbounded, people.alice.into())?;
+        let bob = many(&pulled["user/friends"])[0]
+            .as_entity()
+            .expect("bob is pulled as a map")
+            .to_owned();
+        assert_eq!(bob["user/username"], value("bob"));
+        let bobs_friends = many(&bob["user/friends"]);
+        assert_eq!(bobs_friends.len(), 2);
+        // Alice is already being pulled, so the cycle stops there
+        assert!(bobs_friends.contains(&id_map(people.alice)));
+        assert!(bobs_friends.contains(&PullValue::Entity(
+            [("user/username".to_string(), value("carol"))].into()
+        )));
+
+        let bounded: PullPattern = "[:user/username {:user/friends 1}]".parse()?;
+        let pulled = db.pull(&bounded, people.carol.into())?;
+        assert!(!pulled.contains_key("user/friends"));
+        let pulled = db.pull(&bounded, people.alice.into())?;
+        let bob = many(&pulled["user/friends"])[0]
+            .as_entity()
+            .expect("bob is pulled as a map")
+            .to_owned();
+        assert!(many(&bob["user/friends"]).contains(&id_map(people.carol)));
+        Ok(())
+    })
+}
+
+#[test]
+fn pull_many() -> Result<()> {
+    with_connection(|conn| {
+        let people = transact_people(&conn)?;
+        let db = conn.db()?;
+        let pattern: PullPattern = "[:user/username]".parse()?;
+        let pulled = db.pull_many(&pattern, vec![people.alice.into(), people.carol.into()])?;
+        assert_eq!(
+            pulled,
+            vec![
+                [("user/username".to_string(), value("alice"))].into(),
+                [("user/username".to_string(), value("carol"))].into(),
+            ]
+        );
+        Ok(())
+    })
+}
@@ -0,0 +1,237 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+mod common;
+
+use std::collections::HashSet;
+
+use common::schema::with_connection;
+use datom::{
+    DynamicConnection, Query, QueryError, QueryInput, QueryResult, Transaction, Value, ID,
+};
+use miette::Result;
+
+struct People {
+    alice: ID,
+    bob: ID,
+    carol: ID,
+}
+
+fn transact_people(conn: &DynamicConnection) -> Result<People> {
+    let people = People {
+        alice: ID::new(),
+        bob: ID::new(),
+        carol: ID::new(),
+    };
+    let mut tx = Transaction::new();
+    for (id, name, admin) in [
+        (people.alice, "alice", true),
+        (people.bob, "bob", false),
+        (people.carol, "carol", false),
+    ] {
+        tx.add(id.into(), "user/username".into(), name.into());
+        tx.add(id.into(), "user/admin?".into(), admin.into());
+    }
+    tx.add(
+        people.alice.into(),
+        "user/friends".into(),
+        people.bob.into(),
+    );
+    tx.add(
+        people.alice.into(),
+        "user/friends".into(),
+        people.carol.into(),
+    );
+    tx.add(
+        people.bob.into(),
+        "user/friends".into(),
+        people.carol.into(),
+    );
+    conn.transact(tx)?;
+    Ok(people)
+}
+
+fn relation_set(res: QueryResult) -> HashSet<Vec<Value>> {
+    if let QueryResult::Relation(rows) = res {
+        rows.into_iter().collect()
+    } else {
+        panic!("expected a relation, got {:?}", res);
+    }
+}
+
+#[test]
+fn join_through_refs() -> Result<()> {
+    with_connection(|conn| {
+        transact_people(&conn)?;
+        let db = conn.db()?;
+        let query: Query = "[:find ?name ?friend-name
+                             :where [?e :user/username ?name]
+                                    [?e :user/friends ?f]
+                                    [?f :user/username ?friend-name]]"
+            .parse()?;
+        let expected: HashSet<Vec<Value>> = [
+            vec!["alice".into(), "bob".into()],
+            vec!["alice".into(), "carol".into()],
+            vec!["bob".into(), "carol".into()],
+        ]
+        .into();
+        assert_eq!(relation_set(db.query(&query, &[])?), expected);
+        Ok(())
+    })
+}
+
+#[test]
+fn reverse_lookup() -> Result<()> {
+    with_connection(|conn| {
+        let people = transact_people(&conn)?;
+        let db = conn.db()?;
+        let query: Query = "[:find [?name ...]
+                             :in $ ?friend
+                             :where [?e :user/friends ?friend]
+                                    [?e :user/username ?name]]"
+            .parse()?;
+        let res = db.query(&query, &[Value::from(people.carol).into()])?;
+        let QueryResult::Collection(names) = res else {
+            panic!("expected a collection, got {:?}", res);
+        };
+        let names: HashSet<Value> = names.into_iter().collect();
+        assert_eq!(names, ["alice".into(), "bob".into()].into());
+        Ok(())
+    })
+}
+
+#[test]
+fn input_bindings() -> Result<()> {
+    with_connection(|conn| {
+        let people = transact_people(&conn)?;
+        let db = conn.db()?;
+
+        let scalar: Query = "[:find ?e . :in $ ?name :where [?e :user/username ?name]]".parse()?;
+        assert_eq!(
+            db.query(&scalar, &[Value::from("bob").into()])?,
+            QueryResult::Scalar(Some(people.bob.into()))
+        );
+        assert_eq!(
+            db.query(&scalar, &[Value::from("nobody").into()])?,
+            QueryResult::Scalar(None)
+        );
+
+        let collection: Query =
+            "[:find ?e :in $ [?name ...] :where [?e :user/username ?name]]".parse()?;
+        let res = db.query(
+            &collection,
+            &[QueryInput::Collection(vec!["alice".into(), "carol".into()])],
+        )?;
+        assert_eq!(
+            relation_set(res),
+            [vec![people.alice.into()], vec![people.carol.into()]].into()
+        );
+
+        let relation: Query = "[:find [?admin ?friend]
+                                :in $ [[?name ?admin]]
+                                :where [?e :user/username ?name]
+                                       [?e :user/admin? ?admin]
+                                       [?e :user/friends ?friend]]"
+            .parse()?;
+        let res = db.query(
+            &relation,
+            &[QueryInput::Relation(vec![
+                vec!["bob".into(), false.into()],
+                vec!["carol".into(), true.into()],
+            ])],
+        )?;
+        assert_eq!(
+            res,
+            QueryResult::Tuple(Some(vec![false.into(), people.carol.into()]))
+        );
+
+        let tuple: Query = "[:find ?e :in $ [?name ?admin]
+                             :where [?e :user/username ?name] [?e :user/admin? ?admin]]"
+            .parse()?;
+        let res = db.query(
+            &tuple,
+            &[QueryInput::Tuple(vec!["alice".into(), true.into()])],
+        )?;
+        assert_eq!(relation_set(res), [vec![people.alice.into()]].into());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn only_current_values() -> Result<()> {
+    with_connection(|conn| {
+        let people = transact_people(&conn)?;
+        let before = conn.db()?;
+
+        let mut tx = Transaction::new();
+        tx.add(people.alice.into(), "user/admin?".into(), false.into());
+        tx.retract_value(
+            people.alice.into(),
+            "user/friends".into(),
+            people.bob.into(),
+        );
+        conn.transact(tx)?;
+        let after = conn.db()?;
+
+        let admins: Query = "[:find ?e :where [?e :user/admin? true]]".parse()?;
+        assert_eq!(
+            relation_set(before.query(&admins, &[])?),
+            [vec![people.alice.into()]].into()
+        );
+        assert_eq!(relation_set(after.query(&admins, &[])?), HashSet::new());
+
+        let friends: Query = "[:find ?f :in $ ?e :where [?e :user/friends ?f]]".parse()?;
+        assert_eq!(
+            relation_set(after.query(&friends, &[Value::from(people.alice).into()])?),
+            [vec![people.carol.into()]].into()
+        );
+        Ok(())
+    })
+}
+
+#[test]
+fn indexed_values() -> Result<()> {
+    with_connection(|conn| {
+        let people = transact_people(&conn)?;
+        let mut tx = Transaction::new();
+        tx.add(people.alice.into(), "user/age".into(), 30.into());
+        tx.add(people.bob.into(), "user/age".into(), 30.into());
+        conn.transact(tx)?;
+        let mut tx = Transaction::new();
+        tx.add(people.bob.into(), "user/age".into(), 31.into());
+        conn.transact(tx)?;
+        let db = conn.db()?;
+
+        let thirty: Query = "[:find ?e :where [?e :user/age 30]]".parse()?;
+        assert_eq!(
+            relation_set(db.query(&thirty, &[])?),
+            [vec![people.alice.into()]].into()
+        );
+        Ok(())
+    })
+}
+
+#[test]
+fn invalid_queries() -> Result<()> {
+    with_connection(|conn| {
+        let db = conn.db()?;
+        let unbound: Query = "[:find ?x :where [?e :user/username ?name]]".parse()?;
+        assert!(matches!(
+            db.query(&unbound, &[]),
+            Err(QueryError::UnboundVariable(v)) if v == "?x"
+        ));
+        let missing_input: Query =
+            "[:find ?e :in $ ?name :where [?e :user/username ?name]]".parse()?;
+        assert!(matches!(
+            db.query(&missing_input, &[]),
+            Err(QueryError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            "[:find ?e :where [?e :user/username]]".parse::<Query>(),
+            Err(QueryError::InvalidQuery(_))
+        ));
+        Ok(())
+    })
+}
@@ -0,0 +1,172 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+#![cfg(feature = "segment")]
+
+mod common;
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{Seek, SeekFrom, Write},
+    path::PathBuf,
+};
+
+use common::storage::{read, TempDir};
+use datom::{
+    backends::SegmentStorage,
+    storage::{Item, Storage},
+};
+use miette::{IntoDiagnostic, Result};
+
+fn items(n: u16, size: usize) -> Vec<Item> {
+    (0..n)
+        .map(|i| [&[1][..], &i.to_be_bytes(), &vec![0; size]].concat())
+        .collect()
+}
+
+/// The items stored, not including the format marker
+fn stored(storage: &impl Storage) -> Result<Vec<Item>> {
+    read(storage, &[1][..]..&[2][..])
+}
+
+/// The segment files in a directory
+fn segment_files(dir: &TempDir) -> Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+    for entry in fs::read_dir(dir.path()).into_diagnostic()? {
+        let path = entry.into_diagnostic()?.path();
+        if path.extension().and_then(|e| e.to_str()) == Some("seg") {
+            paths.push(path);
+        }
+    }
+    Ok(paths)
+}
+
+#[test]
+fn recovery() -> Result<()> {
+    let dir = TempDir::new();
+    let path = dir.as_str();
+    let storage = SegmentStorage::connect(path)?;
+    storage.insert(&items(10, 30))?;
+    drop(storage);
+    let storage = SegmentStorage::connect(path)?;
+    assert_eq!(stored(&storage)?, items(10, 30));
+    drop(storage);
+
+    // A record which was only partly written is dropped
+    let mut log = OpenOptions::new()
+        .append(true)
+        .open(dir.join("wal.log"))
+        .into_diagnostic()?;
+    log.write_all(&[0, 0, 0, 40, 1, 2, 3, 4, 1])
+        .into_diagnostic()?;
+    drop(log);
+    let storage = SegmentStorage::connect(path)?;
+    assert_eq!(stored(&storage)?, items(10, 30));
+    storage.insert(&items(20, 30)[10..])?;
+    drop(storage);
+    let storage = SegmentStorage::connect(path)?;
+    assert_eq!(stored(&storage)?, items(20, 30));
+    Ok(())
+}
+
+#[test]
+fn segments() -> Result<()> {
+    let dir = TempDir::new();
+    let path = dir.as_str();
+    let storage = SegmentStorage::connect(path)?;
+    let all = items(1000, 30);
+    // Enough items for each segment to have several blocks
+    for chunk in all.chunks(300) {
+        storage.insert(chunk)?;
+        storage.flush()?;
+    }
+    storage.insert(&all[5..6])?;
+    assert_eq!(storage.segments(), 4);
+    assert_eq!(stored(&storage)?, all);
+    let r = all[250].as_slice()..all[700].as_slice();
+    let mut backwards = read(&storage, r.clone())?;
+    backwards.reverse();
+    assert_eq!(
+        storage
+            .range(r.clone())?
+            .rev()
+            .collect::<Result<Vec<_>, _>>()?,
+        backwards
+    );
+    assert_eq!(backwards.len(), 450);
+
+    // Reads which started before compaction aren't affected by it
+    let mut before = storage.range(r.clone())?;
+    before.next();
+    storage.compact()?;
+    assert_eq!(storage.segments(), 1);
+    assert_eq!(before.count(), 449);
+    assert_eq!(read(&storage, r)?.len(), 450);
+    drop(storage);
+    assert_eq!(segment_files(&dir)?.len(), 1);
+    let storage = SegmentStorage::connect(path)?;
+    assert_eq!(stored(&storage)?, all);
+    Ok(())
+}
+
+#[test]
+fn background_compaction() -> Result<()> {
+    let dir = TempDir::new();
+    let path = dir.as_str();
+    let storage = SegmentStorage::connect(path)?;
+    // Each insert fills the memtable, so it's flushed to a segment,
+    // and the fourth segment starts a compaction
+    let all = items(4 * 1100, 4 << 10);
+    for chunk in all.chunks(1100) {
+        storage.insert(chunk)?;
+    }
+    // Dropping the storage waits for the compaction to finish
+    drop(storage);
+    assert_eq!(segment_files(&dir)?.len(), 1);
+    let storage = SegmentStorage::connect(path)?;
+    assert_eq!(storage.segments(), 1);
+    assert_eq!(stored(&storage)?, all);
+    Ok(())
+}
+
+#[test]
+fn exclusive() -> Result<()> {
+    let dir = TempDir::new();
+    let storage = SegmentStorage::connect(dir.as_str())?;
+    assert!(SegmentStorage::connect(dir.as_str()).is_err());
+    drop(storage);
+    SegmentStorage::connect(dir.as_str())?;
+    Ok(())
+}
+
+#[test]
+fn corrupt_blocks() -> Result<()> {
+    let dir = TempDir::new();
+    let storage = SegmentStorage::connect(dir.as_str())?;
+    let all = items(1000, 30);
+    storage.insert(&all)?;
+    storage.flush()?;
+    drop(storage);
+
+    // Flip a byte in the segment's first block
+    let segment = segment_files(&dir)?.remove(0);
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(segment)
+        .into_diagnostic()?;
+    file.seek(SeekFrom::Start(10)).into_diagnostic()?;
+    file.write_all(&[0xFF]).into_diagnostic()?;
+    drop(file);
+
+    let storage = SegmentStorage::connect(dir.as_str())?;
+    assert!(storage
+        .range(all[0].as_slice()..all[1].as_slice())?
+        .any(|item| item.is_err()));
+    // Blocks which weren't touched can still be read
+    assert_eq!(
+        read(&storage, all[900].as_slice()..all[901].as_slice())?,
+        vec![all[900].clone()]
+    );
+    Ok(())
+}
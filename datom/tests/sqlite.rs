@@ -0,0 +1,51 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use common::storage::{read, TempDir};
+use datom::{
+    backends::SqliteStorage,
+    storage::{Item, Storage},
+};
+use miette::Result;
+
+#[test]
+fn batched_ranges() -> Result<()> {
+    let dir = TempDir::new();
+    let path = dir.join("items.sqlite3");
+    let items: Vec<Item> = (0..1000u16)
+        .map(|i| [&[1][..], &i.to_be_bytes()].concat())
+        .collect();
+    let storage = SqliteStorage::connect(&path)?;
+    storage.insert(&items)?;
+    drop(storage);
+    let storage = SqliteStorage::connect(&path)?;
+    let r = items[100].as_slice()..items[900].as_slice();
+    let read = read(&storage, r.clone())?;
+    assert_eq!(read, items[100..900]);
+    let mut rev: Vec<Item> = storage.range(r.clone())?.rev().collect::<Result<_, _>>()?;
+    rev.reverse();
+    assert_eq!(rev, read);
+
+    // Reading from both ends meets in the middle without repeating
+    // an item
+    let mut iter = storage.range(r)?;
+    let mut front = vec![];
+    let mut back = vec![];
+    loop {
+        match (iter.next(), iter.next_back()) {
+            (None, None) => break,
+            (f, b) => {
+                front.extend(f.transpose()?);
+                back.extend(b.transpose()?);
+            }
+        }
+    }
+    front.extend(back.into_iter().rev());
+    assert_eq!(front, read);
+    Ok(())
+}
@@ -0,0 +1,74 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+mod common;
+
+use std::thread;
+
+use common::schema::with_connection;
+use datom::{Transaction, TxReport, ID};
+use miette::Result;
+
+#[test]
+fn reports() -> Result<()> {
+    with_connection(|conn| {
+        let user = ID::new();
+        let subscription = conn.subscribe();
+        let t_before = conn.latest_t()?;
+        let reader = thread::spawn(move || subscription.take(3).collect::<Vec<TxReport>>());
+        let mut results = vec![];
+        for name in ["alice", "alicia", "ally"] {
+            let mut tx = Transaction::new();
+            tx.add(user.into(), "user/username".into(), name.into());
+            let res = conn.transact(tx)?;
+            results.push((res.before.t(), res.after.t(), res.data));
+        }
+        // Failed transactions aren't reported
+        let mut tx = Transaction::new();
+        tx.add(user.into(), "user/admin?".into(), "yes".into());
+        assert!(conn.transact(tx).is_err());
+
+        let reports = reader.join().expect("subscriber panicked");
+        assert_eq!(reports[0].before_t, t_before);
+        let reported: Vec<_> = reports
+            .into_iter()
+            .map(|report| (report.before_t, report.after_t, report.data))
+            .collect();
+        assert_eq!(reported, results);
+        Ok(())
+    })
+}
+
+#[test]
+fn slow_subscribers() -> Result<()> {
+    with_connection(|conn| {
+        let slow = conn.subscribe_with_capacity(2);
+        let fast = conn.subscribe_with_capacity(2);
+        let dropped = conn.subscribe_with_capacity(2);
+        drop(dropped);
+        let mut ts = vec![];
+        for n in 0..3 {
+            let mut tx = Transaction::new();
+            tx.add(ID::new().into(), "user/age".into(), n.into());
+            ts.push(conn.transact(tx)?.after.t());
+            if n == 0 {
+                assert_eq!(
+                    fast.try_iter().map(|r| r.after_t).collect::<Vec<_>>(),
+                    vec![ts[0]]
+                );
+            }
+        }
+        // The slow subscriber keeps what it buffered, then ends
+        assert!(slow.fell_behind());
+        let buffered: Vec<u64> = slow.map(|report| report.after_t).collect();
+        assert_eq!(buffered, ts[..2]);
+
+        assert!(!fast.fell_behind());
+        assert_eq!(
+            fast.try_iter().map(|r| r.after_t).collect::<Vec<_>>(),
+            ts[1..]
+        );
+        Ok(())
+    })
+}
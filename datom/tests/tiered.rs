@@ -0,0 +1,166 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+#![cfg(feature = "redblacktreeset")]
+
+mod common;
+
+use std::{
+    io,
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use common::storage::{items, read, CountingStorage};
+use datom::{
+    backends::{Promotion, RedBlackTreeSetStorage, TieredStorage},
+    storage::{Item, ItemIterator, Storage},
+    Connection, EntityResult, StorageError, Transaction, ID,
+};
+use miette::Result;
+
+/// Storage whose inserts fail while `failing` is set
+#[derive(Default)]
+struct FlakyStorage {
+    storage: RedBlackTreeSetStorage,
+    failing: AtomicBool,
+}
+
+impl FlakyStorage {
+    fn check(&self) -> Result<(), StorageError> {
+        if self.failing.load(Ordering::SeqCst) {
+            Err(io::Error::new(io::ErrorKind::Other, "insert failed").into())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Storage for FlakyStorage {
+    fn range(&self, r: Range<&[u8]>) -> Result<ItemIterator<'_>, StorageError> {
+        self.storage.range(r)
+    }
+
+    fn insert(&self, is: &[Item]) -> Result<(), StorageError> {
+        self.check()?;
+        self.storage.insert(is)
+    }
+
+    fn insert_if_empty(&self, guard: Range<&[u8]>, is: &[Item]) -> Result<(), StorageError> {
+        self.check()?;
+        self.storage.insert_if_empty(guard, is)
+    }
+
+    fn id(&self) -> ID {
+        self.storage.id()
+    }
+}
+
+#[test]
+fn promotion() -> Result<()> {
+    let a = Arc::new(RedBlackTreeSetStorage::new());
+    let b = RedBlackTreeSetStorage::new();
+    b.insert(&[vec![1], vec![2], vec![3], vec![4]])?;
+    a.insert(&[vec![2]])?;
+    let storage = TieredStorage::new(a.clone(), b);
+
+    // Only the items which were read are promoted
+    let mut read = storage.range(&[0][..]..&[255][..])?;
+    assert_eq!(read.next().transpose()?, Some(vec![1]));
+    assert_eq!(read.next_back().transpose()?, Some(vec![4]));
+    assert_eq!(items(&a)?, vec![vec![2]]);
+    drop(read);
+    assert_eq!(items(&a)?, vec![vec![1], vec![2], vec![4]]);
+
+    let a = Arc::new(RedBlackTreeSetStorage::new());
+    let b = RedBlackTreeSetStorage::new();
+    b.insert(&[vec![1], vec![2], vec![3]])?;
+    let limited = TieredStorage::new(a.clone(), b).promotion(Promotion::UpTo(2));
+    assert_eq!(items(&limited)?.len(), 3);
+    assert_eq!(items(&a)?, vec![vec![1], vec![2]]);
+
+    let a = Arc::new(RedBlackTreeSetStorage::new());
+    let b = RedBlackTreeSetStorage::new();
+    b.insert(&[vec![1]])?;
+    let never = TieredStorage::new(a.clone(), b).promotion(Promotion::Never);
+    assert_eq!(items(&never)?, vec![vec![1]]);
+    assert_eq!(items(&a)?, Vec::<Item>::new());
+    Ok(())
+}
+
+#[test]
+fn covered_ranges() -> Result<()> {
+    let b = Arc::new(CountingStorage::new(RedBlackTreeSetStorage::new()));
+    b.insert(&[vec![1], vec![2], vec![3]])?;
+    let storage = TieredStorage::new(RedBlackTreeSetStorage::new(), b.clone());
+
+    // Once a range has been read to the end, reads within it skip B
+    assert_eq!(items(&storage)?.len(), 3);
+    assert_eq!(b.reads(), 1);
+    assert_eq!(items(&storage)?.len(), 3);
+    assert_eq!(read(&storage, &[2][..]..&[3][..])?, vec![vec![2]]);
+    assert_eq!(b.reads(), 1);
+    storage.insert(&[vec![2, 1]])?;
+    assert_eq!(
+        read(&storage, &[2][..]..&[3][..])?,
+        vec![vec![2], vec![2, 1]]
+    );
+    assert_eq!(b.reads(), 1);
+    read(&storage, &[255][..]..&[255, 1][..])?;
+    assert_eq!(b.reads(), 2);
+
+    // A conflict shows that B was written by other means
+    b.insert(&[vec![2, 2]])?;
+    assert!(storage
+        .insert_if_empty(&[2][..]..&[3][..], &[vec![2, 3]])
+        .is_err());
+    assert_eq!(read(&storage, &[2][..]..&[3][..])?.len(), 3);
+    assert_eq!(b.reads(), 3);
+
+    // Partial reads don't cover their range
+    let b = Arc::new(CountingStorage::new(RedBlackTreeSetStorage::new()));
+    b.insert(&[vec![1], vec![2], vec![3]])?;
+    let storage = TieredStorage::new(RedBlackTreeSetStorage::new(), b.clone());
+    storage.range(&[0][..]..&[255][..])?.next().transpose()?;
+    items(&storage)?;
+    assert_eq!(b.reads(), 2);
+
+    // Neither do reads which didn't promote every item
+    let b = Arc::new(CountingStorage::new(RedBlackTreeSetStorage::new()));
+    b.insert(&[vec![1], vec![2], vec![3]])?;
+    let limited =
+        TieredStorage::new(RedBlackTreeSetStorage::new(), b.clone()).promotion(Promotion::UpTo(2));
+    items(&limited)?;
+    assert_eq!(items(&limited)?.len(), 3);
+    assert_eq!(b.reads(), 2);
+    Ok(())
+}
+
+#[test]
+fn failing_cache() -> Result<()> {
+    let a = Arc::new(FlakyStorage::default());
+    let conn = Connection::new(TieredStorage::new(a.clone(), RedBlackTreeSetStorage::new()));
+    let entity = ID::new();
+    let mut tx = Transaction::new();
+    tx.add(entity.into(), "db/doc".into(), "first".into());
+    conn.transact(tx)?;
+    assert_eq!(
+        conn.db()?.entity(entity.into())?.get("db/doc".into())?,
+        EntityResult::Value("first".into())
+    );
+
+    // Once B has the transaction, it's committed even if A misses it
+    a.failing.store(true, Ordering::SeqCst);
+    let mut tx = Transaction::new();
+    tx.add(entity.into(), "db/doc".into(), "second".into());
+    conn.transact(tx)?;
+    assert_eq!(
+        conn.db()?.entity(entity.into())?.get("db/doc".into())?,
+        EntityResult::Value("second".into())
+    );
+    Ok(())
+}
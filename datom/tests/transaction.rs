@@ -0,0 +1,342 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+mod common;
+
+use common::schema::with_connection;
+use datom::{
+    builtin_idents, AttributeType, DatomType, EntityResult, Query, QueryError, QueryResult, TempID,
+    Transaction, TransactionError, TransactionRecord, Value, EID, ID,
+};
+use miette::Result;
+
+#[test]
+fn tempids() -> Result<()> {
+    with_connection(|conn| {
+        let mut tx = Transaction::new();
+        tx.add(EID::temp("alice"), "user/username".into(), "alice".into());
+        tx.add(EID::temp(1u64), "user/username".into(), "bob".into());
+        tx.add_ref(EID::temp("alice"), "user/friends".into(), EID::temp(1u64));
+        let res = conn.transact(tx)?;
+        assert_eq!(res.tempids.len(), 2);
+        let alice = res.tempids[&TempID::from("alice")];
+        let bob = res.tempids[&TempID::Numbered(1)];
+        assert_ne!(alice, bob);
+
+        let db = conn.db()?;
+        let alice_entity = db.entity(alice.into())?;
+        assert_eq!(
+            alice_entity.get("user/username".into())?,
+            EntityResult::Value("alice".into())
+        );
+        assert_eq!(
+            alice_entity.get("user/friends".into())?,
+            EntityResult::Repeated(vec![EntityResult::Ref(db.entity(bob.into())?)])
+        );
+        assert_eq!(
+            db.entity(bob.into())?.get("user/username".into())?,
+            EntityResult::Value("bob".into())
+        );
+
+        // Temporary IDs are scoped to a single transaction
+        let mut tx = Transaction::new();
+        tx.add(EID::temp("alice"), "user/username".into(), "carol".into());
+        let res = conn.transact(tx)?;
+        assert_ne!(res.tempids[&TempID::from("alice")], alice);
+        assert!(matches!(
+            db.entity(EID::temp("alice")),
+            Err(QueryError::UnresolvedEID(EID::Temp(_)))
+        ));
+        Ok(())
+    })
+}
+
+#[test]
+fn value_types() -> Result<()> {
+    with_connection(|conn| {
+        let user = ID::new();
+        let mut tx = Transaction::new();
+        tx.add(user.into(), "user/username".into(), "alice".into());
+        tx.add(user.into(), "user/admin?".into(), "yes".into());
+        let Err(TransactionError::InvalidValueType(entity, _, expected, value)) = conn.transact(tx)
+        else {
+            panic!("a string was stored in a boolean attribute");
+        };
+        assert_eq!(entity, user);
+        assert_eq!(expected, AttributeType::Boolean);
+        assert_eq!(value, Value::from("yes"));
+        // Nothing from the failed transaction was written
+        assert_eq!(
+            conn.db()?
+                .entity(user.into())?
+                .get("user/username".into())?,
+            EntityResult::NotFound
+        );
+
+        let mut tx = Transaction::new();
+        tx.add(user.into(), "user/friends".into(), "bob".into());
+        assert!(matches!(
+            conn.transact(tx),
+            Err(TransactionError::InvalidValueType(
+                _,
+                _,
+                AttributeType::Ref,
+                _
+            ))
+        ));
+        Ok(())
+    })
+}
+
+#[test]
+fn unique_values() -> Result<()> {
+    with_connection(|conn| {
+        let (alice, bob, customer) = (ID::new(), ID::new(), ID::new());
+        let mut tx = Transaction::new();
+        tx.add(alice.into(), "user/stripe-customer".into(), customer.into());
+        conn.transact(tx)?;
+
+        let mut tx = Transaction::new();
+        tx.add(bob.into(), "user/stripe-customer".into(), customer.into());
+        let Err(TransactionError::UniqueConflict(entity, _, value, holder)) = conn.transact(tx)
+        else {
+            panic!("two entities were given the same unique value");
+        };
+        assert_eq!((entity, value, holder), (bob, customer.into(), alice));
+
+        // Two new entities can't share a value either
+        let mut tx = Transaction::new();
+        tx.add(bob.into(), "user/stripe-customer".into(), bob.into());
+        tx.add(alice.into(), "user/stripe-customer".into(), bob.into());
+        assert!(matches!(
+            conn.transact(tx),
+            Err(TransactionError::UniqueConflict(..))
+        ));
+
+        // Once the value is retracted, it can move to another entity
+        let mut tx = Transaction::new();
+        tx.retract_value(alice.into(), "user/stripe-customer".into(), customer.into());
+        tx.add(bob.into(), "user/stripe-customer".into(), customer.into());
+        conn.transact(tx)?;
+        let db = conn.db()?;
+        let owner = EID::unique("user/stripe-customer".into(), customer.into());
+        assert_eq!(owner.resolve(&db)?, bob);
+        Ok(())
+    })
+}
+
+#[test]
+fn unique_identities() -> Result<()> {
+    with_connection(|conn| {
+        let mut tx = Transaction::new();
+        tx.add(EID::temp("alice"), "user/email".into(), "a@lutris".into());
+        let alice = conn.transact(tx)?.tempids[&TempID::from("alice")];
+
+        // Asserting an existing identity upserts into its entity
+        let mut tx = Transaction::new();
+        tx.add(EID::temp("user"), "user/email".into(), "a@lutris".into());
+        tx.add(EID::temp("user"), "user/admin?".into(), true.into());
+        let res = conn.transact(tx)?;
+        assert_eq!(res.tempids[&TempID::from("user")], alice);
+        let entity = conn.db()?.entity(alice.into())?;
+        assert_eq!(
+            entity.get("user/admin?".into())?,
+            EntityResult::Value(true.into())
+        );
+
+        // Temporary IDs asserting the same new identity are one entity
+        let mut tx = Transaction::new();
+        tx.add(EID::temp(1u64), "user/email".into(), "b@lutris".into());
+        tx.add(EID::temp(2u64), "user/email".into(), "b@lutris".into());
+        let res = conn.transact(tx)?;
+        assert_eq!(
+            res.tempids[&TempID::Numbered(1)],
+            res.tempids[&TempID::Numbered(2)]
+        );
+        assert_ne!(res.tempids[&TempID::Numbered(1)], alice);
+
+        // Resolved entities don't upsert
+        let mut tx = Transaction::new();
+        tx.add(ID::new().into(), "user/email".into(), "a@lutris".into());
+        assert!(matches!(
+            conn.transact(tx),
+            Err(TransactionError::UniqueConflict(..))
+        ));
+        Ok(())
+    })
+}
+
+#[test]
+fn implicit_retraction() -> Result<()> {
+    with_connection(|conn| {
+        let (alice, bob) = (ID::new(), ID::new());
+        let mut tx = Transaction::new();
+        tx.add(alice.into(), "user/username".into(), "alice".into());
+        conn.transact(tx)?;
+
+        let mut tx = Transaction::new();
+        tx.add(alice.into(), "user/username".into(), "alicia".into());
+        let res = conn.transact(tx)?;
+        let mut data: Vec<(Value, DatomType)> = res
+            .data
+            .into_iter()
+            .filter(|datom| datom.entity == alice)
+            .map(|datom| (datom.value, datom.datom_type))
+            .collect();
+        data.sort_by_key(|(_, datom_type)| *datom_type == DatomType::Addition);
+        assert_eq!(
+            data,
+            vec![
+                ("alice".into(), DatomType::Retraction),
+                ("alicia".into(), DatomType::Addition),
+            ]
+        );
+        let db = conn.db()?;
+        let entity = db.entity(alice.into())?;
+        assert_eq!(
+            entity.get("user/username".into())?,
+            EntityResult::Value("alicia".into())
+        );
+        assert_eq!(entity.attributes()?.count(), 1);
+        let username = EID::from("user/username").resolve(&db)?;
+        assert!(db
+            .datoms_for_attribute_value(username, "alice".into())?
+            .collect::<Result<Vec<_>, _>>()?
+            .iter()
+            .any(|datom| datom.datom_type == DatomType::Retraction));
+
+        // The old value is free for another entity to take
+        let mut tx = Transaction::new();
+        tx.add(bob.into(), "user/username".into(), "alice".into());
+        conn.transact(tx)?;
+
+        // Re-asserting the current value writes nothing but the
+        // transaction entity
+        let mut tx = Transaction::new();
+        tx.add(bob.into(), "user/username".into(), "alice".into());
+        let res = conn.transact(tx)?;
+        assert!(res.data.iter().all(|datom| datom.entity != bob));
+
+        let mut tx = Transaction::new();
+        tx.add(bob.into(), "user/username".into(), "bob".into());
+        tx.add(bob.into(), "user/username".into(), "robert".into());
+        assert!(matches!(
+            conn.transact(tx),
+            Err(TransactionError::ConflictingValues(entity, _)) if entity == bob
+        ));
+        Ok(())
+    })
+}
+
+#[test]
+fn retract_entity() -> Result<()> {
+    with_connection(|conn| {
+        let (alice, bob, customer) = (ID::new(), ID::new(), ID::new());
+        let mut tx = Transaction::new();
+        tx.add(alice.into(), "user/username".into(), "alice".into());
+        tx.add(bob.into(), "user/username".into(), "bob".into());
+        tx.add(alice.into(), "user/friends".into(), bob.into());
+        tx.add(bob.into(), "user/friends".into(), alice.into());
+        tx.add(alice.into(), "user/stripe-customer".into(), customer.into());
+        tx.add(customer.into(), "db/doc".into(), "cus_1234".into());
+        conn.transact(tx)?;
+
+        let mut tx = Transaction::new();
+        tx.retract_entity(alice.into());
+        conn.transact(tx)?;
+        let db = conn.db()?;
+        assert_eq!(db.entity(alice.into())?.attributes()?.count(), 0);
+        // Components are retracted along with their owner
+        assert_eq!(db.entity(customer.into())?.attributes()?.count(), 0);
+        // References to the entity are retracted
+        let bob_entity = db.entity(bob.into())?;
+        assert_eq!(
+            bob_entity.get("user/friends".into())?,
+            EntityResult::Repeated(vec![])
+        );
+        assert_eq!(
+            bob_entity.get("user/username".into())?,
+            EntityResult::Value("bob".into())
+        );
+        assert!(matches!(
+            db.entity(EID::unique("user/username".into(), "alice".into())),
+            Err(QueryError::UnresolvedEID(_))
+        ));
+        Ok(())
+    })
+}
+
+#[test]
+fn compare_and_swap() -> Result<()> {
+    with_connection(|conn| {
+        let user = ID::new();
+        let mut tx = Transaction::new();
+        tx.cas(user.into(), "user/admin?".into(), None, false.into());
+        conn.transact(tx)?;
+
+        // Two writers racing from the same state: only the first wins
+        let mut first = Transaction::new();
+        first.cas(
+            user.into(),
+            "user/admin?".into(),
+            Some(false.into()),
+            true.into(),
+        );
+        let second = first.clone();
+        conn.transact(first)?;
+        let Err(TransactionError::CasFailed(_, _, expected, actual)) = conn.transact(second) else {
+            panic!("a stale compare-and-swap succeeded");
+        };
+        assert_eq!(expected, Some(false.into()));
+        assert_eq!(actual, Some(true.into()));
+        assert_eq!(
+            conn.db()?.entity(user.into())?.get("user/admin?".into())?,
+            EntityResult::Value(true.into())
+        );
+
+        let mut tx = Transaction::new();
+        tx.cas(user.into(), "user/admin?".into(), None, false.into());
+        assert!(matches!(
+            conn.transact(tx),
+            Err(TransactionError::CasFailed(_, _, None, Some(_)))
+        ));
+        Ok(())
+    })
+}
+
+#[test]
+fn transaction_entities() -> Result<()> {
+    with_connection(|conn| {
+        let user = ID::new();
+        let mut tx = Transaction::new();
+        tx.add(user.into(), "user/username".into(), "alice".into());
+        tx.add(EID::Tx, "db/doc".into(), "signed up".into());
+        tx.add_ref(EID::Tx, "user/friends".into(), user.into());
+        let res = conn.transact(tx)?;
+        let t = res.after.t();
+        let db = conn.db()?;
+        let tx_entity = db.entity(TransactionRecord::id_for_t(t).into())?;
+        assert_eq!(
+            tx_entity.get("db/doc".into())?,
+            EntityResult::Value("signed up".into())
+        );
+        assert!(matches!(
+            tx_entity.get(builtin_idents::TX_INSTANT.into())?,
+            EntityResult::Value(Value::Integer(_))
+        ));
+
+        let query: Query = "[:find ?doc . :in $ ?e
+                             :where [?tx :user/friends ?e] [?tx :db/doc ?doc]]"
+            .parse()?;
+        assert_eq!(
+            db.query(&query, &[Value::from(user).into()])?,
+            QueryResult::Scalar(Some("signed up".into()))
+        );
+        assert!(matches!(
+            db.entity(EID::Tx),
+            Err(QueryError::UnresolvedEID(EID::Tx))
+        ));
+        Ok(())
+    })
+}
@@ -0,0 +1,322 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+mod common;
+
+use std::{collections::HashSet, thread::sleep, time::Duration};
+
+use common::schema::with_connection;
+use datom::{
+    ConnectionError, DatomType, EntityResult, Index, Query, QueryResult, TempID, Transaction,
+    TransactionError, TransactionRecord, Value, EID, ID,
+};
+use miette::Result;
+
+fn relation_set(res: QueryResult) -> HashSet<Vec<Value>> {
+    if let QueryResult::Relation(rows) = res {
+        rows.into_iter().collect()
+    } else {
+        panic!("expected a relation, got {:?}", res);
+    }
+}
+
+#[test]
+fn history() -> Result<()> {
+    with_connection(|conn| {
+        let user = ID::new();
+        let mut ts = vec![];
+        for name in ["alice", "alicia", "ally"] {
+            let mut tx = Transaction::new();
+            tx.add(user.into(), "user/username".into(), name.into());
+            ts.push(conn.transact(tx)?.after.t());
+        }
+        let db = conn.db()?;
+        let history = db.history();
+        assert!(history.is_history() && !db.is_history());
+
+        let query: Query = "[:find ?name ?tx ?added
+                             :in $ ?e
+                             :where [?e :user/username ?name ?tx ?added]]"
+            .parse()?;
+        let tx = |i: usize| Value::from(TransactionRecord::id_for_t(ts[i]));
+        assert_eq!(
+            relation_set(history.query(&query, &[Value::from(user).into()])?),
+            [
+                vec!["alice".into(), tx(0), true.into()],
+                vec!["alice".into(), tx(1), false.into()],
+                vec!["alicia".into(), tx(1), true.into()],
+                vec!["alicia".into(), tx(2), false.into()],
+                vec!["ally".into(), tx(2), true.into()],
+            ]
+            .into()
+        );
+        // The current database only sees the current value
+        assert_eq!(
+            relation_set(db.query(&query, &[Value::from(user).into()])?),
+            [vec!["ally".into(), tx(2), true.into()]].into()
+        );
+
+        // Constant terms filter by transaction and operation, and the
+        // transaction entity can be joined against
+        let query: Query = "[:find ?name ?instant
+                             :in $ ?e
+                             :where [?e :user/username ?name ?tx false]
+                                    [?tx :db/tx-instant ?instant]]"
+            .parse()?;
+        let retracted: Vec<Value> =
+            relation_set(history.query(&query, &[Value::from(user).into()])?)
+                .into_iter()
+                .map(|row| row[0].clone())
+                .collect();
+        assert_eq!(retracted.len(), 2);
+        assert!(retracted.contains(&"alice".into()) && retracted.contains(&"alicia".into()));
+
+        // Entities and idents still resolve against the current state
+        assert_eq!(
+            history.entity(user.into())?.get("user/username".into())?,
+            EntityResult::Value("ally".into())
+        );
+        let username = EID::from("user/username").resolve(&history)?;
+        let types: Vec<DatomType> = history
+            .datoms_for_entity_attribute(user, username)?
+            .map(|datom| datom.map(|datom| datom.datom_type))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(types.len(), 5);
+        Ok(())
+    })
+}
+
+#[test]
+fn since() -> Result<()> {
+    with_connection(|conn| {
+        let (alice, bob) = (ID::new(), ID::new());
+        let mut tx = Transaction::new();
+        tx.add(alice.into(), "user/username".into(), "alice".into());
+        tx.add(alice.into(), "user/admin?".into(), false.into());
+        let t = conn.transact(tx)?.after.t();
+        let mut tx = Transaction::new();
+        tx.add(bob.into(), "user/username".into(), "bob".into());
+        tx.add(alice.into(), "user/admin?".into(), true.into());
+        conn.transact(tx)?;
+
+        let db = conn.db()?;
+        let since = db.since(t);
+        assert_eq!(since.since_t(), Some(t));
+        assert_eq!(db.since_t(), None);
+
+        // Idents resolve against the whole database, but only the
+        // later transaction's data is matched
+        let query: Query = "[:find ?e ?a ?v :where [?e ?a ?v] [?e :user/username _]]".parse()?;
+        let username = EID::from("user/username").resolve(&db)?;
+        let admin = EID::from("user/admin?").resolve(&db)?;
+        let rows = relation_set(since.query(&query, &[])?);
+        assert!(rows.contains(&vec![bob.into(), username.into(), "bob".into()]));
+        assert!(!rows.iter().any(|row| row[2] == "alice".into()));
+        // Alice isn't matched, since her username is from before t
+        assert!(!rows.contains(&vec![alice.into(), admin.into(), true.into()]));
+
+        let query: Query = "[:find ?v . :in $ ?e :where [?e :user/admin? ?v]]".parse()?;
+        assert_eq!(
+            since.query(&query, &[Value::from(alice).into()])?,
+            QueryResult::Scalar(Some(true.into()))
+        );
+        // The retraction of the replaced value is in the later
+        // transaction, but its assertion isn't
+        let types: Vec<DatomType> = since
+            .datoms_for_entity_attribute(alice, admin)?
+            .map(|datom| datom.map(|datom| datom.datom_type))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(types.len(), 2);
+        assert_eq!(since.history().datoms_for_entity(alice)?.count(), 2);
+
+        // Nothing is newer than the latest transaction
+        assert_eq!(db.since(db.t()).datoms_for_entity(bob)?.count(), 0);
+        assert_eq!(
+            since.since(0).since_t(),
+            Some(t),
+            "a since database can only be narrowed"
+        );
+        Ok(())
+    })
+}
+
+#[test]
+fn with() -> Result<()> {
+    with_connection(|conn| {
+        let alice = ID::new();
+        let mut tx = Transaction::new();
+        tx.add(alice.into(), "user/username".into(), "alice".into());
+        conn.transact(tx)?;
+        let db = conn.db()?;
+
+        let mut tx = Transaction::new();
+        tx.add(EID::temp("bob"), "user/username".into(), "bob".into());
+        tx.add_ref(alice.into(), "user/friends".into(), EID::temp("bob"));
+        let first = db.with(tx)?;
+        let bob = first.tempids[&TempID::from("bob")];
+        assert_eq!(first.after.t(), db.t() + 1);
+        assert_eq!(
+            first
+                .after
+                .entity(alice.into())?
+                .get("user/friends".into())?,
+            EntityResult::Repeated(vec![EntityResult::Ref(first.after.entity(bob.into())?)])
+        );
+
+        // Speculative transactions can be chained, and see each other's
+        // data when they're validated
+        let mut tx = Transaction::new();
+        tx.add(ID::new().into(), "user/username".into(), "bob".into());
+        assert!(matches!(
+            first.after.with(tx),
+            Err(TransactionError::UniqueConflict(..))
+        ));
+        let mut tx = Transaction::new();
+        tx.add(bob.into(), "user/username".into(), "robert".into());
+        let second = first.after.with(tx)?;
+        let query: Query = "[:find ?name :where [_ :user/username ?name]]".parse()?;
+        assert_eq!(
+            relation_set(second.after.query(&query, &[])?),
+            [vec!["alice".into()], vec!["robert".into()]].into()
+        );
+        assert_eq!(
+            relation_set(second.after.history().query(&query, &[])?).len(),
+            3
+        );
+
+        // Nothing was written, and later commits don't leak into the
+        // speculative databases
+        let mut tx = Transaction::new();
+        tx.add(ID::new().into(), "user/username".into(), "carol".into());
+        conn.transact(tx)?;
+        assert_eq!(conn.db()?.t(), db.t() + 1);
+        assert_eq!(
+            relation_set(conn.db()?.query(&query, &[])?),
+            [vec!["alice".into()], vec!["carol".into()]].into()
+        );
+        assert_eq!(
+            relation_set(second.after.query(&query, &[])?),
+            [vec!["alice".into()], vec!["robert".into()]].into()
+        );
+        Ok(())
+    })
+}
+
+#[test]
+fn as_of_instant() -> Result<()> {
+    with_connection(|conn| {
+        let user = ID::new();
+        let mut ts = vec![];
+        for name in ["alice", "alicia", "ally"] {
+            // Transaction timestamps have millisecond precision
+            sleep(Duration::from_millis(2));
+            let mut tx = Transaction::new();
+            tx.add(user.into(), "user/username".into(), name.into());
+            ts.push(conn.transact(tx)?.after.t());
+        }
+        let instants = ts
+            .iter()
+            .map(|t| conn.instant_for_t(*t)?.ok_or(ConnectionError::InvalidData))
+            .collect::<Result<Vec<_>, _>>()?;
+        assert!(instants.windows(2).all(|pair| pair[0] < pair[1]));
+        for (t, instant) in ts.iter().zip(instants.iter()) {
+            assert_eq!(conn.t_for_instant(*instant)?, *t);
+        }
+
+        let between = instants[1] + chrono::Duration::microseconds(500);
+        assert_eq!(conn.t_for_instant(between)?, ts[1]);
+        assert_eq!(
+            conn.as_of_instant(between)?
+                .entity(user.into())?
+                .get("user/username".into())?,
+            EntityResult::Value("alicia".into())
+        );
+        assert_eq!(conn.as_of_instant(between)?.t(), ts[1]);
+
+        let first = conn.instant_for_t(1)?.ok_or(ConnectionError::InvalidData)?;
+        assert_eq!(
+            conn.t_for_instant(first - chrono::Duration::milliseconds(1))?,
+            0
+        );
+        assert_eq!(conn.instant_for_t(conn.latest_t()? + 1)?, None);
+        Ok(())
+    })
+}
+
+#[test]
+fn filter() -> Result<()> {
+    with_connection(|conn| {
+        let (alice, bob, carol) = (ID::new(), ID::new(), ID::new());
+        let mut tx = Transaction::new();
+        tx.add(alice.into(), "user/username".into(), "alice".into());
+        tx.add(bob.into(), "user/username".into(), "bob".into());
+        tx.add(carol.into(), "user/username".into(), "carol".into());
+        tx.add(alice.into(), "user/friends".into(), bob.into());
+        tx.add(carol.into(), "user/friends".into(), bob.into());
+        // Carol is soft-deleted
+        tx.add(carol.into(), "user/admin?".into(), false.into());
+        conn.transact(tx)?;
+        let db = conn.db()?;
+        let admin = EID::from("user/admin?").resolve(&db)?;
+
+        // The predicate sees the unfiltered database
+        let active = db.filter(move |db, datom| {
+            db.entity(datom.entity.into())
+                .and_then(|entity| entity.get(admin.into()))
+                .map_or(true, |res| res != EntityResult::Value(false.into()))
+        });
+        assert!(active.is_filtered() && !db.is_filtered());
+
+        let query: Query = "[:find ?name :where [?e :user/username ?name]]".parse()?;
+        assert_eq!(
+            relation_set(active.query(&query, &[])?),
+            HashSet::from([vec!["alice".into()], vec!["bob".into()]])
+        );
+        assert_eq!(relation_set(db.query(&query, &[])?).len(), 3);
+
+        assert_eq!(
+            active.entity(carol.into())?.get("user/username".into())?,
+            EntityResult::NotFound
+        );
+        assert_eq!(active.entity(carol.into())?.attributes()?.count(), 0);
+        let EntityResult::Repeated(friends_of_bob) = active
+            .entity(bob.into())?
+            .reverse_get("user/friends".into())?
+        else {
+            panic!("reverse lookups are repeated");
+        };
+        let friends_of_bob: Vec<ID> = friends_of_bob
+            .iter()
+            .map(|friend| match friend {
+                EntityResult::Ref(entity) => *entity.id(),
+                _ => panic!("reverse lookups are refs"),
+            })
+            .collect();
+        assert_eq!(friends_of_bob, vec![alice]);
+        assert!(active
+            .datoms(Index::EAVT)?
+            .collect::<Result<Vec<_>, _>>()?
+            .iter()
+            .all(|datom| datom.entity != carol));
+
+        // Filters compose
+        let only_alice = active.filter(move |_, datom| datom.entity != bob);
+        assert_eq!(
+            relation_set(only_alice.query(&query, &[])?),
+            HashSet::from([vec!["alice".into()]])
+        );
+        // Refs reached through a filtered entity are filtered too
+        let EntityResult::Repeated(friends) = only_alice
+            .entity(alice.into())?
+            .get("user/friends".into())?
+        else {
+            panic!("user/friends is repeated");
+        };
+        let [EntityResult::Ref(friend)] = &friends[..] else {
+            panic!("alice has one friend");
+        };
+        assert_eq!(friend.get("user/username".into())?, EntityResult::NotFound);
+        Ok(())
+    })
+}
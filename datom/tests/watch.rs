@@ -0,0 +1,89 @@
+// SPDX-FileCopyrightText: 2022 Lutris, Inc
+// SPDX-License-Identifier: BlueOak-1.0.0 OR BSD-2-Clause-Patent
+// SPDX-FileContributor: Piper McCorkle <piper@lutris.engineering>
+
+mod common;
+
+use std::{ops::Bound, thread};
+
+use common::schema::with_connection;
+use datom::{Query, QueryResult, Transaction, Value, Watch, WatchResult, ID};
+use miette::Result;
+
+#[test]
+fn watch_query() -> Result<()> {
+    with_connection(|conn| {
+        let query: Query =
+            "[:find ?name :where [?e :user/admin? true] [?e :user/username ?name]]".parse()?;
+        let mut admins = conn.watch(Watch::Query(query, vec![]))?;
+        assert_eq!(
+            admins.next().transpose()?,
+            Some(WatchResult::Query(QueryResult::Relation(vec![])))
+        );
+
+        // Transactions which don't touch the query's attributes don't
+        // re-run it
+        let mut tx = Transaction::new();
+        tx.add(ID::new().into(), "user/age".into(), 30.into());
+        conn.transact(tx)?;
+        assert!(admins.try_next().is_none());
+
+        let alice = ID::new();
+        let mut tx = Transaction::new();
+        tx.add(alice.into(), "user/username".into(), "alice".into());
+        tx.add(alice.into(), "user/admin?".into(), true.into());
+        let t = conn.transact(tx)?.after.t();
+        assert_eq!(
+            admins.try_next().transpose()?,
+            Some(WatchResult::Query(QueryResult::Relation(vec![vec![
+                "alice".into()
+            ]])))
+        );
+        assert_eq!(admins.t(), t);
+        assert!(admins.try_next().is_none());
+
+        // Blocking until the next relevant transaction
+        let next = thread::scope(|s| {
+            let reader = s.spawn(move || admins.next());
+            let mut tx = Transaction::new();
+            tx.add(alice.into(), "user/admin?".into(), false.into());
+            conn.transact(tx)?;
+            Ok::<_, miette::Report>(reader.join().expect("watcher panicked").transpose()?)
+        })?;
+        assert_eq!(
+            next,
+            Some(WatchResult::Query(QueryResult::Relation(vec![])))
+        );
+        Ok(())
+    })
+}
+
+#[test]
+fn watch_index_range() -> Result<()> {
+    with_connection(|conn| {
+        let mut adults = conn.watch(Watch::IndexRange(
+            "user/age".into(),
+            Bound::Included(18.into()),
+            Bound::Unbounded,
+        ))?;
+        let ages = |res: Option<WatchResult>| -> Vec<Value> {
+            let Some(WatchResult::Datoms(datoms)) = res else {
+                panic!("expected datoms, got {:?}", res);
+            };
+            datoms.into_iter().map(|datom| datom.value).collect()
+        };
+        assert_eq!(ages(adults.next().transpose()?), vec![]);
+
+        let mut tx = Transaction::new();
+        tx.add(ID::new().into(), "user/username".into(), "alice".into());
+        conn.transact(tx)?;
+        assert!(adults.try_next().is_none());
+
+        let mut tx = Transaction::new();
+        tx.add(ID::new().into(), "user/age".into(), 30.into());
+        tx.add(ID::new().into(), "user/age".into(), 12.into());
+        conn.transact(tx)?;
+        assert_eq!(ages(adults.try_next().transpose()?), vec![30.into()]);
+        Ok(())
+    })
+}